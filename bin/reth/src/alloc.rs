@@ -0,0 +1,152 @@
+//! Information about the global allocator.
+
+use std::{fmt, time::Duration};
+use tracing::{info, warn};
+
+/// The name of the global allocator.
+#[cfg(feature = "jemalloc")]
+pub const ALLOCATOR: &str = "jemalloc";
+
+/// The name of the global allocator.
+#[cfg(not(feature = "jemalloc"))]
+pub const ALLOCATOR: &str = "system";
+
+/// The interval at which the allocator stats are logged with `--alloc-stats`.
+pub const ALLOC_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Logs which global allocator is active.
+pub fn log_allocator() {
+    info!(target: "reth::cli", allocator = ALLOCATOR, "Using global allocator");
+}
+
+/// Logs the [AllocStats] at the given interval.
+///
+/// Returns immediately if the stats are not available for the global allocator.
+pub async fn log_alloc_stats(interval: Duration) {
+    if AllocStats::read().is_none() {
+        warn!(target: "reth::cli", allocator = ALLOCATOR, "Allocator stats are not available");
+        return
+    }
+
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Some(stats) = AllocStats::read() {
+            info!(target: "reth::cli", %stats, "Allocator stats");
+        }
+    }
+}
+
+/// A snapshot of the jemalloc stats, in bytes.
+///
+/// See the [jemalloc docs](https://jemalloc.net/jemalloc.3.html) for the meaning of the counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Bytes allocated by the application (`stats.allocated`).
+    pub allocated: usize,
+    /// Bytes in active pages allocated by the application (`stats.active`).
+    pub active: usize,
+    /// Bytes dedicated to jemalloc metadata (`stats.metadata`).
+    pub metadata: usize,
+    /// Bytes in physically resident data pages mapped by the allocator (`stats.resident`).
+    pub resident: usize,
+    /// Bytes in active extents mapped by the allocator (`stats.mapped`).
+    pub mapped: usize,
+    /// Bytes in virtual memory mappings that were retained (`stats.retained`).
+    pub retained: usize,
+}
+
+impl AllocStats {
+    /// Creates the stats from jemalloc's raw `stats.*` counters.
+    ///
+    /// Unknown counters are ignored and missing counters are zero.
+    pub fn from_counters<'a>(counters: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let mut stats = Self::default();
+        for (name, value) in counters {
+            match name {
+                "stats.allocated" => stats.allocated = value,
+                "stats.active" => stats.active = value,
+                "stats.metadata" => stats.metadata = value,
+                "stats.resident" => stats.resident = value,
+                "stats.mapped" => stats.mapped = value,
+                "stats.retained" => stats.retained = value,
+                _ => {}
+            }
+        }
+        stats
+    }
+
+    /// Reads the current stats from jemalloc.
+    ///
+    /// Returns `None` if the stats could not be read.
+    #[cfg(feature = "jemalloc")]
+    pub fn read() -> Option<Self> {
+        use jemalloc_ctl::{epoch, stats};
+
+        // the stats are cached and only refreshed when the epoch is advanced
+        epoch::advance().ok()?;
+
+        Some(Self::from_counters([
+            ("stats.allocated", stats::allocated::read().ok()?),
+            ("stats.active", stats::active::read().ok()?),
+            ("stats.metadata", stats::metadata::read().ok()?),
+            ("stats.resident", stats::resident::read().ok()?),
+            ("stats.mapped", stats::mapped::read().ok()?),
+            ("stats.retained", stats::retained::read().ok()?),
+        ]))
+    }
+
+    /// Reads the current stats from jemalloc.
+    ///
+    /// Always returns `None`, because jemalloc is not the global allocator.
+    #[cfg(not(feature = "jemalloc"))]
+    pub fn read() -> Option<Self> {
+        None
+    }
+}
+
+impl fmt::Display for AllocStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "allocated={} active={} metadata={} resident={} mapped={} retained={}",
+            self.allocated, self.active, self.metadata, self.resident, self.mapped, self.retained
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alloc_stats() {
+        let stats = AllocStats::from_counters([
+            ("stats.allocated", 1),
+            ("stats.active", 2),
+            ("stats.metadata", 3),
+            ("stats.resident", 4),
+            ("stats.mapped", 5),
+            ("stats.retained", 6),
+            ("stats.unknown", 7),
+        ]);
+        assert_eq!(
+            stats,
+            AllocStats {
+                allocated: 1,
+                active: 2,
+                metadata: 3,
+                resident: 4,
+                mapped: 5,
+                retained: 6
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "allocated=1 active=2 metadata=3 resident=4 mapped=5 retained=6"
+        );
+
+        assert_eq!(AllocStats::from_counters([("stats.active", 2)]).active, 2);
+        assert_eq!(AllocStats::from_counters([("stats.active", 2)]).allocated, 0);
+    }
+}
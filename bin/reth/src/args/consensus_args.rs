@@ -0,0 +1,45 @@
+//! Shared arguments related to consensus
+
+use reth_auto_seal_consensus::AutoSealConsensus;
+use reth_beacon_consensus::BeaconConsensus;
+use reth_interfaces::consensus::Consensus;
+use reth_primitives::ChainSpec;
+use std::sync::Arc;
+
+/// The consensus implementation used to validate blocks.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum ConsensusKind {
+    /// The [BeaconConsensus] of ethereum.
+    #[default]
+    #[value(alias = "ethereum")]
+    Beacon,
+    /// The [AutoSealConsensus] for local development, which accepts all blocks.
+    AutoSeal,
+}
+
+impl ConsensusKind {
+    /// Creates the consensus implementation for the given chain.
+    pub fn consensus(&self, chain_spec: Arc<ChainSpec>) -> Arc<dyn Consensus> {
+        match self {
+            ConsensusKind::Beacon => Arc::new(BeaconConsensus::new(chain_spec)),
+            ConsensusKind::AutoSeal => Arc::new(AutoSealConsensus::new(chain_spec)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_primitives::MAINNET;
+
+    #[test]
+    fn creates_consensus_of_kind() {
+        for (kind, name) in [
+            (ConsensusKind::Beacon, "BeaconConsensus"),
+            (ConsensusKind::AutoSeal, "AutoSealConsensus"),
+        ] {
+            let consensus = kind.consensus(MAINNET.clone());
+            assert!(format!("{consensus:?}").starts_with(name), "{kind:?}");
+        }
+    }
+}
@@ -27,6 +27,10 @@ pub use payload_builder_args::PayloadBuilderArgs;
 mod stage_args;
 pub use stage_args::StageEnum;
 
+/// Consensus related arguments
+mod consensus_args;
+pub use consensus_args::ConsensusKind;
+
 /// Gas price oracle related arguments
 mod gas_price_oracle_args;
 pub use gas_price_oracle_args::GasPriceOracleArgs;
@@ -4,7 +4,7 @@ use crate::version::P2P_CLIENT_VERSION;
 use clap::Args;
 use reth_config::Config;
 use reth_net_nat::NatResolver;
-use reth_network::{HelloMessage, NetworkConfigBuilder};
+use reth_network::{HelloMessage, NetworkConfigBuilder, PeersConfig};
 use reth_primitives::{mainnet_nodes, ChainSpec, NodeRecord};
 use secp256k1::SecretKey;
 use std::{path::PathBuf, sync::Arc};
@@ -60,6 +60,14 @@ pub struct NetworkArgs {
     /// Network listening port. default: 30303
     #[arg(long = "port", value_name = "PORT")]
     pub port: Option<u16>,
+
+    /// Maximum number of outbound peers, overrides the value of the config file.
+    #[arg(long, value_name = "COUNT")]
+    pub max_outbound_peers: Option<usize>,
+
+    /// Maximum number of inbound peers, overrides the value of the config file.
+    #[arg(long, value_name = "COUNT")]
+    pub max_inbound_peers: Option<usize>,
 }
 
 impl NetworkArgs {
@@ -68,16 +76,22 @@ impl NetworkArgs {
     ///
     /// The `default_peers_file` will be used as the default location to store the persistent peers
     /// file if `no_persist_peers` is false, and there is no provided `peers_file`.
+    ///
+    /// Returns an error if the peer limits don't allow any connection, see
+    /// [`NetworkArgs::apply_peer_limits`].
     pub fn network_config(
         &self,
         config: &Config,
         chain_spec: Arc<ChainSpec>,
         secret_key: SecretKey,
         default_peers_file: PathBuf,
-    ) -> NetworkConfigBuilder {
+    ) -> eyre::Result<NetworkConfigBuilder> {
         let chain_bootnodes = chain_spec.chain.bootnodes().unwrap_or_else(mainnet_nodes);
         let peers_file = self.peers_file.clone().unwrap_or(default_peers_file);
 
+        let mut config = config.clone();
+        config.peers = self.apply_peer_limits(config.peers)?;
+
         // Configure basic network stack.
         let mut network_config_builder = config
             .network_config(self.nat, self.persistent_peers_file(peers_file), secret_key)
@@ -89,7 +103,25 @@ impl NetworkArgs {
         network_config_builder = network_config_builder
             .hello_message(HelloMessage::builder(peer_id).client_version(&self.identity).build());
 
-        self.discovery.apply_to_builder(network_config_builder)
+        Ok(self.discovery.apply_to_builder(network_config_builder))
+    }
+
+    /// Overrides the peer limits of the given [`PeersConfig`] with the ones of this option struct.
+    ///
+    /// Returns an error if the resulting limits don't allow a single connection.
+    pub fn apply_peer_limits(&self, mut peers: PeersConfig) -> eyre::Result<PeersConfig> {
+        if let Some(max_outbound) = self.max_outbound_peers {
+            peers = peers.with_max_outbound(max_outbound);
+        }
+        if let Some(max_inbound) = self.max_inbound_peers {
+            peers = peers.with_max_inbound(max_inbound);
+        }
+
+        let connection_info = &peers.connection_info;
+        if connection_info.max_outbound() + connection_info.max_inbound() == 0 {
+            eyre::bail!("The sum of the maximum outbound and inbound peers must be at least 1")
+        }
+        Ok(peers)
     }
 }
 
@@ -163,4 +195,64 @@ mod tests {
             CommandParser::<NetworkArgs>::parse_from(["reth", "--nat", "extip:0.0.0.0"]).args;
         assert_eq!(args.nat, NatResolver::ExternalIp("0.0.0.0".parse().unwrap()));
     }
+
+    #[test]
+    fn parse_peer_limit_args() {
+        let args = CommandParser::<NetworkArgs>::parse_from(["reth"]).args;
+        assert_eq!(args.max_outbound_peers, None);
+        assert_eq!(args.max_inbound_peers, None);
+
+        let args = CommandParser::<NetworkArgs>::parse_from([
+            "reth",
+            "--max-outbound-peers",
+            "10",
+            "--max-inbound-peers",
+            "20",
+        ])
+        .args;
+        assert_eq!(args.max_outbound_peers, Some(10));
+        assert_eq!(args.max_inbound_peers, Some(20));
+
+        assert!(CommandParser::<NetworkArgs>::try_parse_from([
+            "reth",
+            "--max-outbound-peers",
+            "-1"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn peer_limit_args_override_config() {
+        // limits as loaded from the config file
+        let peers = PeersConfig::default().with_max_outbound(50).with_max_inbound(60);
+
+        let args =
+            CommandParser::<NetworkArgs>::parse_from(["reth", "--max-outbound-peers", "5"]).args;
+        let peers = args.apply_peer_limits(peers).unwrap();
+        assert_eq!(peers.connection_info.max_outbound(), 5);
+        assert_eq!(peers.connection_info.max_inbound(), 60);
+
+        let args = CommandParser::<NetworkArgs>::parse_from(["reth"]).args;
+        let peers = args.apply_peer_limits(peers).unwrap();
+        assert_eq!(peers.connection_info.max_outbound(), 5);
+        assert_eq!(peers.connection_info.max_inbound(), 60);
+    }
+
+    #[test]
+    fn reject_zero_peer_limits() {
+        let args = CommandParser::<NetworkArgs>::parse_from([
+            "reth",
+            "--max-outbound-peers",
+            "0",
+            "--max-inbound-peers",
+            "0",
+        ])
+        .args;
+        assert!(args.apply_peer_limits(PeersConfig::default()).is_err());
+
+        // a single allowed connection is enough
+        let args =
+            CommandParser::<NetworkArgs>::parse_from(["reth", "--max-outbound-peers", "0"]).args;
+        assert!(args.apply_peer_limits(PeersConfig::default().with_max_inbound(1)).is_ok());
+    }
 }
@@ -1,7 +1,7 @@
 //! Clap parser utilities
 
 use reth_primitives::{
-    fs, AllGenesisFormats, BlockHashOrNumber, ChainSpec, GOERLI, MAINNET, SEPOLIA,
+    fs, Address, AllGenesisFormats, BlockHashOrNumber, ChainSpec, GOERLI, MAINNET, SEPOLIA, U256,
 };
 use reth_revm::primitives::B256 as H256;
 use std::{
@@ -100,6 +100,24 @@ pub fn parse_socket_address(value: &str) -> eyre::Result<SocketAddr, SocketAddre
         .ok_or_else(|| SocketAddressParsingError::Parse(value.to_string()))
 }
 
+/// Parse an `<ADDRESS>:<WEI>` pair of a genesis account and its balance.
+///
+/// The balance is either decimal or `0x` prefixed hex.
+pub fn parse_genesis_balance(value: &str) -> eyre::Result<(Address, U256)> {
+    let (address, balance) = value
+        .split_once(':')
+        .ok_or_else(|| eyre::eyre!("expected <ADDRESS>:<WEI>, got {value}"))?;
+    let address = address
+        .parse::<Address>()
+        .map_err(|err| eyre::eyre!("invalid address {address}: {err}"))?;
+    let balance = match balance.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16),
+        None => U256::from_str_radix(balance, 10),
+    }
+    .map_err(|err| eyre::eyre!("invalid balance {balance}: {err}"))?;
+    Ok((address, balance))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,4 +155,34 @@ mod tests {
             assert_eq!(socket_addr.port(), port);
         }
     }
+
+    #[test]
+    fn parse_genesis_balances() {
+        let address: Address = "0x71562b71999873DB5b286dF957af199Ec94617F7".parse().unwrap();
+        for value in [
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:1000000000000000000",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:0xde0b6b3a7640000",
+            "71562b71999873DB5b286dF957af199Ec94617F7:1000000000000000000",
+        ] {
+            assert_eq!(
+                parse_genesis_balance(value).unwrap(),
+                (address, U256::from(1_000_000_000_000_000_000u64))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_genesis_balance_malformed() {
+        for value in [
+            "",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:abc",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:0xzz",
+            "0x1234:1000",
+            "not-an-address:1000",
+        ] {
+            assert!(parse_genesis_balance(value).is_err(), "{value}");
+        }
+    }
 }
@@ -1,8 +1,9 @@
 //! CLI definition and entrypoint to executable
 use crate::{
-    chain, config, db, debug_cmd,
+    alloc, chain, config, db, debug_cmd,
     dirs::{LogsDir, PlatformPath},
     node, p2p,
+    panic_hook::{self, PanicStrategy},
     runner::CliRunner,
     stage, test_vectors,
     version::{LONG_VERSION, SHORT_VERSION},
@@ -18,6 +19,10 @@ use reth_tracing::{
 pub fn run() -> eyre::Result<()> {
     let opt = Cli::parse();
 
+    // This is done before any threads are spawned, because `set_var` is not thread safe.
+    let backtrace = resolve_backtrace(opt.backtrace, std::env::var("RUST_BACKTRACE").ok());
+    std::env::set_var("RUST_BACKTRACE", backtrace);
+
     let mut layers = vec![reth_tracing::stdout(opt.verbosity.directive())];
     let _guard = opt.logs.layer()?.map(|(layer, guard)| {
         layers.push(layer);
@@ -25,6 +30,8 @@ pub fn run() -> eyre::Result<()> {
     });
 
     reth_tracing::init(layers);
+    alloc::log_allocator();
+    panic_hook::install(opt.panic == PanicStrategy::Abort);
 
     let runner = CliRunner::default();
 
@@ -85,6 +92,56 @@ struct Cli {
 
     #[clap(flatten)]
     verbosity: Verbosity,
+
+    /// What to do after a thread panicked.
+    ///
+    /// Panics are always logged. `abort` terminates the process afterwards, so that it can be
+    /// restarted by a supervisor.
+    #[arg(long, value_enum, global = true, default_value_t)]
+    panic: PanicStrategy,
+
+    /// Which backtraces to capture on panic.
+    ///
+    /// Takes precedence over the `RUST_BACKTRACE` environment variable. If neither is set, short
+    /// backtraces are captured.
+    #[arg(long, value_enum, global = true)]
+    backtrace: Option<Backtrace>,
+}
+
+/// The backtrace setting, equivalent to the values of the `RUST_BACKTRACE` environment variable.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, clap::ValueEnum)]
+pub enum Backtrace {
+    /// Don't capture backtraces.
+    #[value(name = "0")]
+    Disabled,
+    /// Capture short backtraces.
+    #[value(name = "1")]
+    Short,
+    /// Capture full backtraces.
+    Full,
+}
+
+impl Backtrace {
+    /// Returns the value of the `RUST_BACKTRACE` environment variable for this setting.
+    pub fn as_env_value(&self) -> &'static str {
+        match self {
+            Backtrace::Disabled => "0",
+            Backtrace::Short => "1",
+            Backtrace::Full => "full",
+        }
+    }
+}
+
+/// Returns the value `RUST_BACKTRACE` should be set to.
+///
+/// The `--backtrace` flag takes precedence over the current value of the environment variable.
+/// Defaults to `1` if neither is set.
+fn resolve_backtrace(flag: Option<Backtrace>, env: Option<String>) -> String {
+    match (flag, env) {
+        (Some(flag), _) => flag.as_env_value().to_string(),
+        (None, Some(env)) => env,
+        (None, None) => Backtrace::Short.as_env_value().to_string(),
+    }
 }
 
 /// The log configuration.
@@ -196,4 +253,39 @@ mod tests {
             assert_eq!(err.kind(), clap::error::ErrorKind::DisplayHelp);
         }
     }
+
+    #[test]
+    fn resolve_backtrace_values() {
+        // neither is set
+        assert_eq!(resolve_backtrace(None, None), "1");
+
+        // only the flag is set
+        assert_eq!(resolve_backtrace(Some(Backtrace::Disabled), None), "0");
+        assert_eq!(resolve_backtrace(Some(Backtrace::Full), None), "full");
+
+        // only the env var is set
+        assert_eq!(resolve_backtrace(None, Some("full".to_string())), "full");
+
+        // flag takes precedence over the env var
+        assert_eq!(resolve_backtrace(Some(Backtrace::Disabled), Some("full".to_string())), "0");
+    }
+
+    #[test]
+    fn parse_backtrace() {
+        for (value, backtrace) in
+            [("0", Backtrace::Disabled), ("1", Backtrace::Short), ("full", Backtrace::Full)]
+        {
+            let cli = Cli::try_parse_from(["reth", "node", "--backtrace", value]).unwrap();
+            assert_eq!(cli.backtrace, Some(backtrace));
+        }
+    }
+
+    #[test]
+    fn parse_panic_strategy() {
+        let cli = Cli::try_parse_from(["reth", "node"]).unwrap();
+        assert_eq!(cli.panic, PanicStrategy::Unwind);
+
+        let cli = Cli::try_parse_from(["reth", "node", "--panic", "abort"]).unwrap();
+        assert_eq!(cli.panic, PanicStrategy::Abort);
+    }
 }
@@ -23,6 +23,7 @@
 //! - `min-debug-logs`: Disables all logs below `debug` level.
 //! - `min-trace-logs`: Disables all logs below `trace` level.
 
+pub mod alloc;
 pub mod args;
 pub mod chain;
 pub mod cli;
@@ -33,6 +34,7 @@ pub mod dirs;
 pub mod init;
 pub mod node;
 pub mod p2p;
+pub mod panic_hook;
 pub mod prometheus_exporter;
 pub mod runner;
 pub mod stage;
@@ -2,6 +2,7 @@
 //!
 //! Starts the client
 use crate::{
+    alloc::{self, ALLOC_STATS_INTERVAL},
     args::{get_secret_key, DebugArgs, NetworkArgs, RpcServerArgs, TxPoolArgs},
     dirs::DataDirPath,
     init::init_genesis,
@@ -14,9 +15,9 @@ use clap::Parser;
 use eyre::Context;
 use fdlimit::raise_fd_limit;
 use futures::{future::Either, pin_mut, stream, stream_select, StreamExt};
-use reth_auto_seal_consensus::{AutoSealBuilder, AutoSealConsensus};
+use reth_auto_seal_consensus::{AutoSealBuilder, MiningMode};
 use reth_basic_payload_builder::{BasicPayloadJobGenerator, BasicPayloadJobGeneratorConfig};
-use reth_beacon_consensus::{BeaconConsensus, BeaconConsensusEngine, MIN_BLOCKS_FOR_PIPELINE_RUN};
+use reth_beacon_consensus::{BeaconConsensusEngine, MIN_BLOCKS_FOR_PIPELINE_RUN};
 use reth_blockchain_tree::{
     config::BlockchainTreeConfig, externals::TreeExternals, BlockchainTree, ShareableBlockchainTree,
 };
@@ -38,7 +39,8 @@ use reth_interfaces::{
 use reth_network::{error::NetworkError, NetworkConfig, NetworkHandle, NetworkManager};
 use reth_network_api::NetworkInfo;
 use reth_primitives::{
-    stage::StageId, BlockHashOrNumber, BlockNumber, ChainSpec, Head, SealedHeader, H256,
+    stage::StageId, Address, BlockHashOrNumber, BlockNumber, ChainSpec, Head, SealedHeader, H256,
+    U256,
 };
 use reth_provider::{
     BlockHashReader, BlockReader, CanonStateSubscriptions, HeaderProvider, ProviderFactory,
@@ -62,14 +64,18 @@ use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 use tokio::sync::{mpsc::unbounded_channel, oneshot, watch};
 use tracing::*;
 
 use crate::{
     args::{
-        utils::{genesis_value_parser, parse_socket_address},
-        DatabaseArgs, PayloadBuilderArgs,
+        utils::{
+            genesis_value_parser, parse_duration_from_secs, parse_genesis_balance,
+            parse_socket_address,
+        },
+        ConsensusKind, DatabaseArgs, PayloadBuilderArgs,
     },
     dirs::MaybePlatformPath,
     node::cl_events::ConsensusLayerHealthEvents,
@@ -126,6 +132,12 @@ pub struct Command {
     #[arg(long, value_name = "SOCKET", value_parser = parse_socket_address, help_heading = "Metrics")]
     metrics: Option<SocketAddr>,
 
+    /// Periodically log the stats of the global allocator.
+    ///
+    /// The stats are only available if reth is built with the `jemalloc` feature.
+    #[arg(long, help_heading = "Metrics")]
+    alloc_stats: bool,
+
     #[clap(flatten)]
     network: NetworkArgs,
 
@@ -144,20 +156,51 @@ pub struct Command {
     #[clap(flatten)]
     db: DatabaseArgs,
 
+    /// The consensus implementation used to validate blocks.
+    ///
+    /// Mining blocks with `--auto-mine` always uses `auto-seal`, so the two can't be combined.
+    #[arg(long, value_enum, default_value_t)]
+    consensus: ConsensusKind,
+
     /// Automatically mine blocks for new transactions
-    #[arg(long)]
+    #[arg(long, conflicts_with = "consensus")]
     auto_mine: bool,
+
+    /// The interval between blocks mined with `--auto-mine`, in seconds.
+    ///
+    /// Blocks are mined at this interval even if there are no transactions.
+    #[arg(
+        long = "dev.block-time",
+        value_name = "SECONDS",
+        value_parser = parse_duration_from_secs,
+        requires = "auto_mine"
+    )]
+    dev_block_time: Option<Duration>,
+
+    /// Prefund an account in the genesis block when mining with `--auto-mine`.
+    ///
+    /// Expects `<ADDRESS>:<WEI>`, where the balance is either decimal or `0x` prefixed hex. Can be
+    /// passed multiple times.
+    #[arg(
+        long = "dev.genesis-balance",
+        value_name = "ADDRESS:WEI",
+        value_parser = parse_genesis_balance,
+        requires = "auto_mine"
+    )]
+    dev_genesis_balances: Vec<(Address, U256)>,
 }
 
 impl Command {
     /// Execute `node` command
-    pub async fn execute(self, ctx: CliContext) -> eyre::Result<()> {
+    pub async fn execute(mut self, ctx: CliContext) -> eyre::Result<()> {
         info!(target: "reth::cli", "reth {} starting", SHORT_VERSION);
 
         // Raise the fd limit of the process.
         // Does not do anything on windows.
         raise_fd_limit();
 
+        self.apply_dev_genesis_balances();
+
         // add network name to data dir
         let data_dir = self.datadir.unwrap_or_chain_default(self.chain.chain);
         let config_path = self.config.clone().unwrap_or(data_dir.config_path());
@@ -174,18 +217,20 @@ impl Command {
 
         self.start_metrics_endpoint(Arc::clone(&db)).await?;
 
+        if self.alloc_stats {
+            debug!(target: "reth::cli", "Spawning allocator stats task");
+            ctx.task_executor.spawn(Box::pin(alloc::log_alloc_stats(ALLOC_STATS_INTERVAL)));
+        }
+
         debug!(target: "reth::cli", chain=%self.chain.chain, genesis=?self.chain.genesis_hash(), "Initializing genesis");
 
         let genesis_hash = init_genesis(db.clone(), self.chain.clone())?;
 
         info!(target: "reth::cli", "{}", DisplayHardforks::from(self.chain.hardforks().clone()));
 
-        let consensus: Arc<dyn Consensus> = if self.auto_mine {
-            debug!(target: "reth::cli", "Using auto seal");
-            Arc::new(AutoSealConsensus::new(Arc::clone(&self.chain)))
-        } else {
-            Arc::new(BeaconConsensus::new(Arc::clone(&self.chain)))
-        };
+        let consensus_kind = self.consensus_kind();
+        debug!(target: "reth::cli", ?consensus_kind, "Using consensus");
+        let consensus = consensus_kind.consensus(Arc::clone(&self.chain));
 
         self.init_trusted_nodes(&mut config);
 
@@ -260,7 +305,7 @@ impl Command {
             head,
             secret_key,
             default_peers_path.clone(),
-        );
+        )?;
         let network = self
             .start_network(
                 network_config,
@@ -302,14 +347,17 @@ impl Command {
 
         // Configure the pipeline
         let (mut pipeline, client) = if self.auto_mine {
-            let (_, client, mut task) = AutoSealBuilder::new(
+            let mut builder = AutoSealBuilder::new(
                 Arc::clone(&self.chain),
                 blockchain_db.clone(),
                 transaction_pool.clone(),
                 consensus_engine_tx.clone(),
                 canon_state_notification_sender,
-            )
-            .build();
+            );
+            if let Some(block_time) = self.dev_block_time {
+                builder = builder.mode(MiningMode::interval(block_time));
+            }
+            let (_, client, mut task) = builder.build();
 
             let mut pipeline = self
                 .build_networked_pipeline(
@@ -490,6 +538,30 @@ impl Command {
         Ok(pipeline)
     }
 
+    /// Returns the consensus implementation to use.
+    fn consensus_kind(&self) -> ConsensusKind {
+        if self.auto_mine {
+            ConsensusKind::AutoSeal
+        } else {
+            self.consensus
+        }
+    }
+
+    /// Adds the balances of `--dev.genesis-balance` to the genesis of the chain.
+    fn apply_dev_genesis_balances(&mut self) {
+        if self.dev_genesis_balances.is_empty() {
+            return
+        }
+
+        let mut chain = ChainSpec::clone(&self.chain);
+        for (address, balance) in &self.dev_genesis_balances {
+            chain.genesis.alloc.entry(*address).or_default().balance = *balance;
+        }
+        // the genesis changed, so the known hash of a built-in chain no longer applies
+        chain.genesis_hash = None;
+        self.chain = Arc::new(chain);
+    }
+
     /// Loads the reth config with the given datadir root
     fn load_config(&self, config_path: PathBuf) -> eyre::Result<Config> {
         confy::load_path::<Config>(config_path.clone())
@@ -638,9 +710,10 @@ impl Command {
         head: Head,
         secret_key: SecretKey,
         default_peers_path: PathBuf,
-    ) -> NetworkConfig<ProviderFactory<Arc<DatabaseEnv>>> {
-        self.network
-            .network_config(config, self.chain.clone(), secret_key, default_peers_path)
+    ) -> eyre::Result<NetworkConfig<ProviderFactory<Arc<DatabaseEnv>>>> {
+        Ok(self
+            .network
+            .network_config(config, self.chain.clone(), secret_key, default_peers_path)?
             .with_task_executor(Box::new(executor))
             .set_head(head)
             .listener_addr(SocketAddr::V4(SocketAddrV4::new(
@@ -651,7 +724,7 @@ impl Command {
                 Ipv4Addr::UNSPECIFIED,
                 self.network.discovery.port.unwrap_or(DEFAULT_DISCOVERY_PORT),
             )))
-            .build(ProviderFactory::new(db, self.chain.clone()))
+            .build(ProviderFactory::new(db, self.chain.clone())))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -805,6 +878,74 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_dev_block_time() {
+        let cmd =
+            Command::try_parse_from(["reth", "--auto-mine", "--dev.block-time", "12"]).unwrap();
+        assert_eq!(cmd.dev_block_time, Some(Duration::from_secs(12)));
+
+        // only valid in combination with auto mining
+        assert!(Command::try_parse_from(["reth", "--dev.block-time", "12"]).is_err());
+    }
+
+    #[test]
+    fn parse_dev_genesis_balances() {
+        let mut cmd = Command::try_parse_from([
+            "reth",
+            "--auto-mine",
+            "--dev.genesis-balance",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:1000",
+            "--dev.genesis-balance",
+            "0x2e0a73b5F6D3Ea5a6e1E1E4F6b3f9a1d9F3C5b7A:0x10",
+        ])
+        .unwrap();
+        assert_eq!(cmd.dev_genesis_balances.len(), 2);
+
+        let genesis_hash = cmd.chain.genesis_hash();
+        cmd.apply_dev_genesis_balances();
+        for (address, balance) in &cmd.dev_genesis_balances {
+            assert_eq!(cmd.chain.genesis.alloc[address].balance, *balance);
+        }
+        assert_ne!(cmd.chain.genesis_hash(), genesis_hash);
+
+        // only valid in combination with auto mining
+        assert!(Command::try_parse_from([
+            "reth",
+            "--dev.genesis-balance",
+            "0x71562b71999873DB5b286dF957af199Ec94617F7:1000"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_alloc_stats() {
+        let cmd = Command::try_parse_from(["reth"]).unwrap();
+        assert!(!cmd.alloc_stats);
+
+        let cmd = Command::try_parse_from(["reth", "--alloc-stats"]).unwrap();
+        assert!(cmd.alloc_stats);
+    }
+
+    #[test]
+    fn parse_consensus() {
+        let cmd = Command::try_parse_from(["reth"]).unwrap();
+        assert_eq!(cmd.consensus_kind(), ConsensusKind::Beacon);
+
+        for (value, kind) in [
+            ("beacon", ConsensusKind::Beacon),
+            ("ethereum", ConsensusKind::Beacon),
+            ("auto-seal", ConsensusKind::AutoSeal),
+        ] {
+            let cmd = Command::try_parse_from(["reth", "--consensus", value]).unwrap();
+            assert_eq!(cmd.consensus_kind(), kind);
+        }
+
+        let cmd = Command::try_parse_from(["reth", "--auto-mine"]).unwrap();
+        assert_eq!(cmd.consensus_kind(), ConsensusKind::AutoSeal);
+
+        assert!(Command::try_parse_from(["reth", "--auto-mine", "--consensus", "beacon"]).is_err());
+    }
+
     #[test]
     fn parse_discovery_port() {
         let cmd = Command::try_parse_from(["reth", "--discovery.port", "300"]).unwrap();
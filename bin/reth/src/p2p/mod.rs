@@ -16,7 +16,11 @@ use reth_discv4::NatResolver;
 use reth_interfaces::p2p::bodies::client::BodiesClient;
 use reth_primitives::{BlockHashOrNumber, ChainSpec, NodeRecord};
 use reth_provider::ProviderFactory;
-use std::{path::PathBuf, sync::Arc};
+use reth_rpc_builder::constants::DEFAULT_IPC_ENDPOINT;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 /// `reth p2p` command
 #[derive(Debug, Parser)]
@@ -99,10 +103,24 @@ pub enum Subcommands {
         #[arg(value_parser = hash_or_num_value_parser)]
         id: BlockHashOrNumber,
     },
+    /// Write the active peers of a running node as JSON
+    DumpPeers {
+        /// The IPC endpoint of the running node
+        #[arg(long, value_name = "PATH", default_value = DEFAULT_IPC_ENDPOINT)]
+        ipcpath: String,
+        /// The file to write the peers to, defaults to stdout
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
 }
 impl Command {
     /// Execute `p2p` command
     pub async fn execute(&self) -> eyre::Result<()> {
+        // the peers are queried from a running node, so there's no need to start a network
+        if let Subcommands::DumpPeers { ipcpath, output } = &self.command {
+            return dump_peers(ipcpath, output.as_deref()).await
+        }
+
         let tempdir = tempfile::TempDir::new()?;
         let noop_db = Arc::new(open_db(&tempdir.into_path(), self.db.log_level)?);
 
@@ -180,8 +198,30 @@ impl Command {
                 let body = result.into_iter().next().unwrap();
                 println!("Successfully downloaded body: {body:?}")
             }
+            Subcommands::DumpPeers { .. } => unreachable!("handled before starting the network"),
         }
 
         Ok(())
     }
 }
+
+/// Fetches the active peers of the node listening on the given IPC endpoint and writes them as
+/// JSON to the output file, or stdout if there is none.
+#[cfg(unix)]
+async fn dump_peers(ipcpath: &str, output: Option<&Path>) -> eyre::Result<()> {
+    use reth_rpc_api::clients::AdminApiClient;
+
+    let client = reth_ipc::client::IpcClientBuilder::default().build(ipcpath).await?;
+    let peers = AdminApiClient::peers(&client).await?;
+    let json = serde_json::to_string_pretty(&peers)?;
+    match output {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn dump_peers(_ipcpath: &str, _output: Option<&Path>) -> eyre::Result<()> {
+    eyre::bail!("Dumping peers over IPC is only supported on unix")
+}
@@ -0,0 +1,74 @@
+//! Panic hook that logs panics.
+
+use std::{any::Any, panic};
+use tracing::error;
+
+/// What to do after a thread panicked.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum PanicStrategy {
+    /// Abort the process.
+    Abort,
+    /// Unwind the panicking thread and keep the process running.
+    #[default]
+    Unwind,
+}
+
+/// Installs a panic hook that logs panics.
+///
+/// The hook is chained with the previously installed hook. If `abort_on_panic` is set, the process
+/// is aborted after the panic was handled, so that it can be restarted by a supervisor.
+pub fn install(abort_on_panic: bool) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let location = info.location().map(ToString::to_string);
+        error!(
+            target: "reth::cli",
+            message = payload_message(info.payload()),
+            location = location.as_deref().unwrap_or("<unknown>"),
+            thread = std::thread::current().name().unwrap_or("<unnamed>"),
+            "Thread panicked"
+        );
+
+        previous(info);
+
+        if abort_on_panic {
+            std::process::abort();
+        }
+    }));
+}
+
+/// Returns the message of a panic payload.
+///
+/// Panics with a message are either a `&'static str` or a `String`.
+fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_panic_payload() {
+        let payload: Box<dyn Any + Send> = Box::new("static message");
+        assert_eq!(payload_message(payload.as_ref()), "static message");
+
+        let payload: Box<dyn Any + Send> = Box::new(format!("formatted {}", "message"));
+        assert_eq!(payload_message(payload.as_ref()), "formatted message");
+
+        let payload: Box<dyn Any + Send> = Box::new(1u64);
+        assert_eq!(payload_message(payload.as_ref()), "Box<dyn Any>");
+    }
+
+    #[test]
+    fn formats_caught_panic_payload() {
+        let payload = panic::catch_unwind(|| panic!("value {}", 1)).unwrap_err();
+        assert_eq!(payload_message(payload.as_ref()), "value 1");
+    }
+}
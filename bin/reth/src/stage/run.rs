@@ -169,7 +169,7 @@ impl Command {
                             self.chain.clone(),
                             p2p_secret_key,
                             default_peers_path,
-                        )
+                        )?
                         .build(Arc::new(ProviderFactory::new(db.clone(), self.chain.clone())))
                         .start_network()
                         .await?;
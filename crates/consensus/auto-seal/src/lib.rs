@@ -124,7 +124,7 @@ where
         }
     }
 
-    /// Sets the [MiningMode] it operates in, default is [MiningMode::Auto]
+    /// Sets the [MiningMode] it operates in, default is a [MiningMode::FixedBlockTime] of 1 second
     pub fn mode(mut self, mode: MiningMode) -> Self {
         self.mode = mode;
         self
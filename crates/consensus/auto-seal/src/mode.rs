@@ -153,3 +153,28 @@ impl fmt::Debug for ReadyTransactionMiner {
             .finish_non_exhaustive()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::poll_fn;
+    use reth_transaction_pool::test_utils::testing_pool;
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn fixed_block_time_mines_without_transactions() {
+        let pool = testing_pool();
+        let block_time = Duration::from_secs(1);
+        let mut mode = MiningMode::interval(block_time);
+
+        let start = Instant::now();
+        for block in 1..=2 {
+            assert!(poll_fn(|cx| Poll::Ready(mode.poll(&pool, cx))).await.is_pending());
+
+            tokio::time::advance(block_time).await;
+            let transactions = poll_fn(|cx| mode.poll(&pool, cx)).await;
+            assert!(transactions.is_empty());
+            assert_eq!(start.elapsed(), block_time * block);
+        }
+    }
+}
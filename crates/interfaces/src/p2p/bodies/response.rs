@@ -18,6 +18,14 @@ impl BlockResponse {
         }
     }
 
+    /// Consumes the response and returns the header
+    pub fn into_header(self) -> SealedHeader {
+        match self {
+            BlockResponse::Full(block) => block.header,
+            BlockResponse::Empty(header) => header,
+        }
+    }
+
     /// Returns the total number of bytes of all transactions input data in the block
     pub fn size(&self) -> usize {
         match self {
@@ -159,6 +159,14 @@ pub enum DownloadError {
         /// How many headers we expected.
         expected: u64,
     },
+    /// Received a header that doesn't match the requested hash.
+    #[error("Received header {received} while requesting header {expected}.")]
+    HeaderHashMismatch {
+        /// The hash of the requested header
+        expected: H256,
+        /// The hash of the received header
+        received: H256,
+    },
     /* ==================== BODIES ERRORS ==================== */
     /// Block validation failed
     #[error("Failed to validate body for header {hash}. Details: {error}.")]
@@ -169,6 +177,17 @@ pub enum DownloadError {
         #[source]
         error: consensus::ConsensusError,
     },
+    /// Validation of the body of a downloaded block failed
+    #[error("Failed to validate body for header {hash} of block {number}. Details: {error}.")]
+    BlockBodyValidation {
+        /// Hash of header failing validation
+        hash: H256,
+        /// Number of the block failing validation
+        number: BlockNumber,
+        /// The details of validation failure
+        #[source]
+        error: consensus::ConsensusError,
+    },
     /// Received more bodies than requested.
     #[error("Received more bodies than requested. Expected: {expected}. Received: {received}")]
     TooManyBodies {
@@ -189,6 +208,20 @@ pub enum DownloadError {
         /// Invalid block number range.
         range: RangeInclusive<BlockNumber>,
     },
+    /// Failed to download the bodies of a block range within the allowed number of attempts.
+    #[error("Failed to download bodies for range {range:?} after {attempts} attempts.")]
+    BodiesAttemptsExhausted {
+        /// Block number range of the bodies that couldn't be downloaded.
+        range: RangeInclusive<BlockNumber>,
+        /// Number of failed attempts.
+        attempts: usize,
+    },
+    /// The bodies request of a block range was cancelled.
+    #[error("Bodies request for range {range:?} was cancelled.")]
+    BodiesRequestCancelled {
+        /// Block number range of the cancelled request.
+        range: RangeInclusive<BlockNumber>,
+    },
     /* ==================== COMMON ERRORS ==================== */
     /// Timed out while waiting for request id response.
     #[error("Timed out while waiting for response.")]
@@ -102,6 +102,16 @@ impl BanList {
         self.banned_peers.contains_key(peer_id)
     }
 
+    /// Returns an iterator over all banned peers.
+    pub fn banned_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.banned_peers.keys().copied()
+    }
+
+    /// Returns an iterator over all banned ip addresses.
+    pub fn banned_ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.banned_ips.keys().copied()
+    }
+
     /// Unbans the ip address
     pub fn unban_ip(&mut self, ip: &IpAddr) {
         self.banned_ips.remove(ip);
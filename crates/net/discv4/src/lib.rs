@@ -300,6 +300,18 @@ impl Discv4 {
         self.safe_send_to_service(cmd);
     }
 
+    /// Removes the peer from the ban list.
+    pub fn unban_node(&self, node_id: PeerId) {
+        let cmd = Discv4Command::UnbanPeer(node_id);
+        self.safe_send_to_service(cmd);
+    }
+
+    /// Removes the ip from the ban list.
+    pub fn unban_ip(&self, ip: IpAddr) {
+        let cmd = Discv4Command::UnbanIp(ip);
+        self.safe_send_to_service(cmd);
+    }
+
     /// Sets the tcp port
     ///
     /// This will update our [`NodeRecord`]'s tcp port.
@@ -705,6 +717,16 @@ impl Discv4Service {
         self.config.ban_list.ban_peer(node_id);
     }
 
+    /// Removes the ip from the ban list.
+    pub fn unban_ip(&mut self, ip: IpAddr) {
+        self.config.ban_list.unban_ip(&ip);
+    }
+
+    /// Removes the peer from the ban list.
+    pub fn unban_node(&mut self, node_id: PeerId) {
+        self.config.ban_list.unban_peer(&node_id);
+    }
+
     /// Adds the ip to the ban list until the given timestamp.
     pub fn ban_ip_until(&mut self, ip: IpAddr, until: Instant) {
         self.config.ban_list.ban_ip_until(ip, until);
@@ -1469,6 +1491,8 @@ impl Discv4Service {
                             Discv4Command::BanIp(ip) => {
                                 self.ban_ip(ip);
                             }
+                            Discv4Command::UnbanPeer(node_id) => self.unban_node(node_id),
+                            Discv4Command::UnbanIp(ip) => self.unban_ip(ip),
                             Discv4Command::SetEIP868RLPPair { key, rlp } => {
                                 debug!(target: "discv4", key=%String::from_utf8_lossy(&key), "Update EIP-868 extension pair");
 
@@ -1646,6 +1670,8 @@ enum Discv4Command {
     Ban(PeerId, IpAddr),
     BanPeer(PeerId),
     BanIp(IpAddr),
+    UnbanPeer(PeerId),
+    UnbanIp(IpAddr),
     Remove(PeerId),
     Lookup { node_id: Option<PeerId>, tx: Option<NodeRecordSender> },
     SetLookupInterval(Duration),
@@ -2292,6 +2318,21 @@ mod tests {
         let _ = discv4.lookup_self().await;
     }
 
+    #[tokio::test]
+    async fn test_unban() {
+        let (_discv4, mut service) = create_discv4().await;
+        let node_id = PeerId::random();
+        let ip = IpAddr::from([1, 2, 3, 4]);
+
+        service.ban_node(node_id);
+        service.ban_ip(ip);
+        assert!(service.config.ban_list.is_banned(&node_id, &ip));
+
+        service.unban_node(node_id);
+        service.unban_ip(ip);
+        assert!(!service.config.ban_list.is_banned(&node_id, &ip));
+    }
+
     // sends a PING packet with wrong 'to' field and expects a PONG response.
     #[tokio::test(flavor = "multi_thread")]
     async fn test_check_wrong_to() {
@@ -1,4 +1,4 @@
-use super::queue::BodiesRequestQueue;
+use super::{queue::BodiesRequestQueue, request::DEFAULT_MAX_REQUEST_ATTEMPTS};
 use crate::{bodies::task::TaskDownloader, metrics::BodyDownloaderMetrics};
 use futures::Stream;
 use futures_util::StreamExt;
@@ -12,9 +12,10 @@ use reth_interfaces::{
             response::BlockResponse,
         },
         error::{DownloadError, DownloadResult},
+        headers::client::HeadersClient,
     },
 };
-use reth_primitives::{BlockNumber, SealedHeader};
+use reth_primitives::{BlockNumber, SealedHeader, H256};
 use reth_tasks::{TaskSpawner, TokioTaskExecutor};
 use std::{
     cmp::Ordering,
@@ -23,9 +24,12 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 use tracing::info;
 
+pub use super::queue::{PushByHashesError, ResponseSizeStats};
+
 /// The scope for headers downloader metrics.
 pub const BODIES_DOWNLOADER_SCOPE: &str = "downloaders.bodies";
 
@@ -71,6 +75,37 @@ where
     B: BodiesClient + 'static,
     DB: Database + Unpin + 'static,
 {
+    /// Returns the aggregated sizes of all successful bodies responses received so far.
+    ///
+    /// This helps to tune the request limit, see [BodiesDownloaderBuilder::with_request_limit].
+    pub fn response_size_stats(&self) -> &ResponseSizeStats {
+        self.in_progress_queue.response_size_stats()
+    }
+
+    /// Cancels all in-flight requests whose block range contains the given block number, for
+    /// example because a reorg invalidated it.
+    ///
+    /// Once a cancelled request resolves, the download is cleared and the stream yields
+    /// [DownloadError::BodiesRequestCancelled]. Returns the number of cancelled requests.
+    pub fn cancel_range(&mut self, block: BlockNumber) -> usize {
+        self.in_progress_queue.cancel_range(block)
+    }
+
+    /// Returns the number of bodies per second received within the last 10 seconds.
+    ///
+    /// Unlike the [BodyDownloaderMetrics] this is the live rate, which is useful for adapting the
+    /// request limit.
+    pub fn throughput(&self) -> f64 {
+        self.in_progress_queue.throughput()
+    }
+
+    /// Returns how long the oldest in-flight request has been pending, `None` if there is none.
+    ///
+    /// A monitoring task can use this to detect a stuck download.
+    pub fn oldest_request_age(&self) -> Option<Duration> {
+        self.in_progress_queue.oldest_request_age()
+    }
+
     /// Returns the next contiguous request.
     fn next_headers_request(&mut self) -> DownloadResult<Option<Vec<SealedHeader>>> {
         let start_at = match self.in_progress_queue.last_requested_block_number {
@@ -212,6 +247,29 @@ where
         self.metrics.queued_blocks.set(0.);
     }
 
+    /// Skips all blocks below the given block number that haven't been queued yet, for example
+    /// because the download range moved past them.
+    ///
+    /// In-flight requests for these blocks are dropped.
+    fn skip_below(&mut self, block: BlockNumber) {
+        if block <= self.next_expected_block_number() {
+            return
+        }
+
+        let dropped = self.in_progress_queue.retain_above(block);
+        self.metrics.in_flight_requests.decrement(dropped as f64);
+
+        // all queued bodies are below the next expected block
+        self.queued_bodies.clear();
+        self.metrics.queued_blocks.set(0.);
+        self.latest_queued_block_number = Some(block - 1);
+
+        if self.in_progress_queue.last_requested_block_number.map_or(true, |last| last < block - 1)
+        {
+            self.in_progress_queue.last_requested_block_number = Some(block - 1);
+        }
+    }
+
     /// Queues bodies and sets the latest queued block number
     fn queue_bodies(&mut self, bodies: Vec<BlockResponse>) {
         self.latest_queued_block_number = Some(bodies.last().expect("is not empty").block_number());
@@ -282,6 +340,29 @@ where
     }
 }
 
+impl<B, DB> BodiesDownloader<B, DB>
+where
+    B: BodiesClient + HeadersClient + 'static,
+    DB: Database + Unpin + 'static,
+{
+    /// Requests the bodies of the given block hashes, in addition to the bodies of the download
+    /// range.
+    ///
+    /// The headers of the hashes are fetched first, so this is useful to recover bodies that are
+    /// only known by their hash. The hashes must be of consecutive blocks. Like any other response,
+    /// the bodies are only yielded in order and within the download range, and if the request
+    /// fails the download is cleared and the stream yields the error.
+    pub fn request_bodies_by_hashes(&mut self, hashes: Vec<H256>) -> Result<(), PushByHashesError> {
+        self.in_progress_queue.push_new_request_by_hashes(
+            Arc::clone(&self.client),
+            Arc::clone(&self.consensus),
+            hashes,
+        )?;
+        self.metrics.in_flight_requests.increment(1.);
+        Ok(())
+    }
+}
+
 impl<B, DB> BodiesDownloader<B, DB>
 where
     B: BodiesClient + 'static,
@@ -326,7 +407,9 @@ where
             *range.end() == *self.download_range.end();
         if is_current_range_subset {
             tracing::trace!(target: "downloaders::bodies", ?range, "Download range already in progress");
-            // The current range already includes requested.
+            // The current range already includes requested, but blocks below the new start are no
+            // longer needed.
+            self.skip_below(*range.start());
             return Ok(())
         }
 
@@ -390,16 +473,26 @@ where
             // Submit new requests
             let concurrent_requests_limit = this.concurrent_request_limit();
             'inner: while this.in_progress_queue.len() < concurrent_requests_limit &&
+                this.in_progress_queue.has_capacity() &&
                 this.has_buffer_capacity()
             {
                 match this.next_headers_request() {
                     Ok(Some(request)) => {
                         this.metrics.in_flight_requests.increment(1.);
-                        this.in_progress_queue.push_new_request(
-                            Arc::clone(&this.client),
-                            Arc::clone(&this.consensus),
-                            request,
-                        );
+                        // the stream can't make progress until the next expected block arrives, so
+                        // that request is prioritized
+                        let is_next_expected = request.first().map_or(false, |header| {
+                            header.number == this.next_expected_block_number()
+                        });
+                        let client = Arc::clone(&this.client);
+                        let consensus = Arc::clone(&this.consensus);
+                        if is_next_expected {
+                            this.in_progress_queue
+                                .push_new_request_prioritized(client, consensus, request)
+                        } else {
+                            this.in_progress_queue.push_new_request(client, consensus, request)
+                        }
+                        .expect("queue has capacity");
                         new_request_submitted = true;
                     }
                     Ok(None) => break 'inner,
@@ -509,6 +602,14 @@ pub struct BodiesDownloaderBuilder {
     pub max_buffered_blocks_size_bytes: usize,
     /// The maximum number of requests to send concurrently.
     pub concurrent_requests_range: RangeInclusive<usize>,
+    /// The number of failed attempts after which a request for a range of bodies gives up.
+    pub max_request_attempts: usize,
+    /// How long a single bodies request may take before it's sent again, `None` if only the
+    /// timeout of the peer session applies.
+    pub request_timeout: Option<Duration>,
+    /// The maximum number of responses handled in a row before the downloader submits new
+    /// requests, `None` if unlimited.
+    pub max_results_per_poll: Option<usize>,
 }
 
 impl Default for BodiesDownloaderBuilder {
@@ -518,6 +619,9 @@ impl Default for BodiesDownloaderBuilder {
             stream_batch_size: 10_000,
             max_buffered_blocks_size_bytes: 4 * 1024 * 1024 * 1024, // ~4GB
             concurrent_requests_range: 5..=100,
+            max_request_attempts: DEFAULT_MAX_REQUEST_ATTEMPTS,
+            request_timeout: None,
+            max_results_per_poll: None,
         }
     }
 }
@@ -553,6 +657,27 @@ impl BodiesDownloaderBuilder {
         self
     }
 
+    /// Set the number of failed attempts after which a request for a range of bodies gives up.
+    pub fn with_max_request_attempts(mut self, max_request_attempts: usize) -> Self {
+        self.max_request_attempts = max_request_attempts;
+        self
+    }
+
+    /// Set how long a single bodies request may take before it's sent again.
+    pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = Some(request_timeout);
+        self
+    }
+
+    /// Set the maximum number of responses handled in a row before the downloader submits new
+    /// requests.
+    ///
+    /// This keeps the downloader responsive if many responses are ready at once.
+    pub fn with_max_results_per_poll(mut self, max_results_per_poll: usize) -> Self {
+        self.max_results_per_poll = Some(max_results_per_poll);
+        self
+    }
+
     /// Consume self and return the concurrent downloader.
     pub fn build<B, DB>(
         self,
@@ -569,9 +694,16 @@ impl BodiesDownloaderBuilder {
             stream_batch_size,
             concurrent_requests_range,
             max_buffered_blocks_size_bytes,
+            max_request_attempts,
+            request_timeout,
+            max_results_per_poll,
         } = self;
         let metrics = BodyDownloaderMetrics::default();
-        let in_progress_queue = BodiesRequestQueue::new(metrics.clone());
+        let in_progress_queue = BodiesRequestQueue::new(metrics.clone())
+            .with_max_request_attempts(max_request_attempts)
+            .with_request_timeout(request_timeout)
+            .with_max_results_per_poll(max_results_per_poll)
+            .with_max_concurrent(*concurrent_requests_range.end());
         BodiesDownloader {
             client: Arc::new(client),
             consensus,
@@ -698,6 +830,77 @@ mod tests {
         }
     }
 
+    // Check that all bodies are streamed in order if at most one response is handled per poll.
+    #[tokio::test]
+    async fn streams_bodies_with_max_results_per_poll() {
+        let db = create_test_rw_db();
+        let (headers, mut bodies) = generate_bodies(0..=99);
+
+        insert_headers(&db, &headers);
+
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies.clone()));
+        let mut downloader = BodiesDownloaderBuilder::default()
+            .with_request_limit(10)
+            .with_stream_batch_size(100)
+            .with_max_results_per_poll(1)
+            .build(client.clone(), Arc::new(TestConsensus::default()), db);
+        downloader.set_download_range(0..=99).expect("failed to set download range");
+
+        assert_matches!(
+            downloader.next().await,
+            Some(Ok(res)) => assert_eq!(res, zip_blocks(headers.iter(), &mut bodies))
+        );
+    }
+
+    // Check that blocks below the start of a narrowed download range are skipped.
+    #[tokio::test]
+    async fn skips_blocks_below_narrowed_range() {
+        let db = create_test_rw_db();
+        let (headers, mut bodies) = generate_bodies(0..=99);
+
+        insert_headers(&db, &headers);
+
+        let client = Arc::new(
+            TestBodiesClient::default().with_bodies(bodies.clone()).with_should_delay(true),
+        );
+        let mut downloader = BodiesDownloaderBuilder::default()
+            .with_request_limit(10)
+            .with_stream_batch_size(100)
+            .build(client.clone(), Arc::new(TestConsensus::default()), db);
+        downloader.set_download_range(0..=99).expect("failed to set download range");
+        downloader.set_download_range(50..=99).expect("failed to set download range");
+
+        assert_matches!(
+            downloader.next().await,
+            Some(Ok(res)) => assert_eq!(res, zip_blocks(headers.iter().skip(50), &mut bodies))
+        );
+    }
+
+    // Check that cancelling an in-flight request fails the download.
+    #[tokio::test]
+    async fn cancelled_request_fails_download() {
+        let db = create_test_rw_db();
+        let (headers, bodies) = generate_bodies(0..=19);
+
+        insert_headers(&db, &headers);
+
+        let client =
+            Arc::new(TestBodiesClient::default().with_bodies(bodies).with_should_stall(true));
+        let mut downloader = BodiesDownloaderBuilder::default().build(
+            client,
+            Arc::new(TestConsensus::default()),
+            db,
+        );
+        downloader.set_download_range(0..=19).expect("failed to set download range");
+
+        assert!(futures::poll!(downloader.next()).is_pending());
+        assert_eq!(downloader.cancel_range(10), 1);
+        assert_matches!(
+            downloader.next().await,
+            Some(Err(DownloadError::BodiesRequestCancelled { .. }))
+        );
+    }
+
     // Check that the downloader picks up the new range and downloads bodies after previous range
     // was completed.
     #[tokio::test]
@@ -1,20 +1,34 @@
-use super::request::BodiesRequestFuture;
+use super::request::{BodiesRequestFuture, DEFAULT_MAX_REQUEST_ATTEMPTS};
 use crate::metrics::BodyDownloaderMetrics;
-use futures::{stream::FuturesUnordered, Stream};
-use futures_util::StreamExt;
+use futures::{future::try_join_all, stream::FuturesUnordered, Future, Stream};
+use futures_util::{FutureExt, StreamExt};
 use reth_interfaces::{
     consensus::Consensus,
     p2p::{
         bodies::{client::BodiesClient, response::BlockResponse},
-        error::DownloadResult,
+        error::{DownloadError, DownloadResult},
+        headers::client::HeadersClient,
+        priority::Priority,
     },
 };
-use reth_primitives::{BlockNumber, SealedHeader};
+use reth_primitives::{BlockNumber, SealedHeader, H256};
 use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::RangeInclusive,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
+use tokio_util::sync::CancellationToken;
+
+/// A bodies request that resolves the headers of the requested hashes first, see
+/// [BodiesRequestQueue::push_new_request_by_hashes].
+type BodiesByHashesRequest =
+    Pin<Box<dyn Future<Output = DownloadResult<Vec<BlockResponse>>> + Send + Sync>>;
+
+/// The default window over which [BodiesRequestQueue::throughput] is computed.
+const DEFAULT_THROUGHPUT_WINDOW: Duration = Duration::from_secs(10);
 
 /// The wrapper around [FuturesUnordered] that keeps information
 /// about the blocks currently being requested.
@@ -22,8 +36,29 @@ use std::{
 pub(crate) struct BodiesRequestQueue<B: BodiesClient> {
     /// Inner body request queue.
     inner: FuturesUnordered<BodiesRequestFuture<B>>,
+    /// Prioritized body requests, see [BodiesRequestQueue::push_new_request_prioritized].
+    prioritized: FuturesUnordered<PrioritizedBodiesRequest<B>>,
+    /// Results of prioritized requests, sorted by their first block number.
+    ready_prioritized: VecDeque<(BlockNumber, DownloadResult<Vec<BlockResponse>>)>,
+    /// Body requests by block hash, see [BodiesRequestQueue::push_new_request_by_hashes].
+    by_hashes: FuturesUnordered<BodiesByHashesRequest>,
     /// The downloader metrics.
     metrics: BodyDownloaderMetrics,
+    /// The number of failed attempts after which a request gives up.
+    max_request_attempts: usize,
+    /// How long a single request may take before it's resubmitted, `None` if unlimited.
+    request_timeout: Option<Duration>,
+    /// The maximum number of requests in the queue.
+    max_concurrent: usize,
+    /// Sizes of all successful responses yielded so far.
+    response_size_stats: ResponseSizeStats,
+    /// Completions of successful responses within the throughput window.
+    throughput: Throughput,
+    /// The maximum number of results yielded before the queue yields [Poll::Pending] once,
+    /// `None` if unlimited.
+    max_results_per_poll: Option<usize>,
+    /// The number of results yielded since the queue last returned [Poll::Pending].
+    results_since_pending: usize,
     /// Last requested block number.
     pub(crate) last_requested_block_number: Option<BlockNumber>,
 }
@@ -34,33 +69,259 @@ where
 {
     /// Create new instance of request queue.
     pub(crate) fn new(metrics: BodyDownloaderMetrics) -> Self {
-        Self { metrics, inner: Default::default(), last_requested_block_number: None }
+        Self {
+            metrics,
+            inner: Default::default(),
+            prioritized: Default::default(),
+            ready_prioritized: Default::default(),
+            by_hashes: Default::default(),
+            max_request_attempts: DEFAULT_MAX_REQUEST_ATTEMPTS,
+            request_timeout: None,
+            max_concurrent: usize::MAX,
+            response_size_stats: Default::default(),
+            throughput: Throughput::new(DEFAULT_THROUGHPUT_WINDOW),
+            max_results_per_poll: None,
+            results_since_pending: 0,
+            last_requested_block_number: None,
+        }
+    }
+
+    /// Sets the maximum number of requests in the queue.
+    pub(crate) fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    /// Sets the number of failed attempts after which a request gives up.
+    pub(crate) fn with_max_request_attempts(mut self, max_request_attempts: usize) -> Self {
+        self.max_request_attempts = max_request_attempts;
+        self
+    }
+
+    /// Sets how long a single request may take before it's counted as failed and resubmitted.
+    pub(crate) fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Sets the maximum number of results that are yielded in a row, `None` if unlimited.
+    ///
+    /// Once reached, the queue wakes the task and returns [Poll::Pending] once, so that a caller
+    /// that drains all ready results still gets to its other work.
+    pub(crate) fn with_max_results_per_poll(mut self, max_results_per_poll: Option<usize>) -> Self {
+        self.max_results_per_poll = max_results_per_poll;
+        self
     }
 
     /// Returns `true` if the queue is empty.
     pub(crate) fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+        self.inner.is_empty() &&
+            self.prioritized.is_empty() &&
+            self.ready_prioritized.is_empty() &&
+            self.by_hashes.is_empty()
     }
 
     /// Returns the number of queued requests.
     pub(crate) fn len(&self) -> usize {
-        self.inner.len()
+        self.inner.len() +
+            self.prioritized.len() +
+            self.ready_prioritized.len() +
+            self.by_hashes.len()
+    }
+
+    /// Returns `true` if another request can be pushed into the queue.
+    pub(crate) fn has_capacity(&self) -> bool {
+        self.remaining_capacity() > 0
+    }
+
+    /// Returns how many more requests can be pushed into the queue before it's at capacity.
+    pub(crate) fn remaining_capacity(&self) -> usize {
+        self.max_concurrent.saturating_sub(self.len())
+    }
+
+    /// Returns the aggregated sizes of all successful responses yielded so far.
+    pub(crate) fn response_size_stats(&self) -> &ResponseSizeStats {
+        &self.response_size_stats
+    }
+
+    /// Returns the number of bodies per second yielded within the last throughput window.
+    pub(crate) fn throughput(&self) -> f64 {
+        self.throughput.rate_at(Instant::now())
+    }
+
+    /// Returns the block number ranges of all requests that are currently in flight.
+    pub(crate) fn in_flight_ranges(&self) -> Vec<RangeInclusive<BlockNumber>> {
+        self.inner
+            .iter()
+            .chain(self.prioritized.iter().map(|request| &request.fut))
+            .filter_map(|fut| fut.block_range())
+            .collect()
+    }
+
+    /// Returns how long the oldest in-flight request has been pending, `None` if there is none.
+    ///
+    /// Requests by hash aren't taken into account, see
+    /// [BodiesRequestQueue::push_new_request_by_hashes].
+    pub(crate) fn oldest_request_age(&self) -> Option<Duration> {
+        self.oldest_request_age_at(Instant::now())
+    }
+
+    /// Returns how long the oldest in-flight request has been pending at `now`.
+    fn oldest_request_age_at(&self, now: Instant) -> Option<Duration> {
+        self.inner
+            .iter()
+            .chain(self.prioritized.iter().map(|request| &request.fut))
+            .map(|fut| now.saturating_duration_since(fut.started_at()))
+            .max()
+    }
+
+    /// Drops all in-flight requests whose headers are all below the given block number, for
+    /// example after the pivot moved past them.
+    ///
+    /// Returns the number of dropped requests.
+    pub(crate) fn retain_above(&mut self, block: BlockNumber) -> usize {
+        let is_below = |fut: &BodiesRequestFuture<B>| {
+            fut.max_block_number().map_or(false, |number| number < block)
+        };
+        let len = self.inner.len() + self.prioritized.len();
+        self.inner =
+            std::mem::take(&mut self.inner).into_iter().filter(|fut| !is_below(fut)).collect();
+        self.prioritized = std::mem::take(&mut self.prioritized)
+            .into_iter()
+            .filter(|request| !is_below(&request.fut))
+            .collect();
+        len - self.inner.len() - self.prioritized.len()
+    }
+
+    /// Cancels all in-flight requests whose block range contains the given block number, for
+    /// example because a reorg invalidated it.
+    ///
+    /// The cancelled requests resolve with
+    /// [DownloadError::BodiesRequestCancelled](reth_interfaces::p2p::error::DownloadError::BodiesRequestCancelled).
+    /// Returns the number of cancelled requests.
+    pub(crate) fn cancel_range(&mut self, block: BlockNumber) -> usize {
+        let mut cancelled = 0;
+        for fut in self.inner.iter().chain(self.prioritized.iter().map(|request| &request.fut)) {
+            if fut.block_range().map_or(false, |range| range.contains(&block)) {
+                fut.cancel();
+                cancelled += 1;
+            }
+        }
+        cancelled
     }
 
     /// Clears the inner queue and related data.
-    pub(crate) fn clear(&mut self) {
-        self.inner.clear();
+    ///
+    /// Returns the headers of all requests that haven't been yielded yet, sorted by block number,
+    /// so they can be requested again. Requests by hash are dropped without returning anything.
+    pub(crate) fn clear(&mut self) -> Vec<SealedHeader> {
+        self.by_hashes.clear();
+        let mut headers = std::mem::take(&mut self.inner)
+            .into_iter()
+            .flat_map(BodiesRequestFuture::into_headers)
+            .chain(
+                std::mem::take(&mut self.prioritized)
+                    .into_iter()
+                    .flat_map(|request| request.fut.into_headers()),
+            )
+            .chain(
+                self.ready_prioritized
+                    .drain(..)
+                    .filter_map(|(_, result)| result.ok())
+                    .flatten()
+                    .map(BlockResponse::into_header),
+            )
+            .collect::<Vec<_>>();
+        headers.sort_unstable_by_key(|header| header.number);
         self.last_requested_block_number.take();
+        headers
     }
 
     /// Add new request to the queue.
     /// Expects a sorted list of headers.
+    ///
+    /// Headers that are already part of an in-flight request, see
+    /// [BodiesRequestQueue::in_flight_ranges], are skipped. If this leaves gaps, a separate request
+    /// is added for every contiguous range of the remaining headers. If all headers are skipped, no
+    /// request is added.
+    ///
+    /// Returns the request back if the queue is at capacity, see
+    /// [BodiesRequestQueue::has_capacity]. The requests of a split request are all added, even if
+    /// they exceed the capacity.
     pub(crate) fn push_new_request(
         &mut self,
         client: Arc<B>,
         consensus: Arc<dyn Consensus>,
         request: Vec<SealedHeader>,
-    ) {
+    ) -> Result<(), QueueFull> {
+        if !self.has_capacity() {
+            return Err(QueueFull(request))
+        }
+        for request in self.skip_in_flight(request) {
+            let fut =
+                self.new_request(client.clone(), consensus.clone(), request, Priority::Normal);
+            self.inner.push(fut);
+        }
+        Ok(())
+    }
+
+    /// Add new prioritized request to the queue.
+    /// Expects a sorted list of headers.
+    ///
+    /// The bodies are requested with [Priority::High]. Prioritized requests that complete in the
+    /// same poll are yielded in block number order, before any regular request.
+    pub(crate) fn push_new_request_prioritized(
+        &mut self,
+        client: Arc<B>,
+        consensus: Arc<dyn Consensus>,
+        request: Vec<SealedHeader>,
+    ) -> Result<(), QueueFull> {
+        if !self.has_capacity() {
+            return Err(QueueFull(request))
+        }
+        let first_block_number = request.first().map(|first| first.number).unwrap_or_default();
+        let fut = self.new_request(client, consensus, request, Priority::High);
+        self.prioritized.push(PrioritizedBodiesRequest { first_block_number, fut });
+        Ok(())
+    }
+
+    /// Removes all headers that are part of an in-flight request and splits the remaining headers
+    /// into contiguous ranges.
+    fn skip_in_flight(&self, mut request: Vec<SealedHeader>) -> Vec<Vec<SealedHeader>> {
+        let in_flight = self.in_flight_ranges();
+        let len = request.len();
+        request.retain(|header| !in_flight.iter().any(|range| range.contains(&header.number)));
+        if request.len() < len {
+            tracing::debug!(
+                target: "downloaders::bodies",
+                skipped = len - request.len(),
+                remaining = request.len(),
+                "Skipping headers of overlapping bodies request"
+            );
+        }
+
+        let mut requests: Vec<Vec<SealedHeader>> = Vec::new();
+        for header in request {
+            match requests.last_mut() {
+                Some(last)
+                    if last.last().map_or(false, |prev| prev.number + 1 == header.number) =>
+                {
+                    last.push(header)
+                }
+                _ => requests.push(vec![header]),
+            }
+        }
+        requests
+    }
+
+    /// Creates a new request and updates the last requested block number.
+    fn new_request(
+        &mut self,
+        client: Arc<B>,
+        consensus: Arc<dyn Consensus>,
+        request: Vec<SealedHeader>,
+        priority: Priority,
+    ) -> BodiesRequestFuture<B> {
         // Set last max requested block number
         self.last_requested_block_number = request
             .last()
@@ -69,13 +330,87 @@ where
                 None => last.number,
             })
             .or(self.last_requested_block_number);
-        // Create request and push into the queue.
-        self.inner.push(
-            BodiesRequestFuture::new(client, consensus, self.metrics.clone()).with_headers(request),
-        )
+        BodiesRequestFuture::new(client, consensus, self.metrics.clone())
+            .with_max_attempts(self.max_request_attempts)
+            .with_request_timeout(self.request_timeout)
+            .with_priority(priority)
+            .with_cancellation_token(CancellationToken::new())
+            .with_headers(request)
     }
 }
 
+impl<B> BodiesRequestQueue<B>
+where
+    B: BodiesClient + HeadersClient + 'static,
+{
+    /// Add new request for the bodies of the given block hashes to the queue.
+    ///
+    /// The headers are fetched first, and the bodies are then validated against them like any
+    /// other request. The results are yielded sorted by block number. Since the block numbers
+    /// aren't known upfront, these requests don't update the last requested block number and
+    /// aren't part of [BodiesRequestQueue::in_flight_ranges].
+    ///
+    /// Like a range of headers, the hashes must be of consecutive blocks, otherwise the request
+    /// fails with [DownloadError::InvalidBodyRange].
+    pub(crate) fn push_new_request_by_hashes(
+        &mut self,
+        client: Arc<B>,
+        consensus: Arc<dyn Consensus>,
+        hashes: Vec<H256>,
+    ) -> Result<(), PushByHashesError> {
+        if hashes.is_empty() {
+            return Err(PushByHashesError::Empty)
+        }
+        if !self.has_capacity() {
+            return Err(PushByHashesError::QueueFull(hashes))
+        }
+
+        let metrics = self.metrics.clone();
+        let max_request_attempts = self.max_request_attempts;
+        let request_timeout = self.request_timeout;
+        self.by_hashes.push(Box::pin(async move {
+            let mut headers = fetch_headers(Arc::clone(&client), hashes).await?;
+            headers.sort_unstable_by_key(|header| header.number);
+            if let Some(gap) = headers.windows(2).find(|pair| pair[1].number != pair[0].number + 1)
+            {
+                return Err(DownloadError::InvalidBodyRange { range: gap[0].number..=gap[1].number })
+            }
+            BodiesRequestFuture::new(client, consensus, metrics)
+                .with_max_attempts(max_request_attempts)
+                .with_request_timeout(request_timeout)
+                .with_headers(headers)
+                .await
+        }));
+        Ok(())
+    }
+}
+
+/// Fetches the headers of the given block hashes.
+///
+/// Returns an error if a header is missing or doesn't match its hash.
+async fn fetch_headers<B>(client: Arc<B>, hashes: Vec<H256>) -> DownloadResult<Vec<SealedHeader>>
+where
+    B: HeadersClient,
+{
+    try_join_all(hashes.into_iter().map(|hash| {
+        let request = client.get_header(hash.into());
+        let client = Arc::clone(&client);
+        async move {
+            let (peer_id, header) = request.await?.split();
+            let header = header.ok_or(DownloadError::EmptyResponse)?.seal_slow();
+            if header.hash() != hash {
+                client.report_bad_message(peer_id);
+                return Err(DownloadError::HeaderHashMismatch {
+                    expected: hash,
+                    received: header.hash(),
+                })
+            }
+            Ok(header)
+        }
+    }))
+    .await
+}
+
 impl<B> Stream for BodiesRequestQueue<B>
 where
     B: BodiesClient + 'static,
@@ -83,6 +418,514 @@ where
     type Item = DownloadResult<Vec<BlockResponse>>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.get_mut().inner.poll_next_unpin(cx)
+        let this = self.get_mut();
+
+        if this.max_results_per_poll.map_or(false, |max| this.results_since_pending >= max) {
+            this.results_since_pending = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending
+        }
+
+        // Buffer all prioritized results that are ready, so they can be yielded in order.
+        while let Poll::Ready(Some((number, result))) = this.prioritized.poll_next_unpin(cx) {
+            let idx = this.ready_prioritized.partition_point(|(n, _)| *n <= number);
+            this.ready_prioritized.insert(idx, (number, result));
+        }
+
+        let poll = if let Some((_, result)) = this.ready_prioritized.pop_front() {
+            Poll::Ready(Some(result))
+        } else if let Poll::Ready(Some(result)) = this.by_hashes.poll_next_unpin(cx) {
+            Poll::Ready(Some(result))
+        } else {
+            match this.inner.poll_next_unpin(cx) {
+                // there are still prioritized or by hash requests in progress
+                Poll::Ready(None) if !this.prioritized.is_empty() || !this.by_hashes.is_empty() => {
+                    Poll::Pending
+                }
+                poll => poll,
+            }
+        };
+
+        match &poll {
+            Poll::Ready(Some(result)) => {
+                if let Ok(response) = result {
+                    this.response_size_stats.record(response.iter().map(BlockResponse::size).sum());
+                    this.throughput.record_at(Instant::now(), response.len());
+                }
+                this.results_since_pending += 1;
+            }
+            _ => this.results_since_pending = 0,
+        }
+        poll
+    }
+}
+
+/// Aggregated sizes of the bodies responses received by a
+/// [BodiesDownloader](super::bodies::BodiesDownloader).
+///
+/// See [BlockResponse::size].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseSizeStats {
+    /// The number of recorded responses.
+    pub count: u64,
+    /// The total size of all recorded responses in bytes.
+    pub total_bytes: u64,
+    /// The number of responses per size bucket, keyed by the bucket's upper bound in bytes.
+    ///
+    /// The bucket bounds are powers of two.
+    pub buckets: BTreeMap<u64, u64>,
+}
+
+impl ResponseSizeStats {
+    /// Records the size of a response.
+    fn record(&mut self, size: usize) {
+        let size = size as u64;
+        self.count += 1;
+        self.total_bytes += size;
+        *self.buckets.entry(size.next_power_of_two()).or_default() += 1;
+    }
+}
+
+/// Tracks the number of bodies yielded by a [BodiesRequestQueue] over a sliding window.
+#[derive(Debug)]
+struct Throughput {
+    /// The length of the window.
+    window: Duration,
+    /// When responses completed and how many bodies they contained, oldest first.
+    completions: VecDeque<(Instant, usize)>,
+}
+
+impl Throughput {
+    fn new(window: Duration) -> Self {
+        Self { window, completions: Default::default() }
+    }
+
+    /// Records a response with the given number of bodies that completed at `now`.
+    fn record_at(&mut self, now: Instant, bodies: usize) {
+        while self
+            .completions
+            .front()
+            .map_or(false, |(completed_at, _)| now.duration_since(*completed_at) >= self.window)
+        {
+            self.completions.pop_front();
+        }
+        self.completions.push_back((now, bodies));
+    }
+
+    /// Returns the number of bodies per second that completed within the window before `now`.
+    fn rate_at(&self, now: Instant) -> f64 {
+        let bodies = self
+            .completions
+            .iter()
+            .filter(|(completed_at, _)| now.duration_since(*completed_at) < self.window)
+            .map(|(_, bodies)| bodies)
+            .sum::<usize>();
+        bodies as f64 / self.window.as_secs_f64()
+    }
+}
+
+/// Error returned when pushing a request into a [BodiesRequestQueue] that is at capacity.
+///
+/// Contains the rejected request.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct QueueFull(pub(crate) Vec<SealedHeader>);
+
+/// Error returned when requesting bodies by block hashes, see
+/// [BodiesDownloader::request_bodies_by_hashes](super::bodies::BodiesDownloader::request_bodies_by_hashes).
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushByHashesError {
+    /// No hashes were provided.
+    Empty,
+    /// The queue is at capacity. Contains the rejected hashes.
+    QueueFull(Vec<H256>),
+}
+
+/// A [BodiesRequestFuture] that resolves together with the first block number it requested.
+struct PrioritizedBodiesRequest<B: BodiesClient> {
+    first_block_number: BlockNumber,
+    fut: BodiesRequestFuture<B>,
+}
+
+impl<B> Future for PrioritizedBodiesRequest<B>
+where
+    B: BodiesClient + 'static,
+{
+    type Output = (BlockNumber, DownloadResult<Vec<BlockResponse>>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.fut.poll_unpin(cx).map(|result| (this.first_block_number, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bodies::test_utils::zip_blocks,
+        test_utils::{generate_bodies, TestBodiesClient},
+    };
+    use assert_matches::assert_matches;
+    use reth_interfaces::test_utils::{
+        generators, generators::random_header_range, TestConsensus, TestFullBlockClient,
+    };
+    use std::future::poll_fn;
+
+    #[tokio::test]
+    async fn reports_in_flight_ranges() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        assert!(queue.in_flight_ranges().is_empty());
+
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..5].to_vec()).unwrap();
+        queue.push_new_request(client, consensus, headers[10..].to_vec()).unwrap();
+
+        let mut ranges = queue.in_flight_ranges();
+        ranges.sort_by_key(|range| *range.start());
+        assert_eq!(ranges, vec![0..=4, 10..=19]);
+    }
+
+    /// Check that prioritized requests that are ready at the same time are yielded in block number
+    /// order.
+    #[tokio::test]
+    async fn yields_prioritized_in_block_number_order() {
+        let mut rng = generators::rng();
+        // empty headers resolve immediately
+        let headers = random_header_range(&mut rng, 0..20, H256::zero());
+        let client = Arc::new(TestBodiesClient::default());
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue
+            .push_new_request_prioritized(client.clone(), consensus.clone(), headers[10..].to_vec())
+            .unwrap();
+        queue.push_new_request_prioritized(client, consensus, headers[..10].to_vec()).unwrap();
+        assert_eq!(queue.len(), 2);
+
+        let first = queue.next().await.unwrap().unwrap();
+        assert_eq!(first.first().unwrap().block_number(), 0);
+        // the second result is already buffered
+        assert_eq!(queue.len(), 1);
+        let second = queue.next().await.unwrap().unwrap();
+        assert_eq!(second.first().unwrap().block_number(), 10);
+        assert!(queue.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn clear_returns_in_flight_headers() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[10..].to_vec()).unwrap();
+        queue.push_new_request(client, consensus, headers[..10].to_vec()).unwrap();
+
+        assert_eq!(queue.clear(), headers);
+        assert!(queue.is_empty());
+        assert_eq!(queue.last_requested_block_number, None);
+        assert!(queue.clear().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_beyond_capacity() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue =
+            BodiesRequestQueue::new(BodyDownloaderMetrics::default()).with_max_concurrent(2);
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..5].to_vec()).unwrap();
+        assert!(queue.has_capacity());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[5..10].to_vec()).unwrap();
+        assert!(!queue.has_capacity());
+
+        assert_eq!(
+            queue.push_new_request(client.clone(), consensus.clone(), headers[10..].to_vec()),
+            Err(QueueFull(headers[10..].to_vec()))
+        );
+        assert_eq!(
+            queue.push_new_request_prioritized(client, consensus, headers[10..].to_vec()),
+            Err(QueueFull(headers[10..].to_vec()))
+        );
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.last_requested_block_number, Some(9));
+    }
+
+    #[tokio::test]
+    async fn records_response_sizes() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let expected_size = zip_blocks(headers.iter(), &mut bodies.clone())
+            .iter()
+            .map(BlockResponse::size)
+            .sum::<usize>() as u64;
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        assert_eq!(queue.response_size_stats(), &ResponseSizeStats::default());
+        queue.push_new_request(client, consensus, headers).unwrap();
+
+        assert!(queue.next().await.unwrap().is_ok());
+        let stats = queue.response_size_stats();
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.total_bytes, expected_size);
+        assert_eq!(stats.buckets.get(&expected_size.next_power_of_two()), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn limits_results_per_poll() {
+        let mut rng = generators::rng();
+        // empty headers resolve immediately
+        let headers = random_header_range(&mut rng, 0..20, H256::zero());
+        let client = Arc::new(TestBodiesClient::default());
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default())
+            .with_max_results_per_poll(Some(3));
+        for chunk in headers.chunks(2) {
+            queue.push_new_request(client.clone(), consensus.clone(), chunk.to_vec()).unwrap();
+        }
+
+        // drain all ready results until the queue yields
+        let mut counts = Vec::new();
+        while !queue.is_empty() {
+            let count = poll_fn(|cx| {
+                let mut count = 0;
+                while let Poll::Ready(Some(result)) = queue.poll_next_unpin(cx) {
+                    assert!(result.is_ok());
+                    count += 1;
+                }
+                Poll::Ready(count)
+            })
+            .await;
+            counts.push(count);
+        }
+        assert_eq!(counts, vec![3, 3, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn retains_requests_above_block() {
+        let (headers, bodies) = generate_bodies(0..=29);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..10].to_vec()).unwrap();
+        queue
+            .push_new_request(client.clone(), consensus.clone(), headers[10..20].to_vec())
+            .unwrap();
+        queue.push_new_request_prioritized(client, consensus, headers[20..].to_vec()).unwrap();
+
+        // the second request spans the pivot
+        assert_eq!(queue.retain_above(15), 1);
+        let mut ranges = queue.in_flight_ranges();
+        ranges.sort_by_key(|range| *range.start());
+        assert_eq!(ranges, vec![10..=19, 20..=29]);
+
+        assert_eq!(queue.retain_above(15), 0);
+        assert_eq!(queue.retain_above(30), 2);
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_remaining_capacity() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue =
+            BodiesRequestQueue::new(BodyDownloaderMetrics::default()).with_max_concurrent(3);
+        assert_eq!(queue.remaining_capacity(), 3);
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..10].to_vec()).unwrap();
+        assert_eq!(queue.remaining_capacity(), 2);
+        queue.push_new_request(client, consensus, headers[10..].to_vec()).unwrap();
+        assert_eq!(queue.remaining_capacity(), 1);
+
+        while let Some(result) = queue.next().await {
+            assert!(result.is_ok());
+        }
+        assert_eq!(queue.remaining_capacity(), 3);
+    }
+
+    #[tokio::test]
+    async fn cancels_requests_containing_block() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..10].to_vec()).unwrap();
+        queue.push_new_request(client, consensus, headers[10..].to_vec()).unwrap();
+
+        assert_eq!(queue.cancel_range(5), 1);
+        assert_eq!(queue.cancel_range(20), 0);
+
+        let mut results = vec![queue.next().await.unwrap(), queue.next().await.unwrap()];
+        results.sort_by_key(|result| result.is_ok());
+        assert_matches!(
+            &results[0],
+            Err(DownloadError::BodiesRequestCancelled { range }) if *range == (0..=9)
+        );
+        assert!(results[1].is_ok());
+    }
+
+    #[tokio::test]
+    async fn skips_overlapping_requests() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        // bodies are only served once, so requesting them twice fails
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..10].to_vec()).unwrap();
+        queue.push_new_request(client.clone(), consensus.clone(), headers[5..15].to_vec()).unwrap();
+        // fully overlapping requests aren't added
+        queue.push_new_request(client, consensus, headers[2..8].to_vec()).unwrap();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.last_requested_block_number, Some(14));
+
+        let mut ranges = queue.in_flight_ranges();
+        ranges.sort_by_key(|range| *range.start());
+        assert_eq!(ranges, vec![0..=9, 10..=14]);
+
+        let mut numbers = Vec::new();
+        while let Some(result) = queue.next().await {
+            numbers.extend(result.unwrap().iter().map(BlockResponse::block_number));
+        }
+        numbers.sort_unstable();
+        assert_eq!(numbers, (0..=14).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn splits_requests_around_overlap() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        // bodies are only served once, so requesting them twice fails
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        queue.push_new_request(client.clone(), consensus.clone(), headers[5..10].to_vec()).unwrap();
+        // the in-flight range falls into the middle of the request, so the rest is split in two
+        queue.push_new_request(client, consensus, headers[..15].to_vec()).unwrap();
+        assert_eq!(queue.len(), 3);
+
+        let mut ranges = queue.in_flight_ranges();
+        ranges.sort_by_key(|range| *range.start());
+        assert_eq!(ranges, vec![0..=4, 5..=9, 10..=14]);
+
+        let mut numbers = Vec::new();
+        while let Some(result) = queue.next().await {
+            numbers.extend(result.unwrap().iter().map(BlockResponse::block_number));
+        }
+        numbers.sort_unstable();
+        assert_eq!(numbers, (0..=14).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn reports_oldest_request_age() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        assert_eq!(queue.oldest_request_age(), None);
+
+        let start = Instant::now();
+        queue.push_new_request(client.clone(), consensus.clone(), headers[..10].to_vec()).unwrap();
+        queue.push_new_request_prioritized(client, consensus, headers[10..].to_vec()).unwrap();
+
+        let age = queue.oldest_request_age_at(start + Duration::from_secs(15)).unwrap();
+        assert!(age > Duration::from_secs(14) && age <= Duration::from_secs(15));
+        assert!(queue.oldest_request_age().unwrap() < Duration::from_secs(1));
+
+        while let Some(result) = queue.next().await {
+            assert!(result.is_ok());
+        }
+        assert_eq!(queue.oldest_request_age(), None);
+    }
+
+    #[test]
+    fn computes_throughput_over_window() {
+        let mut throughput = Throughput::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(throughput.rate_at(start), 0.);
+
+        // 10 bodies per second
+        for secs in 0..10 {
+            throughput.record_at(start + Duration::from_secs(secs), 10);
+        }
+        assert_eq!(throughput.rate_at(start + Duration::from_secs(9)), 10.);
+
+        // the first five completions left the window
+        assert_eq!(throughput.rate_at(start + Duration::from_secs(14)), 5.);
+
+        // newer completions replace the old ones
+        throughput.record_at(start + Duration::from_secs(14), 50);
+        assert_eq!(throughput.completions.len(), 6);
+        assert_eq!(throughput.rate_at(start + Duration::from_secs(14)), 10.);
+        assert_eq!(throughput.rate_at(start + Duration::from_secs(30)), 0.);
+    }
+
+    #[tokio::test]
+    async fn records_throughput() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        assert_eq!(queue.throughput(), 0.);
+        queue.push_new_request(client, consensus, headers).unwrap();
+
+        assert!(queue.next().await.unwrap().is_ok());
+        assert_eq!(queue.throughput(), 20. / DEFAULT_THROUGHPUT_WINDOW.as_secs_f64());
+    }
+
+    #[tokio::test]
+    async fn requests_bodies_by_hashes() {
+        let (headers, mut bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestFullBlockClient::default());
+        for header in &headers {
+            client.insert(header.clone(), bodies[&header.hash()].clone());
+        }
+        let consensus = Arc::new(TestConsensus::default());
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        assert_eq!(
+            queue.push_new_request_by_hashes(client.clone(), consensus.clone(), vec![]),
+            Err(PushByHashesError::Empty)
+        );
+
+        let hashes = headers.iter().rev().map(|header| header.hash()).collect::<Vec<_>>();
+        queue.push_new_request_by_hashes(client, consensus, hashes).unwrap();
+        assert_eq!(queue.len(), 1);
+
+        let response = queue.next().await.unwrap().unwrap();
+        assert_eq!(response, zip_blocks(headers.iter(), &mut bodies));
+        assert!(queue.is_empty());
+    }
+
+    /// Check that a request for hashes of blocks that aren't consecutive fails.
+    #[tokio::test]
+    async fn rejects_non_consecutive_hashes() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestFullBlockClient::default());
+        for header in &headers {
+            client.insert(header.clone(), bodies[&header.hash()].clone());
+        }
+
+        let mut queue = BodiesRequestQueue::new(BodyDownloaderMetrics::default());
+        let hashes = vec![headers[3].hash(), headers[5].hash()];
+        queue
+            .push_new_request_by_hashes(client, Arc::new(TestConsensus::default()), hashes)
+            .unwrap();
+
+        assert_matches!(
+            queue.next().await,
+            Some(Err(DownloadError::InvalidBodyRange { range })) => assert_eq!(range, 3..=5)
+        );
     }
 }
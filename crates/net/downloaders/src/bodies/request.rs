@@ -8,13 +8,22 @@ use reth_interfaces::{
         priority::Priority,
     },
 };
-use reth_primitives::{BlockBody, PeerId, SealedBlock, SealedHeader, WithPeerId, H256};
+use reth_primitives::{
+    BlockBody, BlockNumber, PeerId, SealedBlock, SealedHeader, WithPeerId, H256,
+};
 use std::{
     collections::VecDeque,
+    ops::RangeInclusive,
     pin::Pin,
     sync::Arc,
     task::{ready, Context, Poll},
+    time::{Duration, Instant},
 };
+use tokio::time::Sleep;
+use tokio_util::sync::CancellationToken;
+
+/// The default number of failed attempts after which a [BodiesRequestFuture] gives up.
+pub(crate) const DEFAULT_MAX_REQUEST_ATTEMPTS: usize = 1_000;
 
 /// Body request implemented as a [Future].
 ///
@@ -23,12 +32,20 @@ use std::{
 /// will issue another request until all bodies are collected.
 ///
 /// It then proceeds to verify the downloaded bodies. In case of an validation error,
-/// the future will start over.
+/// the future will start over. Once the configured number of attempts failed, the future resolves
+/// with [DownloadError::BodiesAttemptsExhausted].
 ///
 /// The future will filter out any empty headers (see [reth_primitives::Header::is_empty]) from the
 /// request. If [BodiesRequestFuture] was initialized with all empty headers, no request will be
 /// dispatched and they will be immediately returned upon polling.
 ///
+/// If a [CancellationToken] is set and cancelled before the future completed, the future resolves
+/// with [DownloadError::BodiesRequestCancelled].
+///
+/// If a request timeout is set, see [BodiesRequestFuture::with_request_timeout], a request that
+/// isn't answered in time counts as a failed attempt with [DownloadError::Timeout] and is
+/// resubmitted, so that it can be served by another peer.
+///
 /// NB: This assumes that peers respond with bodies in the order that they were requested.
 /// This is a reasonable assumption to make as that's [what Geth
 /// does](https://github.com/ethereum/go-ethereum/blob/f53ff0ff4a68ffc56004ab1d5cc244bcb64d3277/les/server_requests.go#L245).
@@ -41,11 +58,29 @@ pub(crate) struct BodiesRequestFuture<B: BodiesClient> {
     metrics: BodyDownloaderMetrics,
     // Headers to download. The collection is shrunk as responses are buffered.
     pending_headers: VecDeque<SealedHeader>,
+    /// The block number range spanned by the requested headers.
+    block_range: Option<RangeInclusive<BlockNumber>>,
     /// Internal buffer for all blocks
     buffer: Vec<BlockResponse>,
     fut: Option<B::Output>,
     /// Tracks how many bodies we requested in the last request.
     last_request_len: Option<usize>,
+    /// The number of failed attempts after which the future gives up.
+    max_attempts: usize,
+    /// The number of failed attempts so far.
+    failed_attempts: usize,
+    /// The priority of the initial request.
+    priority: Priority,
+    /// When the future was created.
+    started_at: Instant,
+    /// How long a single request may take before it's resubmitted, `None` if unlimited.
+    request_timeout: Option<Duration>,
+    /// Fires once the current request exceeded the `request_timeout`.
+    timeout: Option<Pin<Box<Sleep>>>,
+    /// Aborts the request, see [BodiesRequestFuture::with_cancellation_token].
+    cancellation_token: Option<CancellationToken>,
+    /// Resolves once the `cancellation_token` was cancelled.
+    cancelled: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
 }
 
 impl<B> BodiesRequestFuture<B>
@@ -63,33 +98,123 @@ where
             consensus,
             metrics,
             pending_headers: Default::default(),
+            block_range: None,
             buffer: Default::default(),
             last_request_len: None,
             fut: None,
+            max_attempts: DEFAULT_MAX_REQUEST_ATTEMPTS,
+            failed_attempts: 0,
+            priority: Priority::Normal,
+            started_at: Instant::now(),
+            request_timeout: None,
+            timeout: None,
+            cancellation_token: None,
+            cancelled: None,
+        }
+    }
+
+    /// Sets the token that aborts the request once cancelled.
+    pub(crate) fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        let cancelled = token.clone();
+        self.cancelled = Some(Box::pin(async move { cancelled.cancelled().await }));
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Cancels the request, if a [CancellationToken] is set.
+    pub(crate) fn cancel(&self) {
+        if let Some(token) = &self.cancellation_token {
+            token.cancel();
         }
     }
 
+    /// Sets the number of failed attempts after which the future gives up.
+    pub(crate) fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets how long a single request may take before it's counted as failed and resubmitted.
+    ///
+    /// This needs to be set before [BodiesRequestFuture::with_headers] submits the request.
+    pub(crate) fn with_request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// Sets the priority of the initial request.
+    ///
+    /// This needs to be set before [BodiesRequestFuture::with_headers] submits the request.
+    pub(crate) fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub(crate) fn with_headers(mut self, headers: Vec<SealedHeader>) -> Self {
         self.buffer.reserve_exact(headers.len());
+        self.block_range =
+            headers.first().zip(headers.last()).map(|(first, last)| first.number..=last.number);
         self.pending_headers = VecDeque::from(headers);
         // Submit the request only if there are any headers to download.
         // Otherwise, the future will immediately be resolved.
         if let Some(req) = self.next_request() {
-            self.submit_request(req, Priority::Normal);
+            self.submit_request(req, self.priority);
         }
         self
     }
 
-    fn on_error(&mut self, error: DownloadError, peer_id: Option<PeerId>) {
+    /// Returns the block number range spanned by the requested headers, if any.
+    pub(crate) fn block_range(&self) -> Option<RangeInclusive<BlockNumber>> {
+        self.block_range.clone()
+    }
+
+    /// Returns when the future was created.
+    pub(crate) fn started_at(&self) -> Instant {
+        self.started_at
+    }
+
+    /// Returns the highest block number of the requested headers, if any.
+    pub(crate) fn max_block_number(&self) -> Option<BlockNumber> {
+        self.block_range.as_ref().map(|range| *range.end())
+    }
+
+    /// Consumes the future and returns all of its headers, including the ones whose bodies were
+    /// already downloaded.
+    pub(crate) fn into_headers(self) -> Vec<SealedHeader> {
+        self.buffer
+            .into_iter()
+            .map(BlockResponse::into_header)
+            .chain(self.pending_headers)
+            .collect()
+    }
+
+    /// Handles a failed attempt and resubmits the request.
+    ///
+    /// Returns an error if there are no attempts left.
+    fn on_error(&mut self, error: DownloadError, peer_id: Option<PeerId>) -> DownloadResult<()> {
         self.metrics.increment_errors(&error);
         tracing::debug!(target: "downloaders::bodies", ?peer_id, %error, "Error requesting bodies");
         if let Some(peer_id) = peer_id {
             self.client.report_bad_message(peer_id);
         }
+
+        self.failed_attempts += 1;
+        if self.failed_attempts >= self.max_attempts {
+            self.fut = None;
+            let start = self.pending_headers.front().map(|h| h.number).unwrap_or_default();
+            let end = self.pending_headers.back().map(|h| h.number).unwrap_or_default();
+            return Err(DownloadError::BodiesAttemptsExhausted {
+                range: start..=end,
+                attempts: self.failed_attempts,
+            })
+        }
+
+        self.metrics.retries.increment(1);
         self.submit_request(
             self.next_request().expect("existing hashes to resubmit"),
             Priority::High,
         );
+        Ok(())
     }
 
     /// Retrieve header hashes for the next request.
@@ -104,6 +229,7 @@ where
         tracing::trace!(target: "downloaders::bodies", request_len = req.len(), "Requesting bodies");
         let client = Arc::clone(&self.client);
         self.last_request_len = Some(req.len());
+        self.timeout = self.request_timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout)));
         self.fut = Some(client.get_block_bodies_with_priority(req, priority));
     }
 
@@ -135,7 +261,7 @@ where
         }
 
         // Buffer block responses
-        self.try_buffer_blocks(bodies)?;
+        self.try_buffer_blocks(peer_id, bodies)?;
 
         // Submit next request if any
         if let Some(req) = self.next_request() {
@@ -152,7 +278,7 @@ where
     ///
     /// This method removes headers from the internal collection.
     /// If the response fails validation, then the header will be put back.
-    fn try_buffer_blocks(&mut self, bodies: Vec<BlockBody>) -> DownloadResult<()> {
+    fn try_buffer_blocks(&mut self, peer_id: PeerId, bodies: Vec<BlockBody>) -> DownloadResult<()> {
         let mut bodies = bodies.into_iter().peekable();
 
         while bodies.peek().is_some() {
@@ -175,8 +301,10 @@ where
                 if let Err(error) = self.consensus.validate_block(&block) {
                     // Body is invalid, put the header back and return an error
                     let hash = block.hash();
+                    let number = block.header.number;
+                    tracing::debug!(target: "downloaders::bodies", ?peer_id, number, ?hash, ?error, "Received invalid body");
                     self.pending_headers.push_front(block.header);
-                    return Err(DownloadError::BodyValidation { hash, error })
+                    return Err(DownloadError::BlockBodyValidation { hash, number, error })
                 }
 
                 self.buffer.push(BlockResponse::Full(block));
@@ -198,7 +326,32 @@ where
 
         loop {
             if this.pending_headers.is_empty() {
-                return Poll::Ready(Ok(std::mem::take(&mut this.buffer)))
+                let response = std::mem::take(&mut this.buffer);
+                let size = response.iter().map(BlockResponse::size).sum::<usize>();
+                this.metrics
+                    .request_duration_seconds
+                    .record(this.started_at.elapsed().as_secs_f64());
+                this.metrics.response_size_bytes.record(size as f64);
+                return Poll::Ready(Ok(response))
+            }
+
+            if this
+                .cancelled
+                .as_mut()
+                .map_or(false, |cancelled| cancelled.poll_unpin(cx).is_ready())
+            {
+                let range = this.block_range.clone().expect("headers are pending");
+                return Poll::Ready(Err(DownloadError::BodiesRequestCancelled { range }))
+            }
+
+            if this.fut.is_some() &&
+                this.timeout.as_mut().map_or(false, |timeout| timeout.poll_unpin(cx).is_ready())
+            {
+                tracing::debug!(target: "downloaders::bodies", request_len = ?this.last_request_len, "Bodies request timed out");
+                if let Err(error) = this.on_error(DownloadError::Timeout, None) {
+                    return Poll::Ready(Err(error))
+                }
+                continue
             }
 
             // Check if there is a pending requests. It might not exist if all
@@ -208,7 +361,9 @@ where
                     Ok(response) => {
                         let peer_id = response.peer_id();
                         if let Err(error) = this.on_block_response(response) {
-                            this.on_error(error, Some(peer_id));
+                            if let Err(error) = this.on_error(error, Some(peer_id)) {
+                                return Poll::Ready(Err(error))
+                            }
                         }
                     }
                     Err(error) => {
@@ -216,7 +371,9 @@ where
                             return Poll::Ready(Err(error.into()))
                         }
 
-                        this.on_error(error.into(), None);
+                        if let Err(error) = this.on_error(error.into(), None) {
+                            return Poll::Ready(Err(error))
+                        }
                     }
                 }
             }
@@ -237,12 +394,17 @@ mod tests {
         bodies::test_utils::zip_blocks,
         test_utils::{generate_bodies, TestBodiesClient},
     };
+    use assert_matches::assert_matches;
     use reth_interfaces::{
         p2p::bodies::response::BlockResponse,
         test_utils::{generators, generators::random_header_range, TestConsensus},
     };
+    use reth_metrics::metrics::Counter;
     use reth_primitives::H256;
-    use std::sync::Arc;
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
 
     /// Check if future returns empty bodies without dispathing any requests.
     #[tokio::test]
@@ -289,4 +451,137 @@ mod tests {
             (headers.into_iter().filter(|h| !h.is_empty()).count() as u64 + 1) / 2
         );
     }
+
+    /// Check that the request future gives up once all attempts failed.
+    #[tokio::test]
+    async fn request_fails_after_max_attempts() {
+        // Generate some random blocks
+        let (headers, bodies) = generate_bodies(0..=19);
+
+        let max_attempts = 3;
+        let client =
+            Arc::new(TestBodiesClient::default().with_bodies(bodies).with_keep_bodies(true));
+        let consensus = Arc::new(TestConsensus::default());
+        consensus.set_fail_validation(true);
+        let fut =
+            BodiesRequestFuture::new(client.clone(), consensus, BodyDownloaderMetrics::default())
+                .with_max_attempts(max_attempts)
+                .with_headers(headers.clone());
+
+        // the first non-empty header fails validation
+        let first_invalid = headers.iter().find(|h| !h.is_empty()).unwrap().number;
+        assert_matches!(
+            fut.await,
+            Err(DownloadError::BodiesAttemptsExhausted { range, attempts }) => {
+                assert_eq!(range, first_invalid..=headers.last().unwrap().number);
+                assert_eq!(attempts, max_attempts);
+            }
+        );
+        assert_eq!(client.times_requested(), max_attempts as u64);
+    }
+
+    /// Check that a cancelled request resolves with an error, even though it's already in flight.
+    #[tokio::test]
+    async fn request_resolves_cancelled() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client = Arc::new(TestBodiesClient::default().with_bodies(bodies));
+        let token = CancellationToken::new();
+        let fut = BodiesRequestFuture::new(
+            client.clone(),
+            Arc::new(TestConsensus::default()),
+            BodyDownloaderMetrics::default(),
+        )
+        .with_cancellation_token(token.clone())
+        .with_headers(headers.clone());
+
+        token.cancel();
+        assert_matches!(
+            fut.await,
+            Err(DownloadError::BodiesRequestCancelled { range }) if range == (0..=19)
+        );
+
+        // the request can be cancelled through the future as well
+        let fut = BodiesRequestFuture::new(
+            client,
+            Arc::new(TestConsensus::default()),
+            BodyDownloaderMetrics::default(),
+        )
+        .with_cancellation_token(CancellationToken::new())
+        .with_headers(headers);
+        fut.cancel();
+        assert_matches!(fut.await, Err(DownloadError::BodiesRequestCancelled { .. }));
+    }
+
+    /// Check that a body that fails validation is reported with its block number.
+    #[tokio::test]
+    async fn request_reports_invalid_body() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let consensus = Arc::new(TestConsensus::default());
+        consensus.set_fail_validation(true);
+        let mut fut = BodiesRequestFuture::new(
+            Arc::new(TestBodiesClient::default()),
+            consensus,
+            BodyDownloaderMetrics::default(),
+        );
+        fut.pending_headers = headers.iter().cloned().collect();
+
+        let response = headers
+            .iter()
+            .filter(|h| !h.is_empty())
+            .map(|h| bodies[&h.hash()].clone())
+            .collect::<Vec<_>>();
+        let invalid = headers.iter().find(|h| !h.is_empty()).unwrap();
+        assert_matches!(
+            fut.try_buffer_blocks(PeerId::random(), response),
+            Err(DownloadError::BlockBodyValidation { hash, number, .. }) => {
+                assert_eq!(hash, invalid.hash());
+                assert_eq!(number, invalid.number);
+            }
+        );
+        // the invalid header is put back
+        assert_eq!(fut.pending_headers.front(), Some(invalid));
+    }
+
+    /// Check that a request that isn't answered in time is resubmitted until it gives up.
+    #[tokio::test]
+    async fn request_times_out() {
+        let (headers, bodies) = generate_bodies(0..=19);
+        let client =
+            Arc::new(TestBodiesClient::default().with_bodies(bodies).with_should_stall(true));
+        let request_timeout = Duration::from_millis(20);
+
+        let start = Instant::now();
+        let fut = BodiesRequestFuture::new(
+            client.clone(),
+            Arc::new(TestConsensus::default()),
+            BodyDownloaderMetrics::default(),
+        )
+        .with_max_attempts(2)
+        .with_request_timeout(Some(request_timeout))
+        .with_headers(headers);
+
+        assert_matches!(fut.await, Err(DownloadError::BodiesAttemptsExhausted { attempts: 2, .. }));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= 2 * request_timeout && elapsed < 10 * request_timeout);
+        assert_eq!(client.times_requested(), 2);
+    }
+
+    /// Check that a request that fails once is counted as a single retry.
+    #[tokio::test]
+    async fn request_records_retries() {
+        let (headers, mut bodies) = generate_bodies(0..=19);
+        let client = Arc::new(
+            TestBodiesClient::default().with_bodies(bodies.clone()).with_failed_requests(1),
+        );
+        let retries = Arc::new(AtomicU64::new(0));
+        let mut metrics = BodyDownloaderMetrics::default();
+        metrics.retries = Counter::from_arc(retries.clone());
+        let fut =
+            BodiesRequestFuture::new(client.clone(), Arc::new(TestConsensus::default()), metrics)
+                .with_headers(headers.clone());
+
+        assert_eq!(fut.await.unwrap(), zip_blocks(headers.iter(), &mut bodies));
+        assert_eq!(client.times_requested(), 2);
+        assert_eq!(retries.load(Ordering::Relaxed), 1);
+    }
 }
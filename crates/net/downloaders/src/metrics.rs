@@ -1,6 +1,6 @@
 use reth_interfaces::p2p::error::DownloadError;
 use reth_metrics::{
-    metrics::{self, Counter, Gauge},
+    metrics::{self, Counter, Gauge, Histogram},
     Metrics,
 };
 
@@ -49,6 +49,12 @@ pub struct BodyDownloaderMetrics {
     pub validation_errors: Counter,
     /// Number of unexpected errors while requesting items
     pub unexpected_errors: Counter,
+    /// Number of requests that were resubmitted after a failed attempt
+    pub retries: Counter,
+    /// The time it took to complete a request (can contain more than 1 item) in seconds.
+    pub request_duration_seconds: Histogram,
+    /// The size of the response to a completed request (can contain more than 1 item) in bytes.
+    pub response_size_bytes: Histogram,
 }
 
 impl BodyDownloaderMetrics {
@@ -56,7 +62,9 @@ impl BodyDownloaderMetrics {
     pub fn increment_errors(&self, error: &DownloadError) {
         match error {
             DownloadError::Timeout => self.timeout_errors.increment(1),
-            DownloadError::BodyValidation { .. } => self.validation_errors.increment(1),
+            DownloadError::BodyValidation { .. } | DownloadError::BlockBodyValidation { .. } => {
+                self.validation_errors.increment(1)
+            }
             _error => self.unexpected_errors.increment(1),
         }
     }
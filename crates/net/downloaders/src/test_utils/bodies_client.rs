@@ -1,6 +1,7 @@
 use reth_interfaces::p2p::{
     bodies::client::{BodiesClient, BodiesFut},
     download::DownloadClient,
+    error::RequestError,
     priority::Priority,
 };
 use reth_primitives::{BlockBody, PeerId, H256};
@@ -20,8 +21,11 @@ use tokio::sync::Mutex;
 pub struct TestBodiesClient {
     bodies: Arc<Mutex<HashMap<H256, BlockBody>>>,
     should_delay: bool,
+    should_stall: bool,
     max_batch_size: Option<usize>,
+    keep_bodies: bool,
     times_requested: AtomicU64,
+    failed_requests: AtomicU64,
 }
 
 impl TestBodiesClient {
@@ -35,11 +39,29 @@ impl TestBodiesClient {
         self
     }
 
+    /// Never respond to any request.
+    pub(crate) fn with_should_stall(mut self, should_stall: bool) -> Self {
+        self.should_stall = should_stall;
+        self
+    }
+
     pub(crate) fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
         self.max_batch_size = Some(max_batch_size);
         self
     }
 
+    /// Keep serving the bodies after they were returned, e.g. for retried requests.
+    pub(crate) fn with_keep_bodies(mut self, keep_bodies: bool) -> Self {
+        self.keep_bodies = keep_bodies;
+        self
+    }
+
+    /// Fail the given number of requests with [RequestError::Timeout] before serving any bodies.
+    pub(crate) fn with_failed_requests(self, failed_requests: u64) -> Self {
+        self.failed_requests.store(failed_requests, Ordering::Relaxed);
+        self
+    }
+
     pub(crate) fn times_requested(&self) -> u64 {
         self.times_requested.load(Ordering::Relaxed)
     }
@@ -64,12 +86,25 @@ impl BodiesClient for TestBodiesClient {
         _priority: Priority,
     ) -> Self::Output {
         let should_delay = self.should_delay;
+        let should_stall = self.should_stall;
         let bodies = self.bodies.clone();
         let max_batch_size = self.max_batch_size;
+        let keep_bodies = self.keep_bodies;
 
         self.times_requested.fetch_add(1, Ordering::Relaxed);
+        let should_fail = self
+            .failed_requests
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok();
 
         Box::pin(async move {
+            if should_fail {
+                return Err(RequestError::Timeout)
+            }
+            if should_stall {
+                std::future::pending::<()>().await;
+            }
+
             if should_delay {
                 tokio::time::sleep(Duration::from_millis(hashes[0].to_low_u64_be() % 100)).await;
             }
@@ -81,8 +116,7 @@ impl BodiesClient for TestBodiesClient {
                     .into_iter()
                     .take(max_batch_size.unwrap_or(usize::MAX))
                     .map(|hash| {
-                        bodies
-                            .remove(&hash)
+                        if keep_bodies { bodies.get(&hash).cloned() } else { bodies.remove(&hash) }
                             .expect("Downloader asked for a block it should not ask for")
                     })
                     .collect(),
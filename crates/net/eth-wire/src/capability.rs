@@ -70,6 +70,12 @@ impl Capability {
     pub fn is_eth_v68(&self) -> bool {
         self.name == "eth" && self.version == 68
     }
+
+    /// Whether this is any version of the `snap` protocol.
+    #[inline]
+    pub fn is_snap(&self) -> bool {
+        self.name == "snap"
+    }
 }
 
 #[cfg(any(test, feature = "arbitrary"))]
@@ -142,6 +148,12 @@ impl Capabilities {
     pub fn supports_eth_v68(&self) -> bool {
         self.eth_68
     }
+
+    /// Whether the peer supports the `snap` sub-protocol.
+    #[inline]
+    pub fn supports_snap(&self) -> bool {
+        self.inner.iter().any(Capability::is_snap)
+    }
 }
 
 impl From<Vec<Capability>> for Capabilities {
@@ -21,7 +21,7 @@
 use async_trait::async_trait;
 use reth_eth_wire::DisconnectReason;
 use reth_primitives::{NodeRecord, PeerId};
-use reth_rpc_types::NetworkStatus;
+use reth_rpc_types::{ConnectedPeer, NetworkStatus};
 use std::net::SocketAddr;
 
 pub use error::NetworkError;
@@ -60,6 +60,13 @@ pub trait PeersInfo: Send + Sync {
 
     /// Returns the Ethereum Node Record of the node.
     fn local_node_record(&self) -> NodeRecord;
+
+    /// Returns all established sessions with remote peers.
+    ///
+    /// By default, no sessions are returned.
+    fn connected_peers(&self) -> Vec<ConnectedPeer> {
+        Vec::new()
+    }
 }
 
 /// Provides an API for managing the peers of the network.
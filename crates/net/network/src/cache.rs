@@ -9,12 +9,14 @@ use std::{borrow::Borrow, hash::Hash, num::NonZeroUsize};
 pub struct LruCache<T: Hash + Eq> {
     limit: NonZeroUsize,
     inner: LinkedHashSet<T>,
+    /// Number of entries that were evicted to enforce the limit.
+    evictions: u64,
 }
 
 impl<T: Hash + Eq> LruCache<T> {
     /// Creates a new `LruCache` using the given limit
     pub fn new(limit: NonZeroUsize) -> Self {
-        Self { inner: LinkedHashSet::new(), limit }
+        Self { inner: LinkedHashSet::new(), limit, evictions: 0 }
     }
 
     /// Insert an element into the set.
@@ -41,7 +43,14 @@ impl<T: Hash + Eq> LruCache<T> {
     /// If the `LruCache` is empty this will return None.
     #[inline]
     fn remove_lru(&mut self) {
-        self.inner.pop_front();
+        if self.inner.pop_front().is_some() {
+            self.evictions += 1;
+        }
+    }
+
+    /// Returns the number of entries that were evicted because an insert exceeded the limit.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
     }
 
     /// Returns `true` if the set contains a value.
@@ -100,6 +109,17 @@ mod test {
         assert!(!cache.contains(old_entry));
     }
 
+    #[test]
+    fn test_cache_should_count_evictions() {
+        let limit = NonZeroUsize::new(3).unwrap();
+        let mut cache = LruCache::new(limit);
+        cache.extend(["a", "b"]);
+        assert_eq!(cache.evictions(), 0);
+
+        cache.extend(["c", "d", "d"]);
+        assert_eq!(cache.evictions(), 2);
+    }
+
     #[test]
     fn test_cache_should_extend_an_array() {
         let limit = NonZeroUsize::new(5).unwrap();
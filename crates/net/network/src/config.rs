@@ -2,15 +2,21 @@
 
 use crate::{
     error::NetworkError,
+    fetch::{DefaultPeerSelector, PeerSelector, DEFAULT_MAX_CONCURRENT_REQUESTS_PER_PEER},
     import::{BlockImport, ProofOfStakeBlockImport},
-    peers::PeersConfig,
+    peers::{PeerBlacklist, PeersConfig, ReputationDecay},
     session::SessionsConfig,
+    state::{
+        BlockRequestRateLimit, HealthThresholds, PeerCountWatermarks, StateActionHandler,
+        SubnetLimits, BLOCK_SOURCE_CACHE_LIMIT, PEER_BLOCK_CACHE_LIMIT,
+        PEER_TRANSACTION_HASH_CACHE_LIMIT,
+    },
     NetworkHandle, NetworkManager,
 };
 use reth_discv4::{Discv4Config, Discv4ConfigBuilder, DEFAULT_DISCOVERY_PORT};
 use reth_dns_discovery::DnsDiscoveryConfig;
 use reth_ecies::util::pk2id;
-use reth_eth_wire::{HelloMessage, Status};
+use reth_eth_wire::{EthVersion, HelloMessage, Status};
 use reth_primitives::{
     mainnet_nodes, sepolia_nodes, ChainSpec, ForkFilter, Head, NodeRecord, PeerId, MAINNET,
 };
@@ -21,6 +27,7 @@ use std::{
     collections::HashSet,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     sync::Arc,
+    time::Duration,
 };
 // re-export for convenience
 pub use secp256k1::SecretKey;
@@ -64,6 +71,8 @@ pub struct NetworkConfig<C> {
     pub fork_filter: ForkFilter,
     /// The block importer type.
     pub block_import: Box<dyn BlockImport>,
+    /// Selects the peer for a block request if the assigned peer is no longer available.
+    pub peer_selector: Box<dyn PeerSelector>,
     /// The default mode of the network.
     pub network_mode: NetworkMode,
     /// The executor to use for spawning tasks.
@@ -72,6 +81,65 @@ pub struct NetworkConfig<C> {
     pub status: Status,
     /// Sets the hello message for the p2p handshake in RLPx
     pub hello_message: HelloMessage,
+    /// How many peers a new block should be propagated to via `NewBlock`.
+    pub propagation_fraction: BlockPropagationMode,
+    /// Whether a new block is propagated to the peers with the lowest response latency first.
+    pub prefer_low_latency_propagation: bool,
+    /// If set, a new block is propagated to the peers whose best block is at most this many
+    /// blocks away from the new block first.
+    pub propagation_proximity_window: Option<u64>,
+    /// How many known blocks to keep track of for a single peer.
+    pub peer_block_cache_limit: usize,
+    /// How many announced transaction hashes to keep track of for a single peer.
+    pub peer_transaction_hash_cache_limit: usize,
+    /// How many block requests a single peer handles at most at the same time.
+    pub max_concurrent_requests_per_peer: usize,
+    /// The minimum `eth` version a peer must support, sessions of other peers are disconnected.
+    pub min_eth_version: EthVersion,
+    /// Thresholds of the number of active peers that are reported when crossed.
+    pub peer_count_watermarks: Option<PeerCountWatermarks>,
+    /// Periodic decay of the peer reputations toward the default reputation, if any.
+    pub reputation_decay: Option<ReputationDecay>,
+    /// How far beyond the best known block a block announced via `NewBlock` may be, if limited.
+    pub max_new_block_gap: Option<u64>,
+    /// Soft cap of the network state's buffered actions, above which discovered nodes are
+    /// dropped, if any.
+    pub max_queued_messages: Option<usize>,
+    /// Peers and ip ranges that are ignored when found through discovery.
+    pub blacklist: PeerBlacklist,
+    /// Whether peers that announce an incompatible [`ForkId`](reth_primitives::ForkId) via
+    /// discovery are banned in the discovery service.
+    pub ban_incompatible_fork_peers: bool,
+    /// Thresholds that classify the health of the network.
+    pub health_thresholds: HealthThresholds,
+    /// Number of bad responses after which a peer is disconnected, if limited.
+    pub max_bad_responses: Option<usize>,
+    /// Caps of the number of peers connected per subnet, if any.
+    pub subnet_limits: Option<SubnetLimits>,
+    /// Number of protocol violations after which a peer is banned from discovery, if limited.
+    pub max_protocol_violations: Option<usize>,
+    /// Limits the rate of block requests sent to each peer, if set.
+    pub block_request_rate_limit: Option<BlockRequestRateLimit>,
+    /// The estimated response bytes a peer may owe before further block requests are deferred,
+    /// if limited.
+    pub max_outstanding_response_bytes: Option<u64>,
+    /// Duration without any response or announcement after which a peer is disconnected, if any.
+    pub idle_peer_timeout: Option<Duration>,
+    /// Number of peers on an incompatible fork after which their ip is banned, if limited.
+    pub incompatible_fork_ip_ban_threshold: Option<usize>,
+    /// Encoded size above which new blocks are only announced by hash, if limited.
+    pub max_new_block_size: Option<usize>,
+    /// Number of failed block request dispatches after which a stalled fetcher is reported, if
+    /// any.
+    pub fetch_stall_threshold: Option<usize>,
+    /// The only peers new blocks are announced to, if restricted.
+    pub announce_whitelist: Option<HashSet<PeerId>>,
+    /// How many blocks to remember the first announcing peer of.
+    pub block_source_cache_limit: usize,
+    /// Observes every action of the network state, if set.
+    pub state_action_handler: Option<StateActionHandler>,
+    /// The minimum number of peers a new block is announced to, if that many peers are connected.
+    pub min_propagate: usize,
 }
 
 // === impl NetworkConfig ===
@@ -149,6 +217,66 @@ pub struct NetworkConfigBuilder {
     hello_message: Option<HelloMessage>,
     /// Head used to start set for the fork filter and status.
     head: Option<Head>,
+    /// How many peers a new block should be propagated to via `NewBlock`.
+    propagation_fraction: BlockPropagationMode,
+    /// Whether a new block is propagated to the peers with the lowest response latency first.
+    prefer_low_latency_propagation: bool,
+    /// If set, a new block is propagated to the peers whose best block is at most this many
+    /// blocks away from the new block first.
+    propagation_proximity_window: Option<u64>,
+    /// How many known blocks to keep track of for a single peer.
+    peer_block_cache_limit: usize,
+    /// How many announced transaction hashes to keep track of for a single peer.
+    peer_transaction_hash_cache_limit: usize,
+    /// How many block requests a single peer handles at most at the same time.
+    max_concurrent_requests_per_peer: usize,
+    /// The minimum `eth` version a peer must support.
+    min_eth_version: EthVersion,
+    /// Thresholds of the number of active peers that are reported when crossed.
+    peer_count_watermarks: Option<PeerCountWatermarks>,
+    /// Periodic decay of the peer reputations toward the default reputation, if any.
+    reputation_decay: Option<ReputationDecay>,
+    /// How far beyond the best known block a block announced via `NewBlock` may be, if limited.
+    max_new_block_gap: Option<u64>,
+    /// Soft cap of the network state's buffered actions, if any.
+    max_queued_messages: Option<usize>,
+    /// Peers and ip ranges that are ignored when found through discovery.
+    #[serde(skip)]
+    blacklist: PeerBlacklist,
+    /// Whether peers that announce an incompatible fork id via discovery are banned.
+    ban_incompatible_fork_peers: bool,
+    /// Thresholds that classify the health of the network.
+    health_thresholds: HealthThresholds,
+    /// Number of bad responses after which a peer is disconnected, if limited.
+    max_bad_responses: Option<usize>,
+    /// Caps of the number of peers connected per subnet, if any.
+    subnet_limits: Option<SubnetLimits>,
+    /// Number of protocol violations after which a peer is banned from discovery, if limited.
+    max_protocol_violations: Option<usize>,
+    /// Limits the rate of block requests sent to each peer, if set.
+    block_request_rate_limit: Option<BlockRequestRateLimit>,
+    /// The estimated response bytes a peer may owe before further block requests are deferred,
+    /// if limited.
+    max_outstanding_response_bytes: Option<u64>,
+    /// Duration without any response or announcement after which a peer is disconnected, if any.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    idle_peer_timeout: Option<Duration>,
+    /// Number of peers on an incompatible fork after which their ip is banned, if limited.
+    incompatible_fork_ip_ban_threshold: Option<usize>,
+    /// Encoded size above which new blocks are only announced by hash, if limited.
+    max_new_block_size: Option<usize>,
+    /// Number of failed block request dispatches after which a stalled fetcher is reported, if
+    /// any.
+    fetch_stall_threshold: Option<usize>,
+    /// The only peers new blocks are announced to, if restricted.
+    announce_whitelist: Option<HashSet<PeerId>>,
+    /// How many blocks to remember the first announcing peer of.
+    block_source_cache_limit: usize,
+    /// Observes every action of the network state, if set.
+    #[serde(skip)]
+    state_action_handler: Option<StateActionHandler>,
+    /// The minimum number of peers a new block is announced to, if that many peers are connected.
+    min_propagate: usize,
 }
 
 // === impl NetworkConfigBuilder ===
@@ -170,6 +298,33 @@ impl NetworkConfigBuilder {
             executor: None,
             hello_message: None,
             head: None,
+            propagation_fraction: Default::default(),
+            prefer_low_latency_propagation: false,
+            propagation_proximity_window: None,
+            peer_block_cache_limit: PEER_BLOCK_CACHE_LIMIT,
+            peer_transaction_hash_cache_limit: PEER_TRANSACTION_HASH_CACHE_LIMIT,
+            max_concurrent_requests_per_peer: DEFAULT_MAX_CONCURRENT_REQUESTS_PER_PEER,
+            min_eth_version: EthVersion::Eth66,
+            peer_count_watermarks: None,
+            reputation_decay: None,
+            max_new_block_gap: None,
+            max_queued_messages: None,
+            blacklist: Default::default(),
+            ban_incompatible_fork_peers: false,
+            health_thresholds: Default::default(),
+            max_bad_responses: None,
+            subnet_limits: None,
+            max_protocol_violations: None,
+            block_request_rate_limit: None,
+            max_outstanding_response_bytes: None,
+            idle_peer_timeout: None,
+            incompatible_fork_ip_ban_threshold: None,
+            max_new_block_size: None,
+            fetch_stall_threshold: None,
+            announce_whitelist: None,
+            block_source_cache_limit: BLOCK_SOURCE_CACHE_LIMIT,
+            state_action_handler: None,
+            min_propagate: 0,
         }
     }
 
@@ -217,6 +372,220 @@ impl NetworkConfigBuilder {
         self
     }
 
+    /// Sets the [`BlockPropagationMode`] that determines how many peers receive a full `NewBlock`
+    /// message when a new block is announced.
+    pub fn propagation_fraction(mut self, mode: BlockPropagationMode) -> Self {
+        self.propagation_fraction = mode;
+        self
+    }
+
+    /// Sets whether the peers that receive a full `NewBlock` message are the ones with the lowest
+    /// recorded response latency, instead of randomly selected peers.
+    ///
+    /// Peers without a recorded latency are treated as having the median latency.
+    pub fn prefer_low_latency_propagation(mut self, prefer: bool) -> Self {
+        self.prefer_low_latency_propagation = prefer;
+        self
+    }
+
+    /// Sets the window of block numbers around a new block in which peers are preferred as
+    /// receivers of the full `NewBlock` message.
+    ///
+    /// Peers that are close to the new block relay it quickly. Peers whose best block is unknown
+    /// are treated as being outside the window.
+    pub fn propagation_proximity_window(mut self, window: u64) -> Self {
+        self.propagation_proximity_window = Some(window);
+        self
+    }
+
+    /// Sets how many known blocks are tracked per peer.
+    ///
+    /// A limit of `0` is not allowed and falls back to the default.
+    pub fn peer_block_cache_limit(mut self, limit: usize) -> Self {
+        self.peer_block_cache_limit = limit;
+        self
+    }
+
+    /// Sets how many announced transaction hashes are tracked per peer.
+    ///
+    /// A limit of `0` is not allowed and falls back to the default.
+    pub fn peer_transaction_hash_cache_limit(mut self, limit: usize) -> Self {
+        self.peer_transaction_hash_cache_limit = limit;
+        self
+    }
+
+    /// Sets how many block requests a single peer handles at most at the same time.
+    ///
+    /// A maximum of `0` is not allowed and is treated as `1`.
+    pub fn max_concurrent_requests_per_peer(mut self, max: usize) -> Self {
+        self.max_concurrent_requests_per_peer = max;
+        self
+    }
+
+    /// Sets the minimum `eth` version a peer must support.
+    ///
+    /// Sessions of peers that only support older versions are disconnected.
+    pub fn min_eth_version(mut self, version: EthVersion) -> Self {
+        self.min_eth_version = version;
+        self
+    }
+
+    /// Sets the thresholds of the number of active peers.
+    ///
+    /// Dropping below `low` active peers and recovering above `high` active peers afterwards is
+    /// reported once each.
+    pub fn peer_count_watermarks(mut self, low: usize, high: usize) -> Self {
+        self.peer_count_watermarks = Some(PeerCountWatermarks { low, high });
+        self
+    }
+
+    /// Moves the reputation of all peers toward the default reputation by `amount` every
+    /// `interval`, so that peers recover from one-off penalties.
+    pub fn reputation_decay(mut self, interval: Duration, amount: i32) -> Self {
+        self.reputation_decay = Some(ReputationDecay { interval, amount });
+        self
+    }
+
+    /// Sets how far beyond the best known block a block announced via `NewBlock` may be.
+    ///
+    /// The best known block is the highest of our head and the best blocks of the other peers, so
+    /// peers announcing the tip aren't penalized while syncing. Announcements of blocks further
+    /// ahead are ignored and the peer is penalized.
+    pub fn max_new_block_gap(mut self, max_gap: u64) -> Self {
+        self.max_new_block_gap = Some(max_gap);
+        self
+    }
+
+    /// Sets the soft cap of the network state's buffered actions.
+    ///
+    /// Once exceeded, actions derived from discovery, like newly discovered nodes, are dropped.
+    /// Disconnects and other session related actions are never dropped.
+    pub fn max_queued_messages(mut self, max: usize) -> Self {
+        self.max_queued_messages = Some(max);
+        self
+    }
+
+    /// Sets the [`PeerBlacklist`] of peers and ip ranges that are ignored when found through
+    /// discovery, see also [`PeerBlacklist::from_file`].
+    pub fn blacklist(mut self, blacklist: PeerBlacklist) -> Self {
+        self.blacklist = blacklist;
+        self
+    }
+
+    /// Sets whether peers that announce an incompatible fork id via discovery are banned in the
+    /// discovery service.
+    pub fn ban_incompatible_fork_peers(mut self, ban: bool) -> Self {
+        self.ban_incompatible_fork_peers = ban;
+        self
+    }
+
+    /// Sets the [`HealthThresholds`] that classify the health of the network.
+    pub fn health_thresholds(mut self, thresholds: HealthThresholds) -> Self {
+        self.health_thresholds = thresholds;
+        self
+    }
+
+    /// Disconnects peers as useless once they sent `max` bad responses to block requests.
+    pub fn max_bad_responses(mut self, max: usize) -> Self {
+        self.max_bad_responses = Some(max);
+        self
+    }
+
+    /// Limits the number of peers connected per `/24` IPv4 and `/64` IPv6 subnet.
+    ///
+    /// Outgoing connections to peers that would exceed the limit of their subnet are skipped.
+    pub fn subnet_limits(mut self, limits: SubnetLimits) -> Self {
+        self.subnet_limits = Some(limits);
+        self
+    }
+
+    /// Bans peers from discovery once their sessions reported `max` protocol violations, like
+    /// malformed messages.
+    pub fn max_protocol_violations(mut self, max: usize) -> Self {
+        self.max_protocol_violations = Some(max);
+        self
+    }
+
+    /// Limits the rate of block requests sent to each peer.
+    ///
+    /// Requests exceeding the limit are deferred until the limit permits them.
+    pub fn block_request_rate_limit(mut self, limit: BlockRequestRateLimit) -> Self {
+        self.block_request_rate_limit = Some(limit);
+        self
+    }
+
+    /// Limits the estimated response bytes a peer may owe.
+    ///
+    /// Block requests that would make a peer owe more than `max` bytes are deferred until enough
+    /// of its responses arrived. A request is always sent to a peer that owes nothing, so requests
+    /// larger than `max` still make progress.
+    pub fn max_outstanding_response_bytes(mut self, max: u64) -> Self {
+        self.max_outstanding_response_bytes = Some(max);
+        self
+    }
+
+    /// Disconnects peers that neither responded to a request nor announced anything for the
+    /// given duration.
+    ///
+    /// Trusted peers are never disconnected for being idle.
+    pub fn idle_peer_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_peer_timeout = Some(timeout);
+        self
+    }
+
+    /// Bans the ip address of peers in the discovery service once the given number of peers with
+    /// that ip announced an incompatible fork id via discovery.
+    pub fn incompatible_fork_ip_ban_threshold(mut self, threshold: usize) -> Self {
+        self.incompatible_fork_ip_ban_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the encoded size of a `NewBlock` message above which a new block is only announced
+    /// via `NewBlockHashes`, so that large blocks don't flood the peers.
+    pub fn max_new_block_size(mut self, max_size: usize) -> Self {
+        self.max_new_block_size = Some(max_size);
+        self
+    }
+
+    /// Reports a stalled fetcher once block requests couldn't be dispatched the given number of
+    /// times in a row, because no peer was available.
+    pub fn fetch_stall_threshold(mut self, threshold: usize) -> Self {
+        self.fetch_stall_threshold = Some(threshold);
+        self
+    }
+
+    /// Restricts the announcements of new blocks to the given peers, for example in private
+    /// networks.
+    pub fn announce_whitelist(mut self, peers: HashSet<PeerId>) -> Self {
+        self.announce_whitelist = Some(peers);
+        self
+    }
+
+    /// Sets how many blocks to remember the peer that first announced them for.
+    pub fn block_source_cache_limit(mut self, limit: usize) -> Self {
+        self.block_source_cache_limit = limit;
+        self
+    }
+
+    /// Registers a handler that observes every [`StateAction`](crate::StateAction) of the network
+    /// state, for example for custom telemetry.
+    ///
+    /// The handler runs on its own task, see [`StateActionHandler`].
+    pub fn state_action_handler(mut self, handler: StateActionHandler) -> Self {
+        self.state_action_handler = Some(handler);
+        self
+    }
+
+    /// Sets the minimum number of peers a new block is announced to.
+    ///
+    /// This raises the number of peers selected by the
+    /// [`propagation_fraction`](Self::propagation_fraction), capped by the number of connected
+    /// peers.
+    pub fn min_propagate(mut self, min_propagate: usize) -> Self {
+        self.min_propagate = min_propagate;
+        self
+    }
+
     /// Set a custom peer config for how peers are handled
     pub fn peer_config(mut self, config: PeersConfig) -> Self {
         self.peers_config = Some(config);
@@ -365,6 +734,33 @@ impl NetworkConfigBuilder {
             executor,
             hello_message,
             head,
+            propagation_fraction,
+            prefer_low_latency_propagation,
+            propagation_proximity_window,
+            peer_block_cache_limit,
+            peer_transaction_hash_cache_limit,
+            max_concurrent_requests_per_peer,
+            min_eth_version,
+            peer_count_watermarks,
+            reputation_decay,
+            max_new_block_gap,
+            max_queued_messages,
+            blacklist,
+            ban_incompatible_fork_peers,
+            health_thresholds,
+            max_bad_responses,
+            subnet_limits,
+            max_protocol_violations,
+            block_request_rate_limit,
+            max_outstanding_response_bytes,
+            idle_peer_timeout,
+            incompatible_fork_ip_ban_threshold,
+            max_new_block_size,
+            fetch_stall_threshold,
+            announce_whitelist,
+            block_source_cache_limit,
+            state_action_handler,
+            min_propagate,
         } = self;
 
         let listener_addr = listener_addr.unwrap_or_else(|| {
@@ -414,11 +810,39 @@ impl NetworkConfigBuilder {
             sessions_config: sessions_config.unwrap_or_default(),
             chain_spec,
             block_import: Box::<ProofOfStakeBlockImport>::default(),
+            peer_selector: Box::<DefaultPeerSelector>::default(),
             network_mode,
             executor: executor.unwrap_or_else(|| Box::<TokioTaskExecutor>::default()),
             status,
             hello_message,
             fork_filter,
+            propagation_fraction,
+            prefer_low_latency_propagation,
+            propagation_proximity_window,
+            peer_block_cache_limit,
+            peer_transaction_hash_cache_limit,
+            max_concurrent_requests_per_peer,
+            min_eth_version,
+            peer_count_watermarks,
+            reputation_decay,
+            max_new_block_gap,
+            max_queued_messages,
+            blacklist,
+            ban_incompatible_fork_peers,
+            health_thresholds,
+            max_bad_responses,
+            subnet_limits,
+            max_protocol_violations,
+            block_request_rate_limit,
+            max_outstanding_response_bytes,
+            idle_peer_timeout,
+            incompatible_fork_ip_ban_threshold,
+            max_new_block_size,
+            fetch_stall_threshold,
+            announce_whitelist,
+            block_source_cache_limit,
+            state_action_handler,
+            min_propagate,
         }
     }
 }
@@ -447,6 +871,41 @@ impl NetworkMode {
     }
 }
 
+/// Determines to how many peers a new block is propagated via a full `NewBlock` message.
+///
+/// All remaining peers that haven't seen the block are notified via `NewBlockHashes`.
+///
+/// See also <https://github.com/ethereum/devp2p/blob/master/caps/eth.md#block-propagation>
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlockPropagationMode {
+    /// Propagate to the square root of the number of active peers, plus one.
+    #[default]
+    Sqrt,
+    /// Propagate to a fixed number of peers.
+    FixedCount(usize),
+    /// Propagate to a fraction of the active peers.
+    ///
+    /// The fraction is clamped to `[0.0, 1.0]`.
+    Fraction(f64),
+}
+
+// === impl BlockPropagationMode ===
+
+impl BlockPropagationMode {
+    /// Returns the number of peers a `NewBlock` message should be sent to, given the number of
+    /// active peers.
+    pub fn num_propagate(&self, num_active_peers: usize) -> usize {
+        match *self {
+            BlockPropagationMode::Sqrt => (num_active_peers as f64).sqrt() as usize + 1,
+            BlockPropagationMode::FixedCount(count) => count,
+            BlockPropagationMode::Fraction(fraction) => {
+                (num_active_peers as f64 * fraction.clamp(0.0, 1.0)).ceil() as usize
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -499,4 +958,44 @@ mod tests {
         assert_eq!(status.forkid.hash, genesis_fork_hash);
         assert_eq!(fork_filter.current().hash, genesis_fork_hash);
     }
+
+    #[test]
+    fn test_propagation_sqrt() {
+        let mode = BlockPropagationMode::Sqrt;
+        assert_eq!(mode.num_propagate(0), 1);
+        assert_eq!(mode.num_propagate(1), 2);
+        assert_eq!(mode.num_propagate(16), 5);
+        assert_eq!(mode.num_propagate(100), 11);
+    }
+
+    #[test]
+    fn test_propagation_fixed_count() {
+        let mode = BlockPropagationMode::FixedCount(8);
+        assert_eq!(mode.num_propagate(0), 8);
+        assert_eq!(mode.num_propagate(1), 8);
+        assert_eq!(mode.num_propagate(16), 8);
+        assert_eq!(mode.num_propagate(100), 8);
+    }
+
+    #[test]
+    fn test_propagation_fraction() {
+        let mode = BlockPropagationMode::Fraction(0.5);
+        assert_eq!(mode.num_propagate(0), 0);
+        assert_eq!(mode.num_propagate(1), 1);
+        assert_eq!(mode.num_propagate(16), 8);
+        assert_eq!(mode.num_propagate(100), 50);
+
+        // fractions are clamped to `[0.0, 1.0]`
+        let mode = BlockPropagationMode::Fraction(2.0);
+        assert_eq!(mode.num_propagate(0), 0);
+        assert_eq!(mode.num_propagate(1), 1);
+        assert_eq!(mode.num_propagate(16), 16);
+        assert_eq!(mode.num_propagate(100), 100);
+
+        let mode = BlockPropagationMode::Fraction(-1.0);
+        assert_eq!(mode.num_propagate(0), 0);
+        assert_eq!(mode.num_propagate(1), 0);
+        assert_eq!(mode.num_propagate(16), 0);
+        assert_eq!(mode.num_propagate(100), 0);
+    }
 }
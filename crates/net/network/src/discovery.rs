@@ -119,6 +119,20 @@ impl Discovery {
         }
     }
 
+    /// Lifts the ban of the [`PeerId`] in the discovery service.
+    pub(crate) fn unban_peer(&self, peer_id: PeerId) {
+        if let Some(discv4) = &self.discv4 {
+            discv4.unban_node(peer_id)
+        }
+    }
+
+    /// Lifts the ban of the [`IpAddr`] in the discovery service.
+    pub(crate) fn unban_ip(&self, ip: IpAddr) {
+        if let Some(discv4) = &self.discv4 {
+            discv4.unban_ip(ip)
+        }
+    }
+
     /// Returns the id with which the local identifies itself in the network
     pub(crate) fn local_id(&self) -> PeerId {
         self.local_enr.id
@@ -221,6 +235,12 @@ impl Discovery {
             _dns_disc_service: None,
         }
     }
+
+    /// Returns a Discovery instance that yields the given events, in order, and does nothing
+    /// otherwise.
+    pub(crate) fn from_events(events: Vec<DiscoveryEvent>) -> Self {
+        Self { queued_events: events.into(), ..Self::noop() }
+    }
 }
 
 /// Events produced by the [`Discovery`] manager.
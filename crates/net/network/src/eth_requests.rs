@@ -34,13 +34,13 @@ const MAX_BODIES_SERVE: usize = 1024;
 
 /// Estimated size in bytes of an RLP encoded body.
 // TODO: check 24kb blocksize assumption
-const APPROX_BODY_SIZE: usize = 24 * 1024;
+pub(crate) const APPROX_BODY_SIZE: usize = 24 * 1024;
 
 /// Maximum size of replies to data retrievals.
 const SOFT_RESPONSE_LIMIT: usize = 2 * 1024 * 1024;
 
 /// Estimated size in bytes of an RLP encoded header.
-const APPROX_HEADER_SIZE: usize = 500;
+pub(crate) const APPROX_HEADER_SIZE: usize = 500;
 
 /// Manages eth related requests on top of the p2p network.
 ///
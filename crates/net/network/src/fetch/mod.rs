@@ -20,9 +20,15 @@ use std::{
 };
 use tokio::sync::{mpsc, mpsc::UnboundedSender, oneshot};
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::trace;
 
 mod client;
+mod selector;
 pub use client::FetchClient;
+pub use selector::{DefaultPeerSelector, PeerSelector, RoundRobinPeerSelector};
+
+/// The default number of requests a peer handles concurrently.
+pub(crate) const DEFAULT_MAX_CONCURRENT_REQUESTS_PER_PEER: usize = 1;
 
 /// Manages data fetching operations.
 ///
@@ -31,14 +37,18 @@ pub use client::FetchClient;
 ///
 /// This type maintains a list of connected peers that are available for requests.
 pub struct StateFetcher {
-    /// Currently active [`GetBlockHeaders`] requests
+    /// Currently active [`GetBlockHeaders`] requests, by request id
     inflight_headers_requests:
-        HashMap<PeerId, Request<HeadersRequest, PeerRequestResult<Vec<Header>>>>,
-    /// Currently active [`GetBlockBodies`] requests
+        HashMap<u64, Request<HeadersRequest, PeerRequestResult<Vec<Header>>>>,
+    /// Currently active [`GetBlockBodies`] requests, by request id
     inflight_bodies_requests:
-        HashMap<PeerId, Request<Vec<H256>, PeerRequestResult<Vec<BlockBody>>>>,
+        HashMap<u64, Request<BodiesRequest, PeerRequestResult<Vec<BlockBody>>>>,
+    /// The id of the next request sent to a peer.
+    next_request_id: u64,
     /// The list of _available_ peers for requests.
     peers: HashMap<PeerId, Peer>,
+    /// The number of requests a peer handles at most at the same time.
+    max_concurrent_requests_per_peer: usize,
     /// The handle to the peers manager
     peers_handle: PeersHandle,
     /// Number of active peer sessions the node's currently handling.
@@ -59,7 +69,9 @@ impl StateFetcher {
         Self {
             inflight_headers_requests: Default::default(),
             inflight_bodies_requests: Default::default(),
+            next_request_id: 0,
             peers: Default::default(),
+            max_concurrent_requests_per_peer: DEFAULT_MAX_CONCURRENT_REQUESTS_PER_PEER,
             peers_handle,
             num_active_peers,
             queued_requests: Default::default(),
@@ -68,16 +80,36 @@ impl StateFetcher {
         }
     }
 
+    /// Sets the number of requests a peer handles at most at the same time.
+    ///
+    /// A maximum of `0` is treated as `1`.
+    pub(crate) fn set_max_concurrent_requests_per_peer(&mut self, max: usize) {
+        self.max_concurrent_requests_per_peer = max.max(1);
+    }
+
     /// Invoked when connected to a new peer.
+    ///
+    /// The `best_number` is `None` if the number of the peer's best block is unknown, in which case
+    /// the peer's best header is requested before it's assigned a headers request by number.
     pub(crate) fn new_active_peer(
         &mut self,
         peer_id: PeerId,
         best_hash: H256,
-        best_number: u64,
+        best_number: Option<u64>,
         timeout: Arc<AtomicU64>,
     ) {
-        self.peers
-            .insert(peer_id, Peer { state: PeerState::Idle, best_hash, best_number, timeout });
+        self.peers.insert(
+            peer_id,
+            Peer {
+                state: PeerState::Idle,
+                inflight: 0,
+                best_hash,
+                best_number,
+                best_header_requested: false,
+                best_header_request: None,
+                timeout,
+            },
+        );
     }
 
     /// Removes the peer from the peer list, after which it is no longer available for future
@@ -85,13 +117,13 @@ impl StateFetcher {
     ///
     /// Invoked when an active session was closed.
     ///
-    /// This cancels also inflight request and sends an error to the receiver.
+    /// This cancels also all inflight requests of the peer and sends an error to the receivers.
     pub(crate) fn on_session_closed(&mut self, peer: &PeerId) {
         self.peers.remove(peer);
-        if let Some(req) = self.inflight_headers_requests.remove(peer) {
+        for req in take_peer_requests(&mut self.inflight_headers_requests, peer) {
             let _ = req.response.send(Err(RequestError::ConnectionDropped));
         }
-        if let Some(req) = self.inflight_bodies_requests.remove(peer) {
+        for req in take_peer_requests(&mut self.inflight_bodies_requests, peer) {
             let _ = req.response.send(Err(RequestError::ConnectionDropped));
         }
     }
@@ -101,15 +133,146 @@ impl StateFetcher {
     /// Returns `true` if this a newer block
     pub(crate) fn update_peer_block(&mut self, peer_id: &PeerId, hash: H256, number: u64) -> bool {
         if let Some(peer) = self.peers.get_mut(peer_id) {
-            if number > peer.best_number {
+            if Some(number) > peer.best_number {
                 peer.best_hash = hash;
-                peer.best_number = number;
+                peer.best_number = Some(number);
                 return true
             }
         }
         false
     }
 
+    /// Returns the best block hash and number tracked for the peer, if the peer is known.
+    ///
+    /// The number is `None` if it is not known yet.
+    pub(crate) fn peer_best_block(&self, peer_id: &PeerId) -> Option<(H256, Option<u64>)> {
+        self.peers.get(peer_id).map(|peer| (peer.best_hash, peer.best_number))
+    }
+
+    /// Returns the number of inflight `GetBlockHeaders` requests.
+    pub(crate) fn num_inflight_headers_requests(&self) -> usize {
+        self.inflight_headers_requests.len()
+    }
+
+    /// Returns the number of inflight `GetBlockBodies` requests.
+    pub(crate) fn num_inflight_bodies_requests(&self) -> usize {
+        self.inflight_bodies_requests.len()
+    }
+
+    /// Returns the number of requests that wait for an idle peer.
+    pub(crate) fn num_queued_requests(&self) -> usize {
+        self.queued_requests.len()
+    }
+
+    /// Returns `true` if there are queued requests but no idle peer to dispatch them to.
+    pub(crate) fn is_stalled(&self) -> bool {
+        let max = self.max_concurrent_requests_per_peer;
+        !self.queued_requests.is_empty() && !self.peers.values().any(|peer| peer.is_idle(max))
+    }
+
+    /// Returns `true` if the peer is known and ready to accept a request, because it handles fewer
+    /// than the maximum number of concurrent requests.
+    pub(crate) fn is_idle(&self, peer_id: &PeerId) -> bool {
+        let max = self.max_concurrent_requests_per_peer;
+        self.peers.get(peer_id).map(|peer| peer.is_idle(max)).unwrap_or_default()
+    }
+
+    /// Moves the inflight request with the given id to peer `to`.
+    ///
+    /// Returns `false` if `to` is not idle or there's no inflight request with the id.
+    pub(crate) fn reassign_request(&mut self, request_id: u64, to: PeerId) -> bool {
+        if !self.is_idle(&to) {
+            return false
+        }
+
+        let from = if let Some(req) = self.inflight_headers_requests.get_mut(&request_id) {
+            std::mem::replace(&mut req.peer_id, to)
+        } else if let Some(req) = self.inflight_bodies_requests.get_mut(&request_id) {
+            std::mem::replace(&mut req.peer_id, to)
+        } else {
+            return false
+        };
+
+        self.on_request_finished(&from);
+        self.on_request_started(&to);
+        true
+    }
+
+    /// Fails the inflight request with the given id, for example because the peer it was assigned
+    /// to is no longer available.
+    ///
+    /// Bodies that partial responses already returned are still sent.
+    pub(crate) fn on_request_failed(&mut self, request_id: u64, err: RequestError) {
+        let peer_id = if let Some(req) = self.inflight_headers_requests.remove(&request_id) {
+            let _ = req.response.send(Err(err));
+            req.peer_id
+        } else if let Some(req) = self.inflight_bodies_requests.remove(&request_id) {
+            let peer_id = req.peer_id;
+            req.respond(peer_id, Err(err));
+            peer_id
+        } else {
+            return
+        };
+        self.on_request_finished(&peer_id);
+    }
+
+    /// Returns the inflight block requests of the peer, ordered by their ids.
+    pub(crate) fn inflight_block_requests(&self, peer_id: &PeerId) -> Vec<(u64, BlockRequest)> {
+        let headers =
+            self.inflight_headers_requests.iter().filter(|(_, req)| req.peer_id == *peer_id).map(
+                |(id, req)| {
+                    let HeadersRequest { start, limit, direction } = req.request.clone();
+                    let request = GetBlockHeaders { start_block: start, limit, skip: 0, direction };
+                    (*id, BlockRequest::GetBlockHeaders(request))
+                },
+            );
+        let bodies =
+            self.inflight_bodies_requests.iter().filter(|(_, req)| req.peer_id == *peer_id).map(
+                |(id, req)| {
+                    (*id, BlockRequest::GetBlockBodies(GetBlockBodies(req.request.hashes.clone())))
+                },
+            );
+        let mut requests = headers.chain(bodies).collect::<Vec<_>>();
+        requests.sort_unstable_by_key(|(id, _)| *id);
+        requests
+    }
+
+    /// Returns the number of requests the peer still has to respond to.
+    fn num_inflight_requests(&self, peer_id: &PeerId) -> usize {
+        self.inflight_headers_requests.values().filter(|req| req.peer_id == *peer_id).count() +
+            self.inflight_bodies_requests.values().filter(|req| req.peer_id == *peer_id).count() +
+            self.peers.get(peer_id).map_or(0, |peer| peer.best_header_request.is_some() as usize)
+    }
+
+    /// Counts a new inflight request of the peer.
+    fn on_request_started(&mut self, peer_id: &PeerId) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.inflight += 1;
+        }
+    }
+
+    /// Updates the number of inflight requests of the peer once it responded to a request, and
+    /// resets its state once the request for its best header is answered.
+    ///
+    /// Returns `true` if the peer is ready for another request.
+    fn on_request_finished(&mut self, peer_id: &PeerId) -> bool {
+        let inflight = self.num_inflight_requests(peer_id);
+        let max = self.max_concurrent_requests_per_peer;
+        let Some(peer) = self.peers.get_mut(peer_id) else { return false };
+        peer.inflight = inflight;
+        if peer.best_header_request.is_none() {
+            peer.state.on_request_finished();
+        }
+        peer.is_idle(max)
+    }
+
+    /// Returns the id for the next request sent to a peer.
+    fn next_request_id(&mut self) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        id
+    }
+
     /// Invoked when an active session is about to be disconnected.
     pub(crate) fn on_pending_disconnect(&mut self, peer_id: &PeerId) {
         if let Some(peer) = self.peers.get_mut(peer_id) {
@@ -118,13 +281,26 @@ impl StateFetcher {
     }
 
     /// Returns the _next_ idle peer that's ready to accept a request,
-    /// prioritizing those with the lowest timeout/latency.
+    /// prioritizing those with a known best block number, then the fewest inflight requests and
+    /// then the lowest timeout/latency.
     /// Once a peer has been yielded, it will be moved to the end of the map
     fn next_peer(&mut self) -> Option<PeerId> {
+        let max = self.max_concurrent_requests_per_peer;
         self.peers
             .iter()
-            .filter(|(_, peer)| peer.state.is_idle())
-            .min_by_key(|(_, peer)| peer.timeout())
+            .filter(|(_, peer)| peer.is_idle(max))
+            .min_by_key(|(_, peer)| (peer.best_number.is_none(), peer.inflight, peer.timeout()))
+            .map(|(id, _)| *id)
+    }
+
+    /// Returns the idle peer with the lowest timeout, like [`Self::next_peer`], but never the given
+    /// peer.
+    fn next_peer_except(&self, except: &PeerId) -> Option<PeerId> {
+        let max = self.max_concurrent_requests_per_peer;
+        self.peers
+            .iter()
+            .filter(|(id, peer)| *id != except && peer.is_idle(max))
+            .min_by_key(|(_, peer)| (peer.best_number.is_none(), peer.inflight, peer.timeout()))
             .map(|(id, _)| *id)
     }
 
@@ -137,14 +313,44 @@ impl StateFetcher {
 
         let Some(peer_id) = self.next_peer() else { return PollAction::NoPeersAvailable };
 
+        if let Some((request_id, request)) = self.best_header_request(peer_id) {
+            return PollAction::Ready(FetchAction::BlockRequest { peer_id, request_id, request })
+        }
+
         let request = self.queued_requests.pop_front().expect("not empty; qed");
-        let request = self.prepare_block_request(peer_id, request);
+        let (request_id, request) = self.prepare_block_request(peer_id, request);
 
-        PollAction::Ready(FetchAction::BlockRequest { peer_id, request })
+        PollAction::Ready(FetchAction::BlockRequest { peer_id, request_id, request })
+    }
+
+    /// Removes the first inflight request whose receiver was dropped, so that the peer is
+    /// available again.
+    ///
+    /// Returns the peer that handled the request and the id of the request.
+    fn remove_cancelled_request(&mut self) -> Option<(PeerId, u64)> {
+        let (request_id, peer_id) =
+            if let Some(request_id) = find_cancelled(&self.inflight_headers_requests) {
+                let req = self.inflight_headers_requests.remove(&request_id).expect("exists");
+                (request_id, req.peer_id)
+            } else {
+                let request_id = find_cancelled(&self.inflight_bodies_requests)?;
+                let req = self.inflight_bodies_requests.remove(&request_id).expect("exists");
+                (request_id, req.peer_id)
+            };
+
+        trace!(target: "net", ?peer_id, request_id, "Inflight request cancelled, receiver dropped");
+        self.on_request_finished(&peer_id);
+        Some((peer_id, request_id))
     }
 
     /// Advance the state the syncer
     pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<FetchAction> {
+        // requests nobody waits for anymore don't need to be sent
+        self.queued_requests.retain(|req| !req.is_cancelled());
+        if let Some((peer_id, request_id)) = self.remove_cancelled_request() {
+            return Poll::Ready(FetchAction::RequestCancelled { peer_id, request_id })
+        }
+
         // drain buffered actions first
         loop {
             let no_peers_available = match self.poll_action() {
@@ -184,19 +390,80 @@ impl StateFetcher {
         }
     }
 
+    /// Returns a request for the best header of the peer if the next queued request is a range
+    /// request by number and the number of the peer's best block is unknown.
+    ///
+    /// The best header is only requested once per peer.
+    ///
+    /// Caution: this assumes the peer is idle
+    fn best_header_request(&mut self, peer_id: PeerId) -> Option<(u64, BlockRequest)> {
+        let is_range_by_number = matches!(
+            self.queued_requests.front(),
+            Some(DownloadRequest::GetBlockHeaders { request, .. }) if request.start.as_number().is_some()
+        );
+        if !is_range_by_number {
+            return None
+        }
+
+        let peer = self.peers.get(&peer_id)?;
+        if peer.best_number.is_some() || peer.best_header_requested {
+            return None
+        }
+        let request_id = self.next_request_id();
+        let peer = self.peers.get_mut(&peer_id).expect("exists");
+        peer.best_header_requested = true;
+        peer.best_header_request = Some(request_id);
+        peer.state = PeerState::GetBestHeader;
+        peer.inflight += 1;
+
+        let request = BlockRequest::GetBlockHeaders(GetBlockHeaders {
+            start_block: peer.best_hash.into(),
+            limit: 1,
+            skip: 0,
+            direction: Default::default(),
+        });
+        Some((request_id, request))
+    }
+
+    /// Called on the response to the request issued by [`Self::best_header_request`].
+    ///
+    /// Tracks the number of the peer's best block if the peer returned its best header.
+    fn on_best_header_response(
+        &mut self,
+        peer_id: PeerId,
+        res: RequestResult<Vec<Header>>,
+    ) -> Option<BlockResponseOutcome> {
+        let peer = self.peers.get_mut(&peer_id)?;
+        peer.best_header_request = None;
+        if let Some(header) = res.ok().and_then(|headers| headers.into_iter().next()) {
+            if header.hash_slow() == peer.best_hash {
+                peer.best_number = Some(header.number);
+            }
+        }
+
+        if self.on_request_finished(&peer_id) {
+            return self.followup_request(peer_id)
+        }
+        None
+    }
+
     /// Handles a new request to a peer.
     ///
+    /// Returns the request and the id its response is tracked under.
+    ///
     /// Caution: this assumes the peer exists and is idle
-    fn prepare_block_request(&mut self, peer_id: PeerId, req: DownloadRequest) -> BlockRequest {
-        // update the peer's state
-        if let Some(peer) = self.peers.get_mut(&peer_id) {
-            peer.state = req.peer_state();
-        }
+    pub(crate) fn prepare_block_request(
+        &mut self,
+        peer_id: PeerId,
+        req: DownloadRequest,
+    ) -> (u64, BlockRequest) {
+        self.on_request_started(&peer_id);
 
-        match req {
+        let request_id = self.next_request_id();
+        let request = match req {
             DownloadRequest::GetBlockHeaders { request, response, .. } => {
-                let inflight = Request { request: request.clone(), response };
-                self.inflight_headers_requests.insert(peer_id, inflight);
+                let inflight = Request { peer_id, request: request.clone(), response };
+                self.inflight_headers_requests.insert(request_id, inflight);
                 let HeadersRequest { start, limit, direction } = request;
                 BlockRequest::GetBlockHeaders(GetBlockHeaders {
                     start_block: start,
@@ -206,11 +473,16 @@ impl StateFetcher {
                 })
             }
             DownloadRequest::GetBlockBodies { request, response, .. } => {
-                let inflight = Request { request: request.clone(), response };
-                self.inflight_bodies_requests.insert(peer_id, inflight);
+                let inflight = Request {
+                    peer_id,
+                    request: BodiesRequest { hashes: request.clone(), received: Vec::new() },
+                    response,
+                };
+                self.inflight_bodies_requests.insert(request_id, inflight);
                 BlockRequest::GetBlockBodies(GetBlockBodies(request))
             }
-        }
+        };
+        (request_id, request)
     }
 
     /// Returns a new followup request for the peer.
@@ -218,11 +490,11 @@ impl StateFetcher {
     /// Caution: this expects that the peer is _not_ closed.
     fn followup_request(&mut self, peer_id: PeerId) -> Option<BlockResponseOutcome> {
         let req = self.queued_requests.pop_front()?;
-        let req = self.prepare_block_request(peer_id, req);
-        Some(BlockResponseOutcome::Request(peer_id, req))
+        let (request_id, req) = self.prepare_block_request(peer_id, req);
+        Some(BlockResponseOutcome::Request(peer_id, request_id, req))
     }
 
-    /// Called on a `GetBlockHeaders` response from a peer.
+    /// Called on a `GetBlockHeaders` response from a peer to the request with the given id.
     ///
     /// This delegates the response and returns a [BlockResponseOutcome] to either queue in a direct
     /// followup request or get the peer reported if the response was a
@@ -230,12 +502,17 @@ impl StateFetcher {
     pub(crate) fn on_block_headers_response(
         &mut self,
         peer_id: PeerId,
+        request_id: u64,
         res: RequestResult<Vec<Header>>,
     ) -> Option<BlockResponseOutcome> {
+        if self.peers.get(&peer_id).and_then(|peer| peer.best_header_request) == Some(request_id) {
+            return self.on_best_header_response(peer_id, res)
+        }
+
         let is_error = res.is_err();
         let maybe_reputation_change = res.reputation_change_err();
 
-        let resp = self.inflight_headers_requests.remove(&peer_id);
+        let resp = self.inflight_headers_requests.remove(&request_id);
 
         let is_likely_bad_response = resp
             .as_ref()
@@ -247,12 +524,10 @@ impl StateFetcher {
             let _ = resp.response.send(res.map(|h| (peer_id, h).into()));
         }
 
-        if let Some(peer) = self.peers.get_mut(&peer_id) {
-            // If the peer is still ready to accept new requests, we try to send a followup
-            // request immediately.
-            if peer.state.on_request_finished() && !is_error && !is_likely_bad_response {
-                return self.followup_request(peer_id)
-            }
+        // If the peer is still ready to accept new requests, we try to send a followup request
+        // immediately.
+        if self.on_request_finished(&peer_id) && !is_error && !is_likely_bad_response {
+            return self.followup_request(peer_id)
         }
 
         // if the response was an `Err` worth reporting the peer for then we return a `BadResponse`
@@ -261,20 +536,51 @@ impl StateFetcher {
             .map(|reputation_change| BlockResponseOutcome::BadResponse(peer_id, reputation_change))
     }
 
-    /// Called on a `GetBlockBodies` response from a peer
+    /// Called on a `GetBlockBodies` response from a peer to the request with the given id.
+    ///
+    /// If the peer returned only some of the requested bodies, the missing bodies are requested
+    /// from another idle peer, see [`BlockResponseOutcome::Partial`]. The response is delegated
+    /// once all bodies are received.
     pub(crate) fn on_block_bodies_response(
         &mut self,
         peer_id: PeerId,
+        request_id: u64,
         res: RequestResult<Vec<BlockBody>>,
     ) -> Option<BlockResponseOutcome> {
-        if let Some(resp) = self.inflight_bodies_requests.remove(&peer_id) {
-            let _ = resp.response.send(res.map(|b| (peer_id, b).into()));
-        }
-        if let Some(peer) = self.peers.get_mut(&peer_id) {
-            if peer.state.on_request_finished() {
-                return self.followup_request(peer_id)
+        let mut partial = None;
+        if let Some(mut resp) = self.inflight_bodies_requests.remove(&request_id) {
+            match res {
+                Ok(bodies) if !bodies.is_empty() && bodies.len() < resp.request.hashes.len() => {
+                    // the bodies are returned in order, so the missing ones are at the end
+                    let missing = resp.request.hashes.split_off(bodies.len());
+                    resp.request.received.extend(bodies);
+                    if let Some(target) = self.next_peer_except(&peer_id) {
+                        resp.peer_id = target;
+                        resp.request.hashes = missing.clone();
+                        let request_id = self.next_request_id();
+                        self.inflight_bodies_requests.insert(request_id, resp);
+                        self.on_request_started(&target);
+                        partial = Some(BlockResponseOutcome::Partial {
+                            peer: target,
+                            request_id,
+                            missing,
+                        });
+                    } else {
+                        // no other peer to ask, so the partial response is delegated as is
+                        resp.respond(peer_id, Ok(Vec::new()));
+                    }
+                }
+                res => resp.respond(peer_id, res),
             }
         }
+
+        let is_ready = self.on_request_finished(&peer_id);
+        if partial.is_some() {
+            return partial
+        }
+        if is_ready {
+            return self.followup_request(peer_id)
+        }
         None
     }
 
@@ -301,26 +607,37 @@ struct Peer {
     state: PeerState,
     /// Best known hash that the peer has
     best_hash: H256,
-    /// Tracks the best number of the peer.
-    best_number: u64,
+    /// Tracks the best number of the peer, if known.
+    best_number: Option<u64>,
+    /// Whether the best header was already requested from the peer to resolve its best number.
+    best_header_requested: bool,
+    /// The id of the inflight request for the peer's best header.
+    best_header_request: Option<u64>,
     /// Tracks the current timeout value we use for the peer.
     timeout: Arc<AtomicU64>,
+    /// The number of requests the peer currently handles.
+    inflight: usize,
 }
 
 impl Peer {
     fn timeout(&self) -> u64 {
         self.timeout.load(Ordering::Relaxed)
     }
+
+    /// Returns `true` if the peer can accept another request, because it handles fewer than `max`
+    /// requests.
+    fn is_idle(&self, max: usize) -> bool {
+        self.state.is_idle() && self.inflight < max
+    }
 }
 
 /// Tracks the state of an individual peer
 enum PeerState {
-    /// Peer is currently not handling requests and is available.
+    /// Peer is available for requests, as long as it handles fewer than the maximum number of
+    /// concurrent requests.
     Idle,
-    /// Peer is handling a `GetBlockHeaders` request.
-    GetBlockHeaders,
-    /// Peer is handling a `GetBlockBodies` request.
-    GetBlockBodies,
+    /// Peer is handling a `GetBlockHeaders` request for its best header.
+    GetBestHeader,
     /// Peer session is about to close
     Closing,
 }
@@ -336,20 +653,36 @@ impl PeerState {
     /// Resets the state on a received response.
     ///
     /// If the state was already marked as `Closing` do nothing.
-    ///
-    /// Returns `true` if the peer is ready for another request.
-    fn on_request_finished(&mut self) -> bool {
+    fn on_request_finished(&mut self) {
         if !matches!(self, PeerState::Closing) {
             *self = PeerState::Idle;
-            return true
         }
-        false
     }
 }
 
+/// Returns the id of the first inflight request whose receiver was dropped.
+fn find_cancelled<Req, Resp>(requests: &HashMap<u64, Request<Req, Resp>>) -> Option<u64> {
+    requests.iter().find(|(_, req)| req.response.is_closed()).map(|(id, _)| *id)
+}
+
+/// Removes all inflight requests the peer handles.
+fn take_peer_requests<Req, Resp>(
+    requests: &mut HashMap<u64, Request<Req, Resp>>,
+    peer_id: &PeerId,
+) -> Vec<Request<Req, Resp>> {
+    let ids = requests
+        .iter()
+        .filter(|(_, req)| req.peer_id == *peer_id)
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    ids.into_iter().filter_map(|id| requests.remove(&id)).collect()
+}
+
 /// A request that waits for a response from the network, so it can send it back through the
 /// response channel.
 struct Request<Req, Resp> {
+    /// The peer that handles the request.
+    peer_id: PeerId,
     /// The issued request object
     /// TODO: this can be attached to the response in error case
     #[allow(unused)]
@@ -357,6 +690,33 @@ struct Request<Req, Resp> {
     response: oneshot::Sender<Resp>,
 }
 
+/// The hashes of an inflight `GetBlockBodies` request and the bodies that earlier partial
+/// responses already returned.
+struct BodiesRequest {
+    /// The hashes of the requested bodies.
+    hashes: Vec<H256>,
+    /// Bodies received in earlier partial responses.
+    received: Vec<BlockBody>,
+}
+
+impl Request<BodiesRequest, PeerRequestResult<Vec<BlockBody>>> {
+    /// Sends the received bodies, followed by the bodies of the final response.
+    ///
+    /// If the final response failed, the bodies received so far are still sent.
+    fn respond(self, peer_id: PeerId, res: RequestResult<Vec<BlockBody>>) {
+        let Self { request: BodiesRequest { mut received, .. }, response, .. } = self;
+        let res = match res {
+            Ok(bodies) => {
+                received.extend(bodies);
+                Ok(received)
+            }
+            Err(_) if !received.is_empty() => Ok(received),
+            Err(err) => Err(err),
+        };
+        let _ = response.send(res.map(|bodies| (peer_id, bodies).into()));
+    }
+}
+
 /// Requests that can be sent to the Syncer from a [`FetchClient`]
 pub(crate) enum DownloadRequest {
     /// Download the requested headers and send response through channel
@@ -376,14 +736,6 @@ pub(crate) enum DownloadRequest {
 // === impl DownloadRequest ===
 
 impl DownloadRequest {
-    /// Returns the corresponding state for a peer that handles the request.
-    fn peer_state(&self) -> PeerState {
-        match self {
-            DownloadRequest::GetBlockHeaders { .. } => PeerState::GetBlockHeaders,
-            DownloadRequest::GetBlockBodies { .. } => PeerState::GetBlockBodies,
-        }
-    }
-
     /// Returns the requested priority of this request
     fn get_priority(&self) -> &Priority {
         match self {
@@ -396,6 +748,14 @@ impl DownloadRequest {
     fn is_normal_priority(&self) -> bool {
         self.get_priority().is_normal()
     }
+
+    /// Returns `true` if the receiver of the response was dropped.
+    fn is_cancelled(&self) -> bool {
+        match self {
+            DownloadRequest::GetBlockHeaders { response, .. } => response.is_closed(),
+            DownloadRequest::GetBlockBodies { response, .. } => response.is_closed(),
+        }
+    }
 }
 
 /// An action the syncer can emit.
@@ -404,9 +764,19 @@ pub(crate) enum FetchAction {
     BlockRequest {
         /// The targeted recipient for the request
         peer_id: PeerId,
+        /// The id the response is routed to the fetcher with
+        request_id: u64,
         /// The request to send
         request: BlockRequest,
     },
+    /// The receiver of the peer's inflight request was dropped, so the response isn't needed
+    /// anymore.
+    RequestCancelled {
+        /// The peer that's handling the request
+        peer_id: PeerId,
+        /// The id of the cancelled request
+        request_id: u64,
+    },
 }
 
 /// Outcome of a processed response.
@@ -414,10 +784,20 @@ pub(crate) enum FetchAction {
 /// Returned after processing a response.
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) enum BlockResponseOutcome {
-    /// Continue with another request to the peer.
-    Request(PeerId, BlockRequest),
+    /// Continue with another request to the peer, tracked under the given request id.
+    Request(PeerId, u64, BlockRequest),
     /// How to handle a bad response and the reputation change to apply, if any.
     BadResponse(PeerId, ReputationChangeKind),
+    /// Request the bodies that were missing from a partial `BlockBodies` response from another
+    /// peer.
+    Partial {
+        /// The peer to request the missing bodies from.
+        peer: PeerId,
+        /// The id the request for the missing bodies is tracked under.
+        request_id: u64,
+        /// The hashes of the missing bodies.
+        missing: Vec<H256>,
+    },
 }
 
 #[cfg(test)]
@@ -454,8 +834,8 @@ mod tests {
         // Add a few random peers
         let peer1 = H512::random();
         let peer2 = H512::random();
-        fetcher.new_active_peer(peer1, H256::random(), 1, Arc::new(AtomicU64::new(1)));
-        fetcher.new_active_peer(peer2, H256::random(), 2, Arc::new(AtomicU64::new(1)));
+        fetcher.new_active_peer(peer1, H256::random(), Some(1), Arc::new(AtomicU64::new(1)));
+        fetcher.new_active_peer(peer2, H256::random(), Some(2), Arc::new(AtomicU64::new(1)));
 
         let first_peer = fetcher.next_peer().unwrap();
         assert!(first_peer == peer1 || first_peer == peer2);
@@ -481,9 +861,9 @@ mod tests {
 
         let peer2_timeout = Arc::new(AtomicU64::new(300));
 
-        fetcher.new_active_peer(peer1, H256::random(), 1, Arc::new(AtomicU64::new(30)));
-        fetcher.new_active_peer(peer2, H256::random(), 2, Arc::clone(&peer2_timeout));
-        fetcher.new_active_peer(peer3, H256::random(), 3, Arc::new(AtomicU64::new(50)));
+        fetcher.new_active_peer(peer1, H256::random(), Some(1), Arc::new(AtomicU64::new(30)));
+        fetcher.new_active_peer(peer2, H256::random(), Some(2), Arc::clone(&peer2_timeout));
+        fetcher.new_active_peer(peer3, H256::random(), Some(3), Arc::new(AtomicU64::new(50)));
 
         // Must always get peer1 (lowest timeout)
         assert_eq!(fetcher.next_peer(), Some(peer1));
@@ -495,32 +875,79 @@ mod tests {
         assert_eq!(fetcher.next_peer(), Some(peer2));
     }
 
+    #[tokio::test]
+    async fn test_concurrent_requests_per_peer() {
+        let manager = PeersManager::new(PeersConfig::default());
+        let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
+        fetcher.set_max_concurrent_requests_per_peer(2);
+        let peer1 = H512::random();
+        let peer2 = H512::random();
+        fetcher.new_active_peer(peer1, H256::random(), Some(1), Arc::new(AtomicU64::new(1)));
+        fetcher.new_active_peer(peer2, H256::random(), Some(2), Arc::new(AtomicU64::new(2)));
+
+        let mut receivers = Vec::new();
+        for _ in 0..5 {
+            let (tx, rx) = oneshot::channel();
+            fetcher.queued_requests.push_back(DownloadRequest::GetBlockBodies {
+                request: vec![H256::random()],
+                response: tx,
+                priority: Priority::default(),
+            });
+            receivers.push(rx);
+        }
+
+        // requests go to the peer with the fewest inflight requests, up to the cap per peer
+        let mut dispatched = Vec::new();
+        poll_fn(|cx| {
+            while let Poll::Ready(FetchAction::BlockRequest { peer_id, request_id, .. }) =
+                fetcher.poll(cx)
+            {
+                dispatched.push((peer_id, request_id));
+            }
+            Poll::Ready(())
+        })
+        .await;
+        let peers = dispatched.iter().map(|(peer_id, _)| *peer_id).collect::<Vec<_>>();
+        assert_eq!(peers, vec![peer1, peer2, peer1, peer2]);
+        assert_eq!(fetcher.num_queued_requests(), 1);
+        assert!(!fetcher.is_idle(&peer1));
+
+        // a response frees a slot, so the peer takes the queued request right away
+        let (_, request_id) = dispatched[0];
+        let outcome = fetcher.on_block_bodies_response(peer1, request_id, Ok(Vec::new()));
+        assert!(matches!(outcome, Some(BlockResponseOutcome::Request(peer, ..)) if peer == peer1));
+        assert_eq!(fetcher.num_queued_requests(), 0);
+    }
+
     #[tokio::test]
     async fn test_on_block_headers_response() {
         let manager = PeersManager::new(PeersConfig::default());
         let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
         let peer_id = H512::random();
 
-        assert_eq!(fetcher.on_block_headers_response(peer_id, Ok(vec![Header::default()])), None);
+        assert_eq!(
+            fetcher.on_block_headers_response(peer_id, 0, Ok(vec![Header::default()])),
+            None
+        );
 
         assert_eq!(
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::Timeout)),
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::Timeout)),
             Some(BlockResponseOutcome::BadResponse(peer_id, ReputationChangeKind::Timeout))
         );
         assert_eq!(
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::BadResponse)),
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::BadResponse)),
             None
         );
         assert_eq!(
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::ChannelClosed)),
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::ChannelClosed)),
             None
         );
         assert_eq!(
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::ConnectionDropped)),
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::ConnectionDropped)),
             None
         );
         assert_eq!(
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::UnsupportedCapability)),
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::UnsupportedCapability)),
             None
         );
     }
@@ -534,6 +961,7 @@ mod tests {
         let request_pair = || {
             let (tx, _rx) = oneshot::channel();
             let req = Request {
+                peer_id,
                 request: HeadersRequest {
                     start: 0u64.into(),
                     limit: 1,
@@ -554,14 +982,14 @@ mod tests {
         );
 
         let (req, header) = request_pair();
-        fetcher.inflight_headers_requests.insert(peer_id, req);
+        fetcher.inflight_headers_requests.insert(0, req);
 
-        let outcome = fetcher.on_block_headers_response(peer_id, Ok(vec![header]));
+        let outcome = fetcher.on_block_headers_response(peer_id, 0, Ok(vec![header]));
         assert!(outcome.is_none());
-        assert!(fetcher.peers[&peer_id].state.is_idle());
+        assert!(fetcher.is_idle(&peer_id));
 
         let outcome =
-            fetcher.on_block_headers_response(peer_id, Err(RequestError::Timeout)).unwrap();
+            fetcher.on_block_headers_response(peer_id, 0, Err(RequestError::Timeout)).unwrap();
 
         assert!(EthResponseValidator::reputation_change_err(&Err(RequestError::Timeout)).is_some());
 
@@ -569,11 +997,177 @@ mod tests {
             BlockResponseOutcome::BadResponse(peer, _) => {
                 assert_eq!(peer, peer_id)
             }
-            BlockResponseOutcome::Request(_, _) => {
+            BlockResponseOutcome::Request(..) | BlockResponseOutcome::Partial { .. } => {
                 unreachable!()
             }
         };
 
-        assert!(fetcher.peers[&peer_id].state.is_idle());
+        assert!(fetcher.is_idle(&peer_id));
+    }
+
+    #[tokio::test]
+    async fn test_partial_bodies_response() {
+        let manager = PeersManager::new(PeersConfig::default());
+        let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
+        let peer1 = H512::random();
+        let peer2 = H512::random();
+        fetcher.new_active_peer(peer1, H256::random(), Some(1), Arc::new(AtomicU64::new(1)));
+        fetcher.new_active_peer(peer2, H256::random(), Some(2), Arc::new(AtomicU64::new(2)));
+
+        let hashes = vec![H256::random(), H256::random(), H256::random()];
+        let (tx, mut rx) = oneshot::channel();
+        let request = DownloadRequest::GetBlockBodies {
+            request: hashes.clone(),
+            response: tx,
+            priority: Priority::default(),
+        };
+        let (request_id, _) = fetcher.prepare_block_request(peer1, request);
+
+        // only the missing bodies are requested from the other peer
+        let body = |gas_limit| BlockBody {
+            ommers: vec![Header { gas_limit, ..Default::default() }],
+            ..Default::default()
+        };
+        let outcome = fetcher.on_block_bodies_response(peer1, request_id, Ok(vec![body(1)]));
+        let Some(BlockResponseOutcome::Partial { peer, request_id, missing }) = outcome else {
+            unreachable!()
+        };
+        assert_eq!(peer, peer2);
+        assert_eq!(missing, hashes[1..].to_vec());
+        assert!(fetcher.is_idle(&peer1));
+        assert!(!fetcher.is_idle(&peer2));
+        assert!(rx.try_recv().is_err());
+
+        // the response contains the bodies of both peers
+        let outcome =
+            fetcher.on_block_bodies_response(peer2, request_id, Ok(vec![body(2), body(3)]));
+        assert_eq!(outcome, None);
+        let bodies = rx.try_recv().unwrap().unwrap().1;
+        assert_eq!(bodies, vec![body(1), body(2), body(3)]);
+
+        // if the missing bodies can't be requested, the bodies received so far are returned
+        let (tx, mut rx) = oneshot::channel();
+        let request = DownloadRequest::GetBlockBodies {
+            request: hashes,
+            response: tx,
+            priority: Priority::default(),
+        };
+        let (request_id, _) = fetcher.prepare_block_request(peer1, request);
+        let outcome = fetcher.on_block_bodies_response(peer1, request_id, Ok(vec![body(1)]));
+        let Some(BlockResponseOutcome::Partial { request_id, .. }) = outcome else {
+            unreachable!()
+        };
+        fetcher.on_request_failed(request_id, RequestError::ConnectionDropped);
+        assert_eq!(rx.try_recv().unwrap().unwrap().1, vec![body(1)]);
+        assert!(fetcher.is_idle(&peer2));
+    }
+
+    #[tokio::test]
+    async fn test_best_header_request() {
+        let manager = PeersManager::new(PeersConfig::default());
+        let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
+        let peer_id = H512::random();
+        let best_header = Header { number: 42, ..Default::default() };
+        let best_hash = best_header.hash_slow();
+        fetcher.new_active_peer(peer_id, best_hash, None, Arc::new(AtomicU64::new(1)));
+
+        let (tx, _rx) = oneshot::channel();
+        let request =
+            HeadersRequest { start: 10u64.into(), limit: 5, direction: Default::default() };
+        fetcher.queued_requests.push_back(DownloadRequest::GetBlockHeaders {
+            request,
+            response: tx,
+            priority: Priority::default(),
+        });
+
+        // the best header is requested before the range request
+        let PollAction::Ready(FetchAction::BlockRequest { peer_id: target, request_id, request }) =
+            fetcher.poll_action()
+        else {
+            unreachable!()
+        };
+        assert_eq!(target, peer_id);
+        assert_eq!(
+            request,
+            BlockRequest::GetBlockHeaders(GetBlockHeaders {
+                start_block: best_hash.into(),
+                limit: 1,
+                skip: 0,
+                direction: Default::default(),
+            })
+        );
+        assert_eq!(fetcher.num_queued_requests(), 1);
+        assert!(fetcher.inflight_headers_requests.is_empty());
+
+        // the range request follows once the best number is known
+        let outcome = fetcher.on_block_headers_response(peer_id, request_id, Ok(vec![best_header]));
+        assert_eq!(fetcher.peer_best_block(&peer_id), Some((best_hash, Some(42))));
+        assert_eq!(
+            outcome,
+            Some(BlockResponseOutcome::Request(
+                peer_id,
+                request_id + 1,
+                BlockRequest::GetBlockHeaders(GetBlockHeaders {
+                    start_block: 10u64.into(),
+                    limit: 5,
+                    skip: 0,
+                    direction: Default::default(),
+                })
+            ))
+        );
+        assert_eq!(fetcher.inflight_headers_requests[&(request_id + 1)].peer_id, peer_id);
+    }
+
+    #[tokio::test]
+    async fn test_prefer_peers_with_known_number() {
+        let manager = PeersManager::new(PeersConfig::default());
+        let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
+        let unknown = H512::random();
+        let known = H512::random();
+        fetcher.new_active_peer(unknown, H256::random(), None, Arc::new(AtomicU64::new(1)));
+        fetcher.new_active_peer(known, H256::random(), Some(0), Arc::new(AtomicU64::new(100)));
+
+        assert_eq!(fetcher.next_peer(), Some(known));
+        assert_eq!(fetcher.next_peer_except(&known), Some(unknown));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_requests() {
+        let manager = PeersManager::new(PeersConfig::default());
+        let mut fetcher = StateFetcher::new(manager.handle(), Default::default());
+        let peer_id = H512::random();
+        fetcher.new_active_peer(peer_id, H256::random(), Some(1), Arc::new(AtomicU64::new(1)));
+
+        let (tx, rx) = oneshot::channel();
+        let request = DownloadRequest::GetBlockBodies {
+            request: vec![H256::random()],
+            response: tx,
+            priority: Priority::default(),
+        };
+        let (request_id, _) = fetcher.prepare_block_request(peer_id, request);
+        let (tx, queued_rx) = oneshot::channel();
+        fetcher.queued_requests.push_back(DownloadRequest::GetBlockBodies {
+            request: vec![H256::random()],
+            response: tx,
+            priority: Priority::default(),
+        });
+        drop(rx);
+        drop(queued_rx);
+
+        poll_fn(move |cx| {
+            // the inflight request is cleaned up and the queued one isn't sent
+            assert!(matches!(
+                fetcher.poll(cx),
+                Poll::Ready(FetchAction::RequestCancelled { peer_id: id, request_id: cancelled })
+                    if id == peer_id && cancelled == request_id
+            ));
+            assert!(fetcher.inflight_bodies_requests.is_empty());
+            assert!(fetcher.is_idle(&peer_id));
+            assert_eq!(fetcher.num_queued_requests(), 0);
+            assert!(fetcher.poll(cx).is_pending());
+
+            Poll::Ready(())
+        })
+        .await;
     }
 }
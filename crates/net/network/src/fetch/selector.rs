@@ -0,0 +1,60 @@
+//! Policies for choosing the peer a block request is sent to.
+
+use reth_primitives::PeerId;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Abstraction over the selection of a peer for a block request.
+///
+/// This is consulted if the peer a request was originally assigned to is no longer available.
+pub trait PeerSelector: Send + Sync {
+    /// Selects one of the given candidates.
+    ///
+    /// Returns `None` if none of the candidates should be used.
+    fn select(&self, candidates: &[PeerId]) -> Option<PeerId>;
+}
+
+/// A [`PeerSelector`] that never selects a peer and leaves peer selection entirely to the
+/// fetcher.
+#[derive(Debug, Default)]
+pub struct DefaultPeerSelector;
+
+impl PeerSelector for DefaultPeerSelector {
+    fn select(&self, _candidates: &[PeerId]) -> Option<PeerId> {
+        None
+    }
+}
+
+/// A [`PeerSelector`] that cycles through the candidates.
+#[derive(Debug, Default)]
+pub struct RoundRobinPeerSelector {
+    /// Counter of the selections made so far.
+    next: AtomicUsize,
+}
+
+impl PeerSelector for RoundRobinPeerSelector {
+    fn select(&self, candidates: &[PeerId]) -> Option<PeerId> {
+        if candidates.is_empty() {
+            return None
+        }
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        Some(candidates[idx])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin_selector() {
+        let selector = RoundRobinPeerSelector::default();
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+        for round in 0..2 {
+            for (idx, peer) in peers.iter().enumerate() {
+                assert_eq!(selector.select(&peers), Some(*peer), "round {round}, idx {idx}");
+            }
+        }
+        assert_eq!(selector.select(&[]), None);
+    }
+}
@@ -146,11 +146,15 @@ pub mod transactions;
 pub use builder::NetworkBuilder;
 pub use config::{NetworkConfig, NetworkConfigBuilder};
 pub use discovery::Discovery;
-pub use fetch::FetchClient;
+pub use fetch::{DefaultPeerSelector, FetchClient, PeerSelector, RoundRobinPeerSelector};
 pub use manager::{NetworkEvent, NetworkManager};
-pub use message::PeerRequest;
+pub use message::{NewBlockMessage, PeerRequest};
 pub use network::NetworkHandle;
-pub use peers::PeersConfig;
+pub use peers::{PeerBlacklist, PeerRemovalReason, PeersConfig};
 pub use session::{PeerInfo, SessionsConfig};
+pub use state::{
+    ActivePeerInfo, BlockRequestRateLimit, HealthThresholds, NetworkHealth, NetworkSnapshot,
+    NetworkSnapshotHandle, PeerCountWatermarks, StateAction, StateActionHandler, SubnetLimits,
+};
 
 pub use reth_eth_wire::{DisconnectReason, HelloBuilder, HelloMessage};
@@ -25,9 +25,9 @@ use crate::{
     message::{NewBlockMessage, PeerMessage, PeerRequest, PeerRequestSender},
     metrics::{DisconnectMetrics, NetworkMetrics, NETWORK_POOL_TRANSACTIONS_SCOPE},
     network::{NetworkHandle, NetworkHandleMessage},
-    peers::{PeersHandle, PeersManager},
+    peers::{PeerRemovalReason, PeersHandle, PeersManager},
     session::SessionManager,
-    state::NetworkState,
+    state::{NetworkSnapshotHandle, NetworkState},
     swarm::{NetworkConnectionState, Swarm, SwarmEvent},
     transactions::NetworkTransactionEvent,
     FetchClient, NetworkBuilder,
@@ -180,9 +180,38 @@ where
             status,
             fork_filter,
             dns_discovery_config,
+            propagation_fraction,
+            prefer_low_latency_propagation,
+            propagation_proximity_window,
+            peer_block_cache_limit,
+            peer_transaction_hash_cache_limit,
+            max_concurrent_requests_per_peer,
+            min_eth_version,
+            peer_count_watermarks,
+            reputation_decay,
+            max_new_block_gap,
+            max_queued_messages,
+            blacklist,
+            peer_selector,
+            ban_incompatible_fork_peers,
+            health_thresholds,
+            max_bad_responses,
+            subnet_limits,
+            max_protocol_violations,
+            block_request_rate_limit,
+            max_outstanding_response_bytes,
+            idle_peer_timeout,
+            incompatible_fork_ip_ban_threshold,
+            max_new_block_size,
+            fetch_stall_threshold,
+            announce_whitelist,
+            block_source_cache_limit,
+            state_action_handler,
+            min_propagate,
             ..
         } = config;
 
+        let trusted_only = peers_config.connect_trusted_nodes_only;
         let peers_manager = PeersManager::new(peers_config);
         let peers_handle = peers_manager.handle();
 
@@ -207,23 +236,84 @@ where
         let num_active_peers = Arc::new(AtomicUsize::new(0));
         let bandwidth_meter: BandwidthMeter = BandwidthMeter::default();
 
+        // the handler is spawned before the executor is handed to the sessions
+        let action_listener = state_action_handler.map(|handler| handler.spawn(executor.as_ref()));
+
         let sessions = SessionManager::new(
             secret_key,
             sessions_config,
             executor,
             status,
             hello_message,
-            fork_filter,
+            fork_filter.clone(),
             bandwidth_meter.clone(),
         );
 
-        let state = NetworkState::new(
+        let mut state = NetworkState::new(
             client,
             discovery,
             peers_manager,
             chain_spec.genesis_hash(),
             Arc::clone(&num_active_peers),
         );
+        state.set_propagation_fraction(propagation_fraction);
+        state.set_prefer_low_latency_propagation(prefer_low_latency_propagation);
+        state.set_propagation_proximity_window(propagation_proximity_window);
+        state.set_peer_block_cache_limit(peer_block_cache_limit);
+        state.set_peer_transaction_hash_cache_limit(peer_transaction_hash_cache_limit);
+        state.set_max_concurrent_requests_per_peer(max_concurrent_requests_per_peer);
+        state.set_peer_selector(peer_selector);
+        state.set_fork_filter(fork_filter);
+        state.set_ban_incompatible_fork_peers(ban_incompatible_fork_peers);
+        state.set_trusted_only(trusted_only);
+        state.set_min_eth_version(min_eth_version);
+        if let Some(watermarks) = peer_count_watermarks {
+            state.set_peer_count_watermarks(watermarks);
+        }
+        if let Some(decay) = reputation_decay {
+            state.set_reputation_decay(decay);
+        }
+        if let Some(max_gap) = max_new_block_gap {
+            state.set_max_new_block_gap(max_gap);
+        }
+        if let Some(max) = max_queued_messages {
+            state.set_max_queued_messages(max);
+        }
+        state.set_blacklist(blacklist);
+        state.set_health_thresholds(health_thresholds);
+        if let Some(max) = max_bad_responses {
+            state.set_max_bad_responses(max);
+        }
+        if let Some(limits) = subnet_limits {
+            state.set_subnet_limits(limits);
+        }
+        if let Some(max) = max_protocol_violations {
+            state.set_max_protocol_violations(max);
+        }
+        if let Some(limit) = block_request_rate_limit {
+            state.set_block_request_rate_limit(limit);
+        }
+        if let Some(max) = max_outstanding_response_bytes {
+            state.set_max_outstanding_response_bytes(max);
+        }
+        if let Some(timeout) = idle_peer_timeout {
+            state.set_idle_peer_timeout(timeout);
+        }
+        if let Some(threshold) = incompatible_fork_ip_ban_threshold {
+            state.set_incompatible_fork_ip_ban_threshold(threshold);
+        }
+        if let Some(max_size) = max_new_block_size {
+            state.set_max_new_block_size(max_size);
+        }
+        if let Some(threshold) = fetch_stall_threshold {
+            state.set_fetch_stall_threshold(threshold);
+        }
+        state.set_announce_whitelist(announce_whitelist);
+        state.set_block_source_cache_limit(block_source_cache_limit);
+        state.set_min_propagate(min_propagate);
+        if let Some(listener) = action_listener {
+            state.set_action_listener(listener);
+        }
 
         let swarm = Swarm::new(incoming, sessions, state, NetworkConnectionState::default());
 
@@ -238,6 +328,7 @@ where
             network_mode,
             bandwidth_meter,
             Arc::new(AtomicU64::new(chain_spec.chain.id())),
+            swarm.state().snapshot_handle(),
         );
 
         Ok(Self {
@@ -333,6 +424,14 @@ where
         self.swarm.state().fetch_client()
     }
 
+    /// Returns a new [`NetworkSnapshotHandle`] that can be cloned and shared.
+    ///
+    /// The handle provides a read-only view of the active peers without interfering with the
+    /// network.
+    pub fn snapshot_handle(&self) -> NetworkSnapshotHandle {
+        self.swarm.state().snapshot_handle()
+    }
+
     /// Returns the current [`NetworkStatus`] for the local node.
     pub fn status(&self) -> NetworkStatus {
         let sessions = self.swarm.sessions();
@@ -480,12 +579,18 @@ where
             }
             PeerMessage::NewBlock(block) => {
                 self.within_pow_or_disconnect(peer_id, move |this| {
-                    this.swarm.state_mut().on_new_block(peer_id, block.hash);
-                    // start block import process
-                    this.block_import.on_new_block(peer_id, block);
+                    let number = block.block.block.header.number;
+                    if this.swarm.state_mut().on_new_block(peer_id, block.hash, number) {
+                        // start block import process
+                        this.block_import.on_new_block(peer_id, block);
+                    }
                 });
             }
             PeerMessage::PooledTransactions(msg) => {
+                // update peer's state, to track what transactions this peer has seen
+                self.swarm
+                    .state_mut()
+                    .on_new_pooled_tx_hashes(peer_id, msg.iter_hashes().copied().collect());
                 self.notify_tx_manager(NetworkTransactionEvent::IncomingPooledTransactionHashes {
                     peer_id,
                     msg,
@@ -570,6 +675,7 @@ where
                 let _ = tx.send(self.status());
             }
             NetworkHandleMessage::StatusUpdate { head } => {
+                self.swarm.state_mut().update_fork_filter_head(head);
                 if let Some(transition) = self.swarm.sessions_mut().on_status_update(head) {
                     self.swarm.state_mut().update_fork_id(transition.current);
                 }
@@ -706,9 +812,11 @@ where
                                 .tracked_peers
                                 .set(this.swarm.state().peers().num_known_peers() as f64);
                         }
-                        SwarmEvent::PeerRemoved(peer_id) => {
-                            trace!(target: "net", ?peer_id, "Peer dropped");
+                        SwarmEvent::PeerRemoved { peer_id, reason } => {
+                            trace!(target: "net", ?peer_id, ?reason, "Peer dropped");
                             this.event_listeners.notify(NetworkEvent::PeerRemoved(peer_id));
+                            this.event_listeners
+                                .notify(NetworkEvent::PeerRemovedReason { peer_id, reason });
                             this.metrics
                                 .tracked_peers
                                 .set(this.swarm.state().peers().num_known_peers() as f64);
@@ -918,4 +1026,12 @@ pub enum NetworkEvent {
     PeerAdded(PeerId),
     /// Event emitted when a new peer is removed
     PeerRemoved(PeerId),
+    /// Event emitted right after [`NetworkEvent::PeerRemoved`] with the reason the peer was
+    /// removed.
+    PeerRemovedReason {
+        /// The removed peer.
+        peer_id: PeerId,
+        /// Why the peer was removed.
+        reason: PeerRemovalReason,
+    },
 }
@@ -1,6 +1,6 @@
 use crate::{
     config::NetworkMode, manager::NetworkEvent, message::PeerRequest, peers::PeersHandle,
-    session::PeerInfo, FetchClient,
+    session::PeerInfo, FetchClient, NetworkSnapshotHandle,
 };
 use async_trait::async_trait;
 use parking_lot::Mutex;
@@ -11,7 +11,9 @@ use reth_network_api::{
     NetworkError, NetworkInfo, PeerKind, Peers, PeersInfo, Reputation, ReputationChangeKind,
 };
 use reth_primitives::{Head, NodeRecord, PeerId, TransactionSigned, H256};
-use reth_rpc_types::NetworkStatus;
+use reth_rpc_types::{
+    ConnectedPeer, EthPeerInfo, NetworkStatus, PeerNetworkInfo, PeerProtocolsInfo,
+};
 use std::{
     net::SocketAddr,
     sync::{
@@ -45,6 +47,7 @@ impl NetworkHandle {
         network_mode: NetworkMode,
         bandwidth_meter: BandwidthMeter,
         chain_id: Arc<AtomicU64>,
+        snapshot: NetworkSnapshotHandle,
     ) -> Self {
         let inner = NetworkInner {
             num_active_peers,
@@ -56,6 +59,7 @@ impl NetworkHandle {
             bandwidth_meter,
             is_syncing: Arc::new(AtomicBool::new(false)),
             chain_id,
+            snapshot,
         };
         Self { inner: Arc::new(inner) }
     }
@@ -179,6 +183,26 @@ impl PeersInfo for NetworkHandle {
         let socket_addr = *self.inner.listener_address.lock();
         NodeRecord::new(socket_addr, id)
     }
+
+    fn connected_peers(&self) -> Vec<ConnectedPeer> {
+        self.inner
+            .snapshot
+            .snapshot()
+            .peers
+            .into_iter()
+            .map(|peer| ConnectedPeer {
+                id: peer.peer_id,
+                network: PeerNetworkInfo { remote_address: peer.remote_addr },
+                protocols: PeerProtocolsInfo {
+                    eth: peer.eth_version.map(|version| EthPeerInfo {
+                        version: version.into(),
+                        head: peer.best_hash,
+                        head_number: peer.best_number,
+                    }),
+                },
+            })
+            .collect()
+    }
 }
 
 #[async_trait]
@@ -278,6 +302,8 @@ struct NetworkInner {
     is_syncing: Arc<AtomicBool>,
     /// The chain id
     chain_id: Arc<AtomicU64>,
+    /// The latest snapshot of the active peers.
+    snapshot: NetworkSnapshotHandle,
 }
 
 /// Internal messages that can be passed to the  [`NetworkManager`](crate::NetworkManager).
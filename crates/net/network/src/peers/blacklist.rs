@@ -0,0 +1,287 @@
+//! A persistent blacklist of peers and ip ranges.
+
+use reth_primitives::PeerId;
+use std::{collections::HashSet, fmt, fs, net::IpAddr, path::Path, str::FromStr};
+
+/// Peers and ip ranges that are never connected to.
+///
+/// Unlike the [`BanList`](reth_net_common::ban_list::BanList) of the discovery service, the
+/// blacklist can be loaded from and written to a file, so that it survives restarts.
+///
+/// The file contains one entry per line, either a [`PeerId`] or an ip range in CIDR notation
+/// (`10.0.0.0/8`), a plain ip address is treated as a range of a single address. Empty lines and
+/// lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerBlacklist {
+    /// Blacklisted peers.
+    peers: HashSet<PeerId>,
+    /// Blacklisted ip ranges.
+    ranges: Vec<IpRange>,
+}
+
+impl PeerBlacklist {
+    /// Loads the blacklist from the file at the given path.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BlacklistError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Writes the blacklist to the file at the given path, in the format accepted by
+    /// [`PeerBlacklist::from_file`].
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<(), BlacklistError> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+
+    /// Adds the peer to the blacklist.
+    ///
+    /// Returns `true` if the peer wasn't blacklisted yet.
+    pub fn insert_peer(&mut self, peer_id: PeerId) -> bool {
+        self.peers.insert(peer_id)
+    }
+
+    /// Adds the ip address to the blacklist.
+    ///
+    /// Returns `true` if the ip address wasn't blacklisted yet.
+    pub fn insert_ip(&mut self, ip: IpAddr) -> bool {
+        if self.contains_ip(&ip) {
+            return false
+        }
+        self.ranges.push(IpRange::single(ip));
+        true
+    }
+
+    /// Removes the peer from the blacklist.
+    ///
+    /// Returns `true` if the peer was blacklisted.
+    pub fn remove_peer(&mut self, peer_id: &PeerId) -> bool {
+        self.peers.remove(peer_id)
+    }
+
+    /// Removes the ip address from the blacklist.
+    ///
+    /// This only removes the range that consists of the single address, the address remains
+    /// blacklisted if it's part of another range.
+    ///
+    /// Returns `true` if the ip address was blacklisted on its own.
+    pub fn remove_ip(&mut self, ip: &IpAddr) -> bool {
+        let len = self.ranges.len();
+        self.ranges.retain(|range| *range != IpRange::single(*ip));
+        self.ranges.len() != len
+    }
+
+    /// Returns `true` if the peer is blacklisted.
+    pub fn contains_peer(&self, peer_id: &PeerId) -> bool {
+        self.peers.contains(peer_id)
+    }
+
+    /// Returns `true` if the ip address is part of a blacklisted range.
+    pub fn contains_ip(&self, ip: &IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+
+    /// Returns an iterator over all blacklisted peers.
+    pub fn peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.peers.iter().copied()
+    }
+
+    /// Returns an iterator over all blacklisted ip addresses.
+    ///
+    /// This only includes ranges that consist of a single address.
+    pub fn ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.ranges.iter().filter(|range| range.is_single()).map(|range| range.addr)
+    }
+
+    /// Returns `true` if either the peer or its ip address is blacklisted.
+    pub fn is_blacklisted(&self, peer_id: &PeerId, ip: &IpAddr) -> bool {
+        self.contains_peer(peer_id) || self.contains_ip(ip)
+    }
+
+    /// Returns `true` if nothing is blacklisted.
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty() && self.ranges.is_empty()
+    }
+}
+
+impl FromStr for PeerBlacklist {
+    type Err = BlacklistError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut blacklist = Self::default();
+        for (idx, line) in s.lines().enumerate() {
+            let entry = line.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue
+            }
+
+            if let Ok(range) = entry.parse::<IpRange>() {
+                blacklist.ranges.push(range);
+            } else if let Ok(peer_id) = entry.parse::<PeerId>() {
+                blacklist.peers.insert(peer_id);
+            } else {
+                return Err(BlacklistError::InvalidEntry { line: idx + 1, entry: entry.to_string() })
+            }
+        }
+        Ok(blacklist)
+    }
+}
+
+impl fmt::Display for PeerBlacklist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut peers = self.peers.iter().collect::<Vec<_>>();
+        peers.sort_unstable();
+        for peer_id in peers {
+            writeln!(f, "{peer_id:x}")?;
+        }
+        for range in &self.ranges {
+            writeln!(f, "{range}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors when loading or writing a [`PeerBlacklist`].
+#[derive(Debug, thiserror::Error)]
+pub enum BlacklistError {
+    /// Failed to read or write the blacklist file.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// A line is neither a peer id nor an ip range.
+    #[error("invalid blacklist entry {entry:?} in line {line}")]
+    InvalidEntry {
+        /// The line number, starting at 1.
+        line: usize,
+        /// The invalid entry.
+        entry: String,
+    },
+}
+
+/// A range of ip addresses in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpRange {
+    /// The first address of the range.
+    addr: IpAddr,
+    /// The number of leading bits that are fixed.
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Returns the range that only contains the given address.
+    fn single(addr: IpAddr) -> Self {
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix_len }
+    }
+
+    /// Returns `true` if the range only contains a single address.
+    fn is_single(&self) -> bool {
+        *self == Self::single(self.addr)
+    }
+
+    /// Returns `true` if the address is part of the range.
+    fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(addr), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(addr) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(addr), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(addr) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((addr, prefix_len)) = s.split_once('/') else {
+            return s.parse().map(Self::single).map_err(|_| ())
+        };
+        let addr = addr.parse::<IpAddr>().map_err(|_| ())?;
+        let prefix_len = prefix_len.parse::<u8>().map_err(|_| ())?;
+        let max_prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix_len > max_prefix_len {
+            return Err(())
+        }
+        Ok(Self { addr, prefix_len })
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_blacklist() {
+        let peer_id = PeerId::random();
+        let s =
+            format!("# blacklisted peers\n{peer_id:x}\n\n  10.0.0.0/8  \n192.168.1.1\nfe80::/10\n");
+        let blacklist = s.parse::<PeerBlacklist>().unwrap();
+
+        assert!(blacklist.contains_peer(&peer_id));
+        assert!(!blacklist.contains_peer(&PeerId::random()));
+        assert!(blacklist.contains_ip(&"10.1.2.3".parse().unwrap()));
+        assert!(!blacklist.contains_ip(&"11.0.0.1".parse().unwrap()));
+        assert!(blacklist.contains_ip(&"192.168.1.1".parse().unwrap()));
+        assert!(!blacklist.contains_ip(&"192.168.1.2".parse().unwrap()));
+        assert!(blacklist.contains_ip(&"fe80::1".parse().unwrap()));
+        assert!(!blacklist.contains_ip(&"::1".parse().unwrap()));
+        assert_eq!(blacklist.peers().collect::<Vec<_>>(), vec![peer_id]);
+        assert_eq!(
+            blacklist.ips().collect::<Vec<_>>(),
+            vec!["192.168.1.1".parse::<IpAddr>().unwrap()]
+        );
+
+        // the written blacklist parses to the same entries
+        assert_eq!(blacklist.to_string().parse::<PeerBlacklist>().unwrap(), blacklist);
+    }
+
+    #[test]
+    fn reject_invalid_entries() {
+        let err = "10.0.0.0/8\n10.0.0.0/33".parse::<PeerBlacklist>().unwrap_err();
+        assert!(matches!(err, BlacklistError::InvalidEntry { line: 2, .. }));
+        assert!("not a peer".parse::<PeerBlacklist>().is_err());
+    }
+
+    #[test]
+    fn insert_entries() {
+        let mut blacklist = PeerBlacklist::default();
+        assert!(blacklist.is_empty());
+
+        let peer_id = PeerId::random();
+        assert!(blacklist.insert_peer(peer_id));
+        assert!(!blacklist.insert_peer(peer_id));
+
+        let ip = "127.0.0.1".parse().unwrap();
+        assert!(blacklist.insert_ip(ip));
+        assert!(!blacklist.insert_ip(ip));
+        assert!(blacklist.is_blacklisted(&PeerId::random(), &ip));
+        assert!(blacklist.is_blacklisted(&peer_id, &"127.0.0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn remove_entries() {
+        let mut blacklist = "10.0.0.0/8".parse::<PeerBlacklist>().unwrap();
+        let peer_id = PeerId::random();
+        blacklist.insert_peer(peer_id);
+        assert!(blacklist.remove_peer(&peer_id));
+        assert!(!blacklist.remove_peer(&peer_id));
+
+        let ip = "10.0.0.1".parse().unwrap();
+        blacklist.insert_ip(ip);
+        assert!(!blacklist.remove_ip(&ip));
+        assert!(blacklist.contains_ip(&ip));
+
+        let ip = "11.0.0.1".parse().unwrap();
+        blacklist.insert_ip(ip);
+        assert!(blacklist.remove_ip(&ip));
+        assert!(!blacklist.contains_ip(&ip));
+    }
+}
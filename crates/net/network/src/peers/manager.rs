@@ -72,6 +72,14 @@ impl PeersHandle {
 
         rx.await.unwrap_or_default()
     }
+
+    /// Returns the peers that were added to the set within the given window.
+    pub async fn peers_added_within(&self, window: Duration) -> Vec<PeerId> {
+        let (tx, rx) = oneshot::channel();
+        self.send(PeerCommand::GetPeersAddedWithin(window, tx));
+
+        rx.await.unwrap_or_default()
+    }
 }
 
 /// Maintains the state of _all_ the peers known to the network.
@@ -110,6 +118,8 @@ pub(crate) struct PeersManager {
     connect_trusted_nodes_only: bool,
     /// Timestamp of the last time [Self::tick] was called.
     last_tick: Instant,
+    /// If set, no new outbound connections are initiated.
+    outbound_disabled: bool,
 }
 
 impl PeersManager {
@@ -161,6 +171,7 @@ impl PeersManager {
             backoff_durations,
             connect_trusted_nodes_only,
             last_tick: Instant::now(),
+            outbound_disabled: false,
         }
     }
 
@@ -192,12 +203,27 @@ impl PeersManager {
         self.connection_info.num_outbound
     }
 
+    /// Returns an iterator over all currently banned peers.
+    pub(crate) fn banned_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.ban_list.banned_peers()
+    }
+
+    /// Returns an iterator over all currently banned ip addresses.
+    pub(crate) fn banned_ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.ban_list.banned_ips()
+    }
+
     /// Returns the number of currently backed off peers.
     #[inline]
     pub(crate) fn num_backed_off_peers(&self) -> usize {
         self.backed_off_peers.len()
     }
 
+    /// Stops initiating new outbound connections, for example because the node is shutting down.
+    pub(crate) fn disable_outbound_connections(&mut self) {
+        self.outbound_disabled = true;
+    }
+
     /// Invoked when a new _incoming_ tcp connection is accepted.
     ///
     /// returns an error if the inbound ip address is on the ban list or
@@ -276,7 +302,7 @@ impl PeersManager {
                 // peer is missing in the table, we add it but mark it as to be removed after
                 // disconnect, because we only know the outgoing port
                 let mut peer = Peer::with_state(addr, PeerConnectionState::In);
-                peer.remove_after_disconnect = true;
+                peer.remove_after_disconnect = Some(PeerRemovalReason::Disconnected);
                 entry.insert(peer);
                 self.queued_actions.push_back(PeerAction::PeerAdded(peer_id));
             }
@@ -305,11 +331,23 @@ impl PeersManager {
     }
 
     /// Unbans the peer
-    fn unban_peer(&mut self, peer_id: PeerId) {
+    ///
+    /// If the peer is still banned because of its reputation, the reputation is reset.
+    pub(crate) fn unban_peer(&mut self, peer_id: PeerId) {
         self.ban_list.unban_peer(&peer_id);
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            if peer.is_banned() {
+                peer.unban();
+            }
+        }
         self.queued_actions.push_back(PeerAction::UnBanPeer { peer_id });
     }
 
+    /// Unbans the IP
+    pub(crate) fn unban_ip(&mut self, ip: IpAddr) {
+        self.ban_list.unban_ip(&ip);
+    }
+
     /// Tick function to update reputation of all connected peers.
     /// Peers are rewarded with reputation increases for the time they are connected since the last
     /// tick. This is to prevent peers from being disconnected eventually due to slashed
@@ -333,10 +371,36 @@ impl PeersManager {
         }
     }
 
+    /// Moves the reputation of all peers that aren't banned toward the default reputation by at
+    /// most `amount`.
+    pub(crate) fn decay_reputations(&mut self, amount: i32) {
+        for peer in self.peers.values_mut().filter(|peer| !peer.is_banned()) {
+            if peer.reputation < DEFAULT_REPUTATION {
+                peer.reputation = peer.reputation.saturating_add(amount).min(DEFAULT_REPUTATION);
+            } else {
+                peer.reputation = peer.reputation.saturating_sub(amount).max(DEFAULT_REPUTATION);
+            }
+        }
+    }
+
     pub(crate) fn get_reputation(&self, peer_id: &PeerId) -> Option<i32> {
         self.peers.get(peer_id).map(|peer| peer.reputation)
     }
 
+    /// Returns the reputations of all known peers.
+    pub(crate) fn reputations(&self) -> impl Iterator<Item = (PeerId, i32)> + '_ {
+        self.peers.iter().map(|(peer_id, peer)| (*peer_id, peer.reputation))
+    }
+
+    /// Overwrites the reputation of the peer.
+    ///
+    /// Returns `false` if the peer is not known.
+    pub(crate) fn set_reputation(&mut self, peer_id: &PeerId, reputation: i32) -> bool {
+        let Some(peer) = self.peers.get_mut(peer_id) else { return false };
+        peer.reputation = reputation;
+        true
+    }
+
     /// Apply the corresponding reputation change to the given peer
     pub(crate) fn apply_reputation_change(&mut self, peer_id: &PeerId, rep: ReputationChangeKind) {
         let outcome = if let Some(peer) = self.peers.get_mut(peer_id) {
@@ -393,10 +457,12 @@ impl PeersManager {
             Entry::Occupied(mut entry) => {
                 self.connection_info.decr_state(entry.get().state);
 
-                if entry.get().remove_after_disconnect && !entry.get().is_trusted() {
+                let remove_after_disconnect =
+                    entry.get().remove_after_disconnect.filter(|_| !entry.get().is_trusted());
+                if let Some(reason) = remove_after_disconnect {
                     // this peer should be removed from the set
                     entry.remove();
-                    self.queued_actions.push_back(PeerAction::PeerRemoved(peer_id));
+                    self.queued_actions.push_back(PeerAction::PeerRemoved { peer_id, reason });
                 } else {
                     // reset the peer's state
                     // we reset the backoff counter since we're able to establish a successful
@@ -450,7 +516,10 @@ impl PeersManager {
             // issues.
             if let Some(peer) = self.peers.remove(peer_id) {
                 self.connection_info.decr_state(peer.state);
-                self.queued_actions.push_back(PeerAction::PeerRemoved(*peer_id));
+                self.queued_actions.push_back(PeerAction::PeerRemoved {
+                    peer_id: *peer_id,
+                    reason: PeerRemovalReason::Banned,
+                });
             }
 
             // ban the peer
@@ -522,6 +591,25 @@ impl PeersManager {
         }
     }
 
+    /// Returns the peers with an incoming or outgoing session, including pending outgoing ones,
+    /// and their addresses.
+    pub(crate) fn connected_peers(&self) -> impl Iterator<Item = (PeerId, SocketAddr)> + '_ {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| peer.state.is_connected())
+            .map(|(peer_id, peer)| (*peer_id, peer.addr))
+    }
+
+    /// Returns the kind of the peer, if it is known.
+    pub(crate) fn peer_kind(&self, peer_id: &PeerId) -> Option<PeerKind> {
+        self.peers.get(peer_id).map(|peer| peer.kind)
+    }
+
+    /// Returns the address of the peer, if it is known.
+    pub(crate) fn peer_addr(&self, peer_id: &PeerId) -> Option<SocketAddr> {
+        self.peers.get(peer_id).map(|peer| peer.addr)
+    }
+
     /// Called for a newly discovered peer.
     ///
     /// If the peer already exists, then the address, kind and fork_id will be updated.
@@ -557,12 +645,13 @@ impl PeersManager {
                 peer.kind = kind;
                 peer.fork_id = fork_id;
                 peer.addr = addr;
+                peer.added_at = Some(std::time::Instant::now());
 
                 if peer.state.is_incoming() {
                     // now that we have an actual discovered address, for that peer and not just the
                     // ip of the incoming connection, we don't need to remove the peer after
                     // disconnecting, See `on_incoming_session_established`
-                    peer.remove_after_disconnect = false;
+                    peer.remove_after_disconnect = None;
                 }
 
                 return
@@ -571,6 +660,7 @@ impl PeersManager {
                 trace!(target : "net::peers", ?peer_id, ?addr, "discovered new node");
                 let mut peer = Peer::with_kind(addr, kind);
                 peer.fork_id = fork_id;
+                peer.added_at = Some(std::time::Instant::now());
                 entry.insert(peer);
                 self.queued_actions.push_back(PeerAction::PeerAdded(peer_id));
             }
@@ -579,6 +669,24 @@ impl PeersManager {
         self.fill_outbound_slots();
     }
 
+    /// Returns the peers that were added via [`Self::add_peer_kind`] within the given window.
+    pub(crate) fn peers_added_within(&self, window: Duration) -> Vec<PeerId> {
+        self.peers_added_within_at(window, std::time::Instant::now())
+    }
+
+    /// Returns the peers that were added via [`Self::add_peer_kind`] within the window before
+    /// `now`.
+    fn peers_added_within_at(&self, window: Duration, now: std::time::Instant) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| {
+                peer.added_at
+                    .map_or(false, |added_at| now.saturating_duration_since(added_at) <= window)
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
     /// Removes the tracked node from the set.
     pub(crate) fn remove_peer(&mut self, peer_id: PeerId) {
         let Entry::Occupied(entry) = self.peers.entry(peer_id) else { return };
@@ -588,7 +696,8 @@ impl PeersManager {
         let mut peer = entry.remove();
 
         trace!(target : "net::peers",  ?peer_id, "remove discovered node");
-        self.queued_actions.push_back(PeerAction::PeerRemoved(peer_id));
+        self.queued_actions
+            .push_back(PeerAction::PeerRemoved { peer_id, reason: PeerRemovalReason::Manual });
 
         if peer.state.is_connected() {
             debug!(target : "net::peers",  ?peer_id, "disconnecting on remove from discovery");
@@ -596,7 +705,7 @@ impl PeersManager {
             // was disconnected, this prevents the case where the session is scheduled for
             // disconnect but the node is immediately rediscovered, See also
             // [`Self::on_disconnected()`]
-            peer.remove_after_disconnect = true;
+            peer.remove_after_disconnect = Some(PeerRemovalReason::Manual);
             peer.state.disconnect();
             self.peers.insert(peer_id, peer);
             self.queued_actions.push_back(PeerAction::Disconnect {
@@ -606,6 +715,22 @@ impl PeersManager {
         }
     }
 
+    /// Removes the peer from the set with the given reason once its active session was gracefully
+    /// closed, see [`Self::on_active_session_gracefully_closed`].
+    ///
+    /// Trusted peers and peers without an active session are kept.
+    pub(crate) fn remove_peer_after_disconnect(
+        &mut self,
+        peer_id: PeerId,
+        reason: PeerRemovalReason,
+    ) {
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            if peer.state.is_connected() && !peer.is_trusted() {
+                peer.remove_after_disconnect = Some(reason);
+            }
+        }
+    }
+
     /// Removes the tracked node from the trusted set.
     pub(crate) fn remove_peer_from_trusted_set(&mut self, peer_id: PeerId) {
         let Entry::Occupied(mut entry) = self.peers.entry(peer_id) else { return };
@@ -664,6 +789,10 @@ impl PeersManager {
     fn fill_outbound_slots(&mut self) {
         self.tick();
 
+        if self.outbound_disabled {
+            return
+        }
+
         // as long as there a slots available try to fill them with the best peers
         while self.connection_info.has_out_capacity() {
             let action = {
@@ -714,6 +843,9 @@ impl PeersManager {
                     PeerCommand::GetPeers(tx) => {
                         let _ = tx.send(self.iter_peers().collect());
                     }
+                    PeerCommand::GetPeersAddedWithin(window, tx) => {
+                        let _ = tx.send(self.peers_added_within(window));
+                    }
                 }
             }
 
@@ -781,6 +913,16 @@ pub struct ConnectionInfo {
 // === impl ConnectionInfo ===
 
 impl ConnectionInfo {
+    /// Returns the maximum allowed outbound connections.
+    pub fn max_outbound(&self) -> usize {
+        self.max_outbound
+    }
+
+    /// Returns the maximum allowed inbound connections.
+    pub fn max_inbound(&self) -> usize {
+        self.max_inbound
+    }
+
     ///  Returns `true` if there's still capacity for a new outgoing connection.
     fn has_out_capacity(&self) -> bool {
         self.num_outbound < self.max_outbound
@@ -838,14 +980,16 @@ pub struct Peer {
     state: PeerConnectionState,
     /// The [`ForkId`] that the peer announced via discovery.
     fork_id: Option<ForkId>,
-    /// Whether the entry should be removed after an existing session was terminated.
-    remove_after_disconnect: bool,
+    /// Why the entry should be removed after an existing session was terminated, if it should.
+    remove_after_disconnect: Option<PeerRemovalReason>,
     /// The kind of peer
     kind: PeerKind,
     /// Whether the peer is currently backed off.
     backed_off: bool,
     /// Counts number of times the peer was backed off due to a severe [BackoffKind].
     severe_backoff_counter: u32,
+    /// When the peer was last added via [`PeersManager::add_peer_kind`], if ever.
+    added_at: Option<std::time::Instant>,
 }
 
 // === impl Peer ===
@@ -865,10 +1009,11 @@ impl Peer {
             state,
             reputation: DEFAULT_REPUTATION,
             fork_id: None,
-            remove_after_disconnect: false,
+            remove_after_disconnect: None,
             kind: Default::default(),
             backed_off: false,
             severe_backoff_counter: 0,
+            added_at: None,
         }
     }
 
@@ -1006,6 +1151,8 @@ pub(crate) enum PeerCommand {
     GetPeer(PeerId, oneshot::Sender<Option<Peer>>),
     /// Get node information on all peers
     GetPeers(oneshot::Sender<Vec<NodeRecord>>),
+    /// Get the peers that were added within the given window
+    GetPeersAddedWithin(Duration, oneshot::Sender<Vec<PeerId>>),
 }
 
 /// Actions the peer manager can trigger.
@@ -1037,7 +1184,24 @@ pub enum PeerAction {
     /// Emit peerAdded event
     PeerAdded(PeerId),
     /// Emit peerRemoved event
-    PeerRemoved(PeerId),
+    PeerRemoved { peer_id: PeerId, reason: PeerRemovalReason },
+}
+
+/// Why a peer was removed from the peer set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerRemovalReason {
+    /// A session to the peer failed with a fatal error, like a protocol mismatch, so the peer was
+    /// banned because we'll never be able to establish a connection to it.
+    Banned,
+    /// The session to the peer was closed after the peer exceeded the maximum number of protocol
+    /// violations, like malformed messages, for which it was also banned from discovery.
+    ProtocolViolation,
+    /// The session to the peer was closed after the peer didn't respond to a request in time.
+    Timeout,
+    /// The peer was removed on request, or because it's on an incompatible fork.
+    Manual,
+    /// The session to a peer that was only tracked while connected was closed.
+    Disconnected,
 }
 
 /// Config type for initiating a [`PeersManager`] instance
@@ -1228,7 +1392,7 @@ mod test {
         peers::{
             manager::{ConnectionInfo, PeerBackoffDurations, PeerConnectionState},
             reputation::DEFAULT_REPUTATION,
-            PeerAction,
+            PeerAction, PeerRemovalReason,
         },
         session::PendingSessionHandshakeError,
         PeersConfig,
@@ -1291,6 +1455,33 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_peers_added_within() {
+        let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 1, 2)), 8008);
+        let mut peers = PeersManager::default();
+
+        let first = PeerId::random();
+        let second = PeerId::random();
+        peers.add_peer(first, socket_addr, None);
+        peers.add_peer(second, socket_addr, None);
+
+        // pretend the second peer was added 30s after the first one
+        let added = peers.peers[&first].added_at.unwrap();
+        peers.peers.get_mut(&second).unwrap().added_at = Some(added + Duration::from_secs(30));
+
+        let now = added + Duration::from_secs(35);
+        assert_eq!(peers.peers_added_within_at(Duration::from_secs(10), now), vec![second]);
+
+        let mut recent = peers.peers_added_within_at(Duration::from_secs(40), now);
+        recent.sort_unstable();
+        let mut expected = vec![first, second];
+        expected.sort_unstable();
+        assert_eq!(recent, expected);
+
+        let now = added + Duration::from_secs(100);
+        assert!(peers.peers_added_within_at(Duration::from_secs(10), now).is_empty());
+    }
+
     #[tokio::test]
     async fn test_ban() {
         let peer = PeerId::random();
@@ -1554,8 +1745,9 @@ mod test {
         );
 
         match event!(peers) {
-            PeerAction::PeerRemoved(peer_id) => {
+            PeerAction::PeerRemoved { peer_id, reason } => {
                 assert_eq!(peer_id, peer);
+                assert_eq!(reason, PeerRemovalReason::Banned);
             }
             _ => unreachable!(),
         }
@@ -1610,8 +1802,9 @@ mod test {
         );
 
         match event!(peers) {
-            PeerAction::PeerRemoved(peer_id) => {
+            PeerAction::PeerRemoved { peer_id, reason } => {
                 assert_eq!(peer_id, peer);
+                assert_eq!(reason, PeerRemovalReason::Banned);
             }
             _ => unreachable!(),
         }
@@ -1773,8 +1966,9 @@ mod test {
         peers.remove_peer(peer);
 
         match event!(peers) {
-            PeerAction::PeerRemoved(peer_id) => {
+            PeerAction::PeerRemoved { peer_id, reason } => {
                 assert_eq!(peer_id, peer);
+                assert_eq!(reason, PeerRemovalReason::Manual);
             }
             _ => unreachable!(),
         }
@@ -2039,10 +2233,44 @@ mod test {
         peers.on_incoming_session_established(peer_id, addr);
         let peer = peers.peers.get(&peer_id).unwrap();
         assert_eq!(peer.state, PeerConnectionState::In);
-        assert!(peer.remove_after_disconnect);
+        assert_eq!(peer.remove_after_disconnect, Some(PeerRemovalReason::Disconnected));
 
         peers.on_active_session_gracefully_closed(peer_id);
-        assert!(peers.peers.get(&peer_id).is_none())
+        assert!(peers.peers.get(&peer_id).is_none());
+        assert!(matches!(
+            peers.queued_actions.back(),
+            Some(PeerAction::PeerRemoved { peer_id: id, reason: PeerRemovalReason::Disconnected })
+                if *id == peer_id
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_remove_peer_after_disconnect() {
+        let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 1, 2)), 8009);
+        let mut peers = PeersManager::default();
+
+        // peers without an active session are kept
+        let unconnected = PeerId::random();
+        peers.add_peer(unconnected, addr, None);
+        peers.remove_peer_after_disconnect(unconnected, PeerRemovalReason::Timeout);
+        assert_eq!(peers.peers[&unconnected].remove_after_disconnect, None);
+
+        for reason in [PeerRemovalReason::Timeout, PeerRemovalReason::ProtocolViolation] {
+            let peer_id = PeerId::random();
+            peers.on_incoming_pending_session(addr.ip()).unwrap();
+            peers.on_incoming_session_established(peer_id, addr);
+            // the discovered peer would be kept after the session was closed otherwise
+            peers.add_peer(peer_id, addr, None);
+            peers.remove_peer_after_disconnect(peer_id, reason);
+
+            peers.on_active_session_gracefully_closed(peer_id);
+            assert!(peers.peers.get(&peer_id).is_none());
+            assert!(matches!(
+                peers.queued_actions.back(),
+                Some(PeerAction::PeerRemoved { peer_id: id, reason: removed })
+                    if *id == peer_id && *removed == reason
+            ));
+        }
     }
 
     #[tokio::test]
@@ -2055,7 +2283,7 @@ mod test {
         peers.on_incoming_session_established(peer_id, addr);
         let peer = peers.peers.get(&peer_id).unwrap();
         assert_eq!(peer.state, PeerConnectionState::In);
-        assert!(peer.remove_after_disconnect);
+        assert_eq!(peer.remove_after_disconnect, Some(PeerRemovalReason::Disconnected));
 
         // trigger discovery manually while the peer is still connected
         peers.add_peer(peer_id, addr, None);
@@ -2064,6 +2292,6 @@ mod test {
 
         let peer = peers.peers.get(&peer_id).unwrap();
         assert_eq!(peer.state, PeerConnectionState::Idle);
-        assert!(!peer.remove_after_disconnect);
+        assert_eq!(peer.remove_after_disconnect, None);
     }
 }
@@ -1,11 +1,13 @@
 //! Peer related implementations
 
+mod blacklist;
 mod manager;
 mod reputation;
 
+pub use blacklist::{BlacklistError, PeerBlacklist};
 pub(crate) use manager::{InboundConnectionError, PeerAction, PeersManager};
-pub use manager::{Peer, PeersConfig, PeersHandle};
-pub use reputation::ReputationChangeWeights;
+pub use manager::{Peer, PeerRemovalReason, PeersConfig, PeersHandle};
+pub use reputation::{ReputationChangeWeights, ReputationDecay};
 pub use reth_network_api::PeerKind;
 
 /// Maximum number of available slots for outbound sessions.
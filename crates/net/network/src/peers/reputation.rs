@@ -1,6 +1,7 @@
 //! Peer reputation management
 
 use reth_network_api::{Reputation, ReputationChangeKind};
+use std::time::Duration;
 
 /// The default reputation of a peer
 pub(crate) const DEFAULT_REPUTATION: Reputation = 0;
@@ -97,6 +98,17 @@ impl Default for ReputationChangeWeights {
     }
 }
 
+/// Periodic decay of peer reputations toward the default reputation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReputationDecay {
+    /// How often the reputations decay.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub interval: Duration,
+    /// By how much a reputation moves toward the default reputation on every decay.
+    pub amount: Reputation,
+}
+
 /// Represents a change in a peer's reputation.
 #[derive(Debug, Copy, Clone, Default)]
 pub(crate) struct ReputationChange(Reputation);
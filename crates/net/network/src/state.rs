@@ -2,36 +2,89 @@
 
 use crate::{
     cache::LruCache,
+    config::BlockPropagationMode,
     discovery::{Discovery, DiscoveryEvent},
-    fetch::{BlockResponseOutcome, FetchAction, StateFetcher},
+    eth_requests::{APPROX_BODY_SIZE, APPROX_HEADER_SIZE},
+    fetch::{BlockResponseOutcome, DefaultPeerSelector, FetchAction, PeerSelector, StateFetcher},
     message::{
         BlockRequest, NewBlockMessage, PeerRequest, PeerRequestSender, PeerResponse,
         PeerResponseResult,
     },
-    peers::{PeerAction, PeersManager},
+    peers::{PeerAction, PeerBlacklist, PeerRemovalReason, PeersManager, ReputationDecay},
     FetchClient,
 };
+use futures::Stream;
+use parking_lot::RwLock;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 use reth_eth_wire::{
-    capability::Capabilities, BlockHashNumber, DisconnectReason, NewBlockHashes, Status,
+    capability::{Capabilities, Capability},
+    BlockHashNumber, BlockHeaders, DisconnectReason, EthVersion, GetBlockBodies, GetBlockHeaders,
+    NewBlockHashes, NewPooledTransactionHashes, NewPooledTransactionHashes66, Status,
 };
-use reth_network_api::PeerKind;
-use reth_primitives::{ForkId, PeerId, H256};
+use reth_interfaces::p2p::error::{RequestError, RequestResult};
+use reth_network_api::{PeerKind, Reputation, ReputationChangeKind};
+use reth_primitives::{ForkFilter, ForkId, Head, PeerId, TxHash, H256};
 use reth_provider::BlockReader;
+use reth_rlp::Encodable;
+use reth_tasks::TaskSpawner;
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
     net::{IpAddr, SocketAddr},
     num::NonZeroUsize,
+    pin::Pin,
     sync::{
-        atomic::{AtomicU64, AtomicUsize},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
-use tokio::sync::oneshot;
-use tracing::debug;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    time::{Interval, MissedTickBehavior, Sleep},
+};
+use tokio_util::sync::PollSender;
+use tracing::{debug, trace, warn};
 
 /// Cache limit of blocks to keep track of for a single peer.
-const PEER_BLOCK_CACHE_LIMIT: usize = 512;
+pub(crate) const PEER_BLOCK_CACHE_LIMIT: usize = 512;
+
+/// Cache limit of announced transaction hashes to keep track of for a single peer.
+pub(crate) const PEER_TRANSACTION_HASH_CACHE_LIMIT: usize = 1024 * 10;
+
+/// Number of blocks to remember the first announcing peer of, see [`NetworkState::block_source`].
+pub(crate) const BLOCK_SOURCE_CACHE_LIMIT: usize = 1024;
+
+/// Maximum number of disconnects issued per [`NetworkState::poll`] while draining.
+const DRAIN_DISCONNECTS_PER_POLL: usize = 8;
+
+/// The reputation change applied to a peer that announced a block too far beyond our head.
+const FAR_FUTURE_BLOCK_REPUTATION_CHANGE: Reputation = -1024;
+
+/// Minimum interval between two warnings about dropped discovery actions, see
+/// [`NetworkState::set_max_queued_messages`].
+const QUEUE_FULL_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How many times the peer's request timeout we wait for a response before the session is
+/// considered dead.
+///
+/// The session times out requests itself, so this only kicks in if the session is stuck.
+const PENDING_RESPONSE_DEADLINE_FACTOR: u32 = 2;
+
+/// Maximum number of requests buffered for a session whose channel is full, before the session is
+/// closed for not keeping up with the requests.
+const MAX_UNSENT_REQUESTS: usize = 32;
+
+/// The backoff after the first failed attempt to dispatch block requests, doubled with every
+/// further failed attempt.
+const FETCH_BACKOFF_BASE: Duration = Duration::from_millis(50);
+
+/// The maximum backoff between two attempts to dispatch block requests.
+const MAX_FETCH_BACKOFF: Duration = Duration::from_secs(5);
 
 /// The [`NetworkState`] keeps track of the state of all peers in the network.
 ///
@@ -64,6 +117,98 @@ pub struct NetworkState<C> {
     /// The fetcher streams RLPx related requests on a per-peer basis to this type. This type will
     /// then queue in the request and notify the fetcher once the result has been received.
     state_fetcher: StateFetcher,
+    /// Determines how many peers receive a `NewBlock` message in
+    /// [`NetworkState::announce_new_block()`].
+    propagation_fraction: BlockPropagationMode,
+    /// The minimum number of peers a new block is announced to, if that many peers are eligible.
+    min_propagate: usize,
+    /// Whether `NewBlock` messages are sent to the peers with the lowest response latency first.
+    prefer_low_latency_propagation: bool,
+    /// If set, new blocks are propagated to the peers near the block first.
+    propagation_proximity_window: Option<u64>,
+    /// Capacity of the known blocks cache of each [`ActivePeer`].
+    peer_block_cache_limit: NonZeroUsize,
+    /// Capacity of the announced transaction hashes cache of each [`ActivePeer`].
+    peer_transaction_hash_cache_limit: NonZeroUsize,
+    /// Block hash announcements buffered per peer until the next [`NetworkState::poll`], so that
+    /// multiple announcements can be sent in a single `NewBlockHashes` message.
+    pending_block_hashes: HashMap<PeerId, Vec<BlockHashNumber>>,
+    /// Peers that still need to be disconnected, if draining, see [`NetworkState::begin_drain`].
+    drain_queue: Option<VecDeque<PeerId>>,
+    /// Selects a replacement peer for block requests whose peer is no longer available.
+    peer_selector: Box<dyn PeerSelector>,
+    /// Validates the [`ForkId`]s peers announce via discovery, if set.
+    fork_filter: Option<ForkFilter>,
+    /// Whether peers that announce an incompatible [`ForkId`] are banned in the discovery service.
+    ban_incompatible_fork_peers: bool,
+    /// If set, nodes found through discovery are ignored and only trusted peers are connected.
+    trusted_only: bool,
+    /// Sessions of peers that don't support at least this `eth` version are rejected.
+    min_eth_version: EthVersion,
+    /// Selects the peers that receive a `NewBlock` message in
+    /// [`NetworkState::announce_new_block()`].
+    propagation_rng: StdRng,
+    /// If set, the [`StateFetcher`] is not polled, see [`NetworkState::pause_fetching`].
+    fetching_paused: bool,
+    /// Delays polling the [`StateFetcher`] while no peer is available for its requests.
+    fetch_backoff: FetchBackoff,
+    /// If set, new blocks are only announced to these peers.
+    announce_whitelist: Option<HashSet<PeerId>>,
+    /// The peer that first announced a block, see [`NetworkState::block_source`].
+    block_sources: HashMap<H256, PeerId>,
+    /// The blocks of `block_sources` in the order they were first announced.
+    block_source_order: VecDeque<H256>,
+    /// How many entries `block_sources` holds at most.
+    block_source_cache_limit: usize,
+    /// Number of failed dispatch attempts after which [`StateAction::FetchStalled`] is emitted.
+    fetch_stall_threshold: Option<usize>,
+    /// The low and high watermarks of the number of active peers, if configured.
+    peer_count_watermarks: Option<PeerCountWatermarks>,
+    /// Whether the number of active peers dropped below the low watermark and hasn't recovered
+    /// above the high watermark yet.
+    peer_count_low: bool,
+    /// Decays the peer reputations toward the default reputation, if configured.
+    reputation_decay: Option<(Interval, Reputation)>,
+    /// How far beyond our head a block announced via `NewBlock` may be, if limited.
+    max_new_block_gap: Option<u64>,
+    /// Peers and ip ranges that are ignored when found through discovery.
+    blacklist: PeerBlacklist,
+    /// Soft cap of `queued_messages`, above which discovery-derived actions are dropped.
+    max_queued_messages: Option<usize>,
+    /// Number of discovery-derived actions dropped since the last warning.
+    dropped_discovery_actions: usize,
+    /// When the last warning about dropped discovery actions was logged.
+    last_queue_full_warning: Option<Instant>,
+    /// Thresholds that classify the [`NetworkHealth`], see [`NetworkState::is_healthy`].
+    health_thresholds: HealthThresholds,
+    /// When a block request was last answered successfully.
+    last_fetch_progress: Instant,
+    /// Number of bad responses after which a peer is disconnected, if limited.
+    max_bad_responses: Option<usize>,
+    /// Caps of the number of peers connected per subnet, if any.
+    subnet_limits: Option<SubnetLimits>,
+    /// Number of protocol violations after which a peer is banned from discovery, if limited.
+    max_protocol_violations: Option<usize>,
+    /// Limits the rate of block requests sent to each peer, if set.
+    block_request_rate_limit: Option<BlockRequestRateLimit>,
+    /// The estimated response bytes a peer may owe before further requests are deferred, if
+    /// limited.
+    max_outstanding_response_bytes: Option<u64>,
+    /// Interval at which idle peers are checked for and the duration after which a peer is idle.
+    idle_peer_eviction: Option<(Interval, Duration)>,
+    /// Number of incompatible [`ForkId`]s announced via discovery after which the ip is banned.
+    incompatible_fork_ip_ban_threshold: Option<usize>,
+    /// Number of incompatible [`ForkId`]s announced via discovery per ip.
+    incompatible_forks_by_ip: HashMap<IpAddr, usize>,
+    /// Encoded size of a `NewBlock` message above which the block is only announced by hash.
+    max_new_block_size: Option<usize>,
+    /// The snapshot published whenever [`NetworkState::poll`] ran out of work.
+    snapshot: NetworkSnapshotHandle,
+    /// Whether the active peers changed since the snapshot was last published.
+    snapshot_outdated: bool,
+    /// Receives a copy of every action yielded by [`NetworkState::poll`], see
+    /// [`StateActionHandler`].
+    action_listener: Option<mpsc::Sender<StateAction>>,
 }
 
 impl<C> NetworkState<C>
@@ -87,9 +232,253 @@ where
             discovery,
             genesis_hash,
             state_fetcher,
+            propagation_fraction: Default::default(),
+            min_propagate: 0,
+            prefer_low_latency_propagation: false,
+            propagation_proximity_window: None,
+            peer_block_cache_limit: NonZeroUsize::new(PEER_BLOCK_CACHE_LIMIT).unwrap(),
+            peer_transaction_hash_cache_limit: NonZeroUsize::new(PEER_TRANSACTION_HASH_CACHE_LIMIT)
+                .unwrap(),
+            pending_block_hashes: Default::default(),
+            drain_queue: None,
+            peer_selector: Box::<DefaultPeerSelector>::default(),
+            fork_filter: None,
+            ban_incompatible_fork_peers: false,
+            trusted_only: false,
+            min_eth_version: EthVersion::Eth66,
+            propagation_rng: StdRng::from_entropy(),
+            fetching_paused: false,
+            peer_count_watermarks: None,
+            peer_count_low: false,
+            reputation_decay: None,
+            max_new_block_gap: None,
+            blacklist: Default::default(),
+            max_queued_messages: None,
+            dropped_discovery_actions: 0,
+            last_queue_full_warning: None,
+            health_thresholds: Default::default(),
+            last_fetch_progress: Instant::now(),
+            max_bad_responses: None,
+            subnet_limits: None,
+            max_protocol_violations: None,
+            block_request_rate_limit: None,
+            max_outstanding_response_bytes: None,
+            idle_peer_eviction: None,
+            incompatible_fork_ip_ban_threshold: None,
+            incompatible_forks_by_ip: Default::default(),
+            max_new_block_size: None,
+            fetch_backoff: Default::default(),
+            fetch_stall_threshold: None,
+            announce_whitelist: None,
+            block_sources: Default::default(),
+            block_source_order: Default::default(),
+            block_source_cache_limit: BLOCK_SOURCE_CACHE_LIMIT,
+            snapshot: Default::default(),
+            snapshot_outdated: false,
+            action_listener: None,
+        }
+    }
+
+    /// Sets the [`PeerBlacklist`] that is consulted for nodes found through discovery.
+    pub(crate) fn set_blacklist(&mut self, blacklist: PeerBlacklist) {
+        self.blacklist = blacklist;
+    }
+
+    /// Returns the [`PeerBlacklist`], which includes all peers and ips banned in discovery.
+    #[cfg(test)]
+    pub(crate) fn blacklist(&self) -> &PeerBlacklist {
+        &self.blacklist
+    }
+
+    /// Sets the duration without any response or announcement after which a peer is
+    /// disconnected in [`NetworkState::poll`].
+    ///
+    /// Idle peers are checked for once per `timeout`.
+    pub(crate) fn set_idle_peer_timeout(&mut self, timeout: Duration) {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + timeout, timeout);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.idle_peer_eviction = Some((interval, timeout));
+    }
+
+    /// Sets the [`BlockRequestRateLimit`] of newly activated peers.
+    pub(crate) fn set_block_request_rate_limit(&mut self, limit: BlockRequestRateLimit) {
+        self.block_request_rate_limit = Some(limit);
+    }
+
+    /// Sets the estimated response bytes a newly activated peer may owe before further block
+    /// requests to it are deferred.
+    pub(crate) fn set_max_outstanding_response_bytes(&mut self, max: u64) {
+        self.max_outstanding_response_bytes = Some(max);
+    }
+
+    /// Sets the number of protocol violations after which a peer is banned from discovery.
+    pub(crate) fn set_max_protocol_violations(&mut self, max: usize) {
+        self.max_protocol_violations = Some(max);
+    }
+
+    /// Sets the [`SubnetLimits`] enforced for outgoing connections.
+    pub(crate) fn set_subnet_limits(&mut self, limits: SubnetLimits) {
+        self.subnet_limits = Some(limits);
+    }
+
+    /// Sets the number of bad responses after which a peer is disconnected as useless.
+    pub(crate) fn set_max_bad_responses(&mut self, max: usize) {
+        self.max_bad_responses = Some(max);
+    }
+
+    /// Sets the [`HealthThresholds`] used by [`NetworkState::is_healthy`].
+    pub(crate) fn set_health_thresholds(&mut self, thresholds: HealthThresholds) {
+        self.health_thresholds = thresholds;
+    }
+
+    /// Sets the soft cap of buffered [`StateAction`]s.
+    ///
+    /// Once exceeded, actions derived from discovery events are dropped until the buffer was
+    /// drained. Other actions, like disconnects, are always buffered.
+    pub(crate) fn set_max_queued_messages(&mut self, max: usize) {
+        self.max_queued_messages = Some(max);
+    }
+
+    /// Sets how many blocks to remember the first announcing peer of.
+    pub(crate) fn set_block_source_cache_limit(&mut self, limit: usize) {
+        self.block_source_cache_limit = limit;
+        while self.block_source_order.len() > limit {
+            self.evict_oldest_block_source();
         }
     }
 
+    /// Restricts the announcements of new blocks to the given peers, or lifts the restriction if
+    /// `None`.
+    pub(crate) fn set_announce_whitelist(&mut self, whitelist: Option<HashSet<PeerId>>) {
+        self.announce_whitelist = whitelist;
+    }
+
+    /// Sets the number of failed attempts to dispatch block requests after which
+    /// [`StateAction::FetchStalled`] is emitted.
+    pub(crate) fn set_fetch_stall_threshold(&mut self, threshold: usize) {
+        self.fetch_stall_threshold = Some(threshold);
+    }
+
+    /// Sets the encoded size of a `NewBlock` message above which
+    /// [`NetworkState::announce_new_block()`] skips the block, so that it's only announced by
+    /// hash.
+    pub(crate) fn set_max_new_block_size(&mut self, max_size: usize) {
+        self.max_new_block_size = Some(max_size);
+    }
+
+    /// Sets how far beyond the best known block a block announced via `NewBlock` may be, see
+    /// [`NetworkState::best_known_block_number`].
+    ///
+    /// Announcements of blocks further ahead are ignored and the peer is penalized.
+    pub(crate) fn set_max_new_block_gap(&mut self, max_gap: u64) {
+        self.max_new_block_gap = Some(max_gap);
+    }
+
+    /// Sets the [`ReputationDecay`] applied to the peer reputations in [`NetworkState::poll`].
+    pub(crate) fn set_reputation_decay(&mut self, decay: ReputationDecay) {
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + decay.interval, decay.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        self.reputation_decay = Some((interval, decay.amount));
+    }
+
+    /// Sets the [`PeerCountWatermarks`] at which [`StateAction::PeerCountLow`] and
+    /// [`StateAction::PeerCountHealthy`] are emitted.
+    pub(crate) fn set_peer_count_watermarks(&mut self, watermarks: PeerCountWatermarks) {
+        self.peer_count_watermarks = Some(watermarks);
+    }
+
+    /// Seeds the rng that selects the peers that receive a `NewBlock` message, which makes the
+    /// selection reproducible.
+    #[cfg(test)]
+    pub(crate) fn set_propagation_seed(&mut self, seed: u64) {
+        self.propagation_rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the minimum `eth` version a peer must support for its session to be activated.
+    pub(crate) fn set_min_eth_version(&mut self, min_eth_version: EthVersion) {
+        self.min_eth_version = min_eth_version;
+    }
+
+    /// Sets whether nodes found through discovery are ignored, so that only trusted peers added via
+    /// [`NetworkState::add_peer_kind`] are connected.
+    pub(crate) fn set_trusted_only(&mut self, trusted_only: bool) {
+        self.trusted_only = trusted_only;
+    }
+
+    /// Sets the [`ForkFilter`] used to validate the [`ForkId`]s peers announce via discovery.
+    pub(crate) fn set_fork_filter(&mut self, fork_filter: ForkFilter) {
+        self.fork_filter = Some(fork_filter);
+    }
+
+    /// Sets whether peers that announce an incompatible [`ForkId`] are banned in the discovery
+    /// service.
+    pub(crate) fn set_ban_incompatible_fork_peers(&mut self, ban: bool) {
+        self.ban_incompatible_fork_peers = ban;
+    }
+
+    /// Sets the number of incompatible [`ForkId`]s announced via discovery by peers of the same
+    /// ip after which the ip is banned in the discovery service.
+    pub(crate) fn set_incompatible_fork_ip_ban_threshold(&mut self, threshold: usize) {
+        self.incompatible_fork_ip_ban_threshold = Some(threshold);
+    }
+
+    /// Sets the [`PeerSelector`] consulted when a block request can't be sent to its peer.
+    pub(crate) fn set_peer_selector(&mut self, peer_selector: Box<dyn PeerSelector>) {
+        self.peer_selector = peer_selector;
+    }
+
+    /// Sets the number of block requests a peer handles at most at the same time.
+    pub(crate) fn set_max_concurrent_requests_per_peer(&mut self, max: usize) {
+        self.state_fetcher.set_max_concurrent_requests_per_peer(max);
+    }
+
+    /// Sets the [`BlockPropagationMode`] used when announcing new blocks.
+    pub(crate) fn set_propagation_fraction(&mut self, propagation_fraction: BlockPropagationMode) {
+        self.propagation_fraction = propagation_fraction;
+    }
+
+    /// Sets the minimum number of peers [`NetworkState::announce_new_block()`] sends the block to.
+    ///
+    /// The number of peers selected by the [`BlockPropagationMode`] is raised to at least this
+    /// value, capped by the number of eligible peers.
+    pub(crate) fn set_min_propagate(&mut self, min_propagate: usize) {
+        self.min_propagate = min_propagate;
+    }
+
+    /// Sets whether [`NetworkState::announce_new_block()`] prefers the peers with the lowest
+    /// recorded response latency.
+    pub(crate) fn set_prefer_low_latency_propagation(&mut self, prefer: bool) {
+        self.prefer_low_latency_propagation = prefer;
+    }
+
+    /// Sets the window of block numbers in which [`NetworkState::announce_new_block()`] prefers
+    /// the peers whose tracked best block is close to the announced block.
+    pub(crate) fn set_propagation_proximity_window(&mut self, window: Option<u64>) {
+        self.propagation_proximity_window = window;
+    }
+
+    /// Sets the capacity of the known blocks cache for newly activated peers.
+    ///
+    /// A limit of `0` is rejected and the default [`PEER_BLOCK_CACHE_LIMIT`] is used instead.
+    pub(crate) fn set_peer_block_cache_limit(&mut self, limit: usize) {
+        self.peer_block_cache_limit = NonZeroUsize::new(limit).unwrap_or_else(|| {
+            warn!(target: "net", "Peer block cache limit must be non-zero, using default");
+            NonZeroUsize::new(PEER_BLOCK_CACHE_LIMIT).unwrap()
+        });
+    }
+
+    /// Sets the capacity of the announced transaction hashes cache for newly activated peers.
+    ///
+    /// A limit of `0` is rejected and the default [`PEER_TRANSACTION_HASH_CACHE_LIMIT`] is used
+    /// instead.
+    pub(crate) fn set_peer_transaction_hash_cache_limit(&mut self, limit: usize) {
+        self.peer_transaction_hash_cache_limit = NonZeroUsize::new(limit).unwrap_or_else(|| {
+            warn!(target: "net", "Peer transaction hash cache limit must be non-zero, using default");
+            NonZeroUsize::new(PEER_TRANSACTION_HASH_CACHE_LIMIT).unwrap()
+        });
+    }
+
     /// Returns mutable access to the [`PeersManager`]
     pub(crate) fn peers_mut(&mut self) -> &mut PeersManager {
         &mut self.peers_manager
@@ -110,40 +499,461 @@ where
         self.genesis_hash
     }
 
+    /// Sets the genesis hash that the `Status` of newly activated sessions must match.
+    #[cfg(test)]
+    pub(crate) fn set_genesis_hash(&mut self, hash: H256) {
+        self.genesis_hash = hash;
+    }
+
     /// How many peers we're currently connected to.
     pub fn num_active_peers(&self) -> usize {
         self.active_peers.len()
     }
 
+    /// Returns the number of block requests that haven't been answered yet.
+    ///
+    /// This includes the requests sent to the peers and the requests the fetcher hasn't dispatched
+    /// yet.
+    #[allow(dead_code)]
+    pub(crate) fn pending_request_count(&self) -> usize {
+        let sent =
+            self.active_peers.values().map(|peer| peer.pending_responses.len()).sum::<usize>();
+        sent + self.state_fetcher.num_queued_requests()
+    }
+
+    /// Returns a new [`NetworkSnapshotHandle`] that can be cloned and shared.
+    ///
+    /// The snapshot is updated whenever [`NetworkState::poll`] ran out of work and returns
+    /// [`Poll::Pending`].
+    pub(crate) fn snapshot_handle(&self) -> NetworkSnapshotHandle {
+        self.snapshot.clone()
+    }
+
+    /// Publishes the current active peers to the [`NetworkSnapshotHandle`], if they changed since
+    /// the last snapshot.
+    fn publish_snapshot(&mut self) {
+        if !std::mem::take(&mut self.snapshot_outdated) {
+            return
+        }
+        let mut peers = self.active_peer_infos().collect::<Vec<_>>();
+        peers.sort_unstable_by_key(|peer| peer.peer_id);
+        *self.snapshot.inner.write() = NetworkSnapshot { num_active_peers: peers.len(), peers };
+    }
+
+    /// Sets the channel that receives a copy of every action yielded by [`NetworkState::poll`].
+    pub(crate) fn set_action_listener(&mut self, listener: mpsc::Sender<StateAction>) {
+        self.action_listener = Some(listener);
+    }
+
+    /// Sends a copy of the action to the action listener, without waiting for it.
+    fn notify_action_listener(&mut self, action: &StateAction) {
+        let Some(listener) = &self.action_listener else { return };
+        match listener.try_send(action.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                trace!(target: "net", "State action handler is lagging, dropping action")
+            }
+            Err(TrySendError::Closed(_)) => self.action_listener = None,
+        }
+    }
+
+    /// Returns a snapshot of the state's counters.
+    #[allow(dead_code)]
+    pub(crate) fn metrics_snapshot(&self) -> NetworkStateMetrics {
+        NetworkStateMetrics {
+            num_active_peers: self.active_peers.len(),
+            queued_messages: self.queued_messages.len(),
+            inflight_headers_requests: self.state_fetcher.num_inflight_headers_requests(),
+            inflight_bodies_requests: self.state_fetcher.num_inflight_bodies_requests(),
+        }
+    }
+
+    /// Classifies the health of the network based on the number of active peers and whether
+    /// inflight block requests are answered, see [`HealthThresholds`].
+    #[allow(dead_code)]
+    pub(crate) fn is_healthy(&self) -> NetworkHealth {
+        let active_peers = self.active_peers.len();
+        if active_peers == 0 {
+            return NetworkHealth::Isolated
+        }
+
+        let inflight = self.state_fetcher.num_inflight_headers_requests() +
+            self.state_fetcher.num_inflight_bodies_requests();
+        let stalled = inflight > 0 &&
+            self.last_fetch_progress.elapsed() > self.health_thresholds.max_fetch_stall;
+        if active_peers < self.health_thresholds.min_peers || stalled {
+            return NetworkHealth::Degraded { active_peers }
+        }
+
+        NetworkHealth::Healthy
+    }
+
+    /// Returns the reputations of all known peers, ordered by [`PeerId`], so that they can be
+    /// restored via [`NetworkState::import_peer_reputations`] after a restart.
+    #[allow(dead_code)]
+    pub(crate) fn export_peer_reputations(&self) -> Vec<(PeerId, i32)> {
+        let mut reputations = self.peers_manager.reputations().collect::<Vec<_>>();
+        reputations.sort_unstable_by_key(|(peer_id, _)| *peer_id);
+        reputations
+    }
+
+    /// Restores the reputations exported via [`NetworkState::export_peer_reputations`].
+    ///
+    /// Only the reputations of peers that are already known are restored, since the address of
+    /// unknown peers isn't. Returns the number of restored reputations.
+    #[allow(dead_code)]
+    pub(crate) fn import_peer_reputations(
+        &mut self,
+        entries: impl IntoIterator<Item = (PeerId, i32)>,
+    ) -> usize {
+        entries
+            .into_iter()
+            .filter(|(peer_id, reputation)| self.peers_manager.set_reputation(peer_id, *reputation))
+            .count()
+    }
+
+    /// Returns the block data exchanged with all active peers combined.
+    #[allow(dead_code)]
+    pub(crate) fn bandwidth_stats(&self) -> BandwidthStats {
+        self.active_peers.values().fold(BandwidthStats::default(), |mut total, peer| {
+            total.bytes_sent += peer.bandwidth.bytes_sent;
+            total.bytes_received += peer.bandwidth.bytes_received;
+            total
+        })
+    }
+
+    /// Returns the block data exchanged with the given peer, if it's active.
+    #[allow(dead_code)]
+    pub(crate) fn peer_bandwidth_stats(&self, peer_id: &PeerId) -> Option<BandwidthStats> {
+        self.active_peers.get(peer_id).map(|peer| peer.bandwidth)
+    }
+
+    /// Removes and returns all queued [`StateAction`]s, without polling any subsystems.
+    #[cfg(test)]
+    pub(crate) fn drain_queued(&mut self) -> Vec<StateAction> {
+        self.queued_messages.drain(..).collect()
+    }
+
+    /// Stops issuing new block requests to peers, without affecting the sessions.
+    ///
+    /// Requests that are received while paused are buffered until [`NetworkState::resume_fetching`]
+    /// is called. Responses for requests that are already inflight are still handled.
+    #[allow(dead_code)]
+    pub(crate) fn pause_fetching(&mut self) {
+        self.fetching_paused = true;
+    }
+
+    /// Resumes issuing block requests after [`NetworkState::pause_fetching`].
+    #[allow(dead_code)]
+    pub(crate) fn resume_fetching(&mut self) {
+        self.fetching_paused = false;
+    }
+
+    /// Returns an [`ActivePeerInfo`] for every active peer.
+    pub(crate) fn active_peer_infos(&self) -> impl Iterator<Item = ActivePeerInfo> + '_ {
+        self.active_peers.iter().map(|(peer_id, peer)| ActivePeerInfo {
+            peer_id: *peer_id,
+            best_hash: peer.best_hash,
+            best_number: self.state_fetcher.peer_best_block(peer_id).and_then(|(_, number)| number),
+            eth_version: Some(peer.eth_version),
+            remote_addr: peer.remote_addr,
+        })
+    }
+
+    /// Returns the number of active peers per negotiated `eth` version.
+    #[allow(dead_code)]
+    pub(crate) fn version_histogram(&self) -> HashMap<EthVersion, usize> {
+        let mut histogram = HashMap::new();
+        for peer in self.active_peers.values() {
+            *histogram.entry(peer.eth_version).or_default() += 1;
+        }
+        histogram
+    }
+
+    /// Returns the active peer with the highest known block and that block's hash.
+    ///
+    /// Peers whose best block number is unknown rank below all others. Ties are broken by
+    /// selecting the lowest [`PeerId`].
+    #[allow(dead_code)]
+    pub(crate) fn best_peer(&self) -> Option<(PeerId, H256)> {
+        self.active_peers
+            .keys()
+            .filter_map(|peer_id| {
+                let (hash, number) = self.state_fetcher.peer_best_block(peer_id)?;
+                Some((*peer_id, hash, number))
+            })
+            .max_by(|(a_id, _, a_num), (b_id, _, b_num)| {
+                a_num.cmp(b_num).then_with(|| b_id.cmp(a_id))
+            })
+            .map(|(peer_id, hash, _)| (peer_id, hash))
+    }
+
+    /// Estimates the head of the chain as the highest block reported by any active peer.
+    ///
+    /// Returns the hash and number of that block, or `None` without active peers whose best block
+    /// number is known.
+    #[allow(dead_code)]
+    pub(crate) fn estimate_chain_head(&self) -> Option<(H256, u64)> {
+        self.active_peers
+            .keys()
+            .filter_map(|peer_id| {
+                let (hash, number) = self.state_fetcher.peer_best_block(peer_id)?;
+                Some((hash, number?))
+            })
+            .max_by_key(|(_, number)| *number)
+    }
+
+    /// Starts to gracefully drain all active sessions.
+    ///
+    /// This stops initiating new outbound connections and disconnects all active peers with
+    /// [`DisconnectReason::ClientQuitting`], issuing at most a limited number of disconnects per
+    /// [`NetworkState::poll`]. Sessions that are activated while draining are disconnected as well.
+    #[allow(dead_code)]
+    pub(crate) fn begin_drain(&mut self) {
+        self.peers_manager.disable_outbound_connections();
+        self.drain_queue = Some(self.active_peers.keys().copied().collect());
+    }
+
+    /// Returns `true` if there are no active sessions left.
+    #[allow(dead_code)]
+    pub(crate) fn is_drained(&self) -> bool {
+        self.active_peers.is_empty()
+    }
+
+    /// Queues a [`StateAction::PeerCountLow`] or [`StateAction::PeerCountHealthy`] if the number
+    /// of active peers crossed one of the [`PeerCountWatermarks`].
+    fn on_peer_count(&mut self) {
+        let Some(watermarks) = self.peer_count_watermarks else { return };
+        let num_active_peers = self.active_peers.len();
+        if !self.peer_count_low && num_active_peers < watermarks.low {
+            self.peer_count_low = true;
+            self.queued_messages.push_back(StateAction::PeerCountLow(num_active_peers));
+        } else if self.peer_count_low && num_active_peers > watermarks.high {
+            self.peer_count_low = false;
+            self.queued_messages.push_back(StateAction::PeerCountHealthy(num_active_peers));
+        }
+    }
+
+    /// Disconnects all peers that have been idle for longer than `timeout`, except trusted peers.
+    fn evict_idle_peers(&mut self, timeout: Duration) {
+        let idle_peers = self
+            .active_peers
+            .iter()
+            .filter(|(_, peer)| peer.last_activity.elapsed() > timeout)
+            .map(|(peer_id, _)| *peer_id)
+            .filter(|peer_id| self.peers_manager.peer_kind(peer_id) != Some(PeerKind::Trusted))
+            .collect::<Vec<_>>();
+        for peer_id in idle_peers {
+            debug!(target: "net", ?peer_id, "Evicting idle peer");
+            self.disconnect_peer(peer_id, DisconnectReason::UselessPeer);
+        }
+    }
+
+    /// Backs off from polling the [`StateFetcher`] if its requests couldn't be dispatched because
+    /// no peer is available.
+    fn on_fetch_dispatch(&mut self, cx: &mut Context<'_>) {
+        if !self.state_fetcher.is_stalled() {
+            self.fetch_backoff = Default::default();
+            return
+        }
+
+        let attempts = self.fetch_backoff.on_failed_attempt(cx);
+        // only report once
+        if self.fetch_stall_threshold == Some(attempts) {
+            debug!(target: "net", attempts, "No peer available for block requests");
+            self.queued_messages.push_back(StateAction::FetchStalled(attempts));
+        }
+    }
+
+    /// Queues the next batch of disconnects if draining.
+    fn on_drain(&mut self) {
+        let Some(queue) = self.drain_queue.as_mut() else { return };
+        let mut disconnects = 0;
+        while disconnects < DRAIN_DISCONNECTS_PER_POLL {
+            let Some(peer_id) = queue.pop_front() else { break };
+            if !self.active_peers.contains_key(&peer_id) {
+                // already disconnected
+                continue
+            }
+            self.state_fetcher.on_pending_disconnect(&peer_id);
+            self.queued_messages.push_back(StateAction::Disconnect {
+                peer_id,
+                reason: Some(DisconnectReason::ClientQuitting),
+            });
+            disconnects += 1;
+        }
+    }
+
+    /// Returns the latency of the last response of every active peer, if any.
+    #[allow(dead_code)]
+    pub(crate) fn peer_latencies(&self) -> Vec<(PeerId, Option<Duration>)> {
+        self.active_peers
+            .iter()
+            .map(|(peer_id, peer)| (*peer_id, peer.last_response_latency))
+            .collect()
+    }
+
+    /// Returns all active peers that advertise the given capability.
+    #[allow(dead_code)]
+    pub(crate) fn peers_by_capability(&self, cap: &Capability) -> Vec<PeerId> {
+        if self.active_peers.is_empty() {
+            return Vec::new()
+        }
+        self.active_peers
+            .iter()
+            .filter(|(_, peer)| peer.capabilities.capabilities().contains(cap))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Returns the active peers that advertised the `snap` capability.
+    #[allow(dead_code)]
+    pub(crate) fn snap_peers(&self) -> Vec<PeerId> {
+        self.active_peers
+            .iter()
+            .filter(|(_, peer)| peer.supports_snap)
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Returns the number of entries evicted from the block caches of all active peers.
+    ///
+    /// A high number relative to the announced blocks indicates that [`PEER_BLOCK_CACHE_LIMIT`]
+    /// is too small.
+    #[allow(dead_code)]
+    pub(crate) fn cache_evictions(&self) -> u64 {
+        self.active_peers.values().map(|peer| peer.blocks.evictions()).sum()
+    }
+
+    /// Returns how long the peer has been connected, if it's an active peer.
+    #[allow(dead_code)]
+    pub(crate) fn connected_duration(&self, peer_id: &PeerId) -> Option<Duration> {
+        self.active_peers.get(peer_id).map(|peer| peer.connected_since.elapsed())
+    }
+
+    /// Disconnects the peer with the given reason.
+    ///
+    /// This is intended for misbehavior that's detected outside of the network, for example
+    /// invalid block bodies.
+    pub(crate) fn disconnect_peer(&mut self, peer_id: PeerId, reason: DisconnectReason) {
+        self.state_fetcher.on_pending_disconnect(&peer_id);
+        self.queued_messages.push_back(StateAction::Disconnect { peer_id, reason: Some(reason) });
+    }
+
     /// Event hook for an activated session for the peer.
     ///
     /// Returns `Ok` if the session is valid, returns an `Err` if the session is not accepted and
-    /// should be rejected.
+    /// should be rejected, in which case a disconnect of the session is queued.
+    ///
+    /// A session for a peer that is already active is rejected without queueing a disconnect,
+    /// since that would close the existing session of the peer.
     pub(crate) fn on_session_activated(
         &mut self,
         peer: PeerId,
+        remote_addr: SocketAddr,
         capabilities: Arc<Capabilities>,
         status: Status,
         request_tx: PeerRequestSender,
         timeout: Arc<AtomicU64>,
-    ) {
-        debug_assert!(!self.active_peers.contains_key(&peer), "Already connected; not possible");
+    ) -> Result<(), SessionActivationError> {
+        if self.active_peers.contains_key(&peer) {
+            // the session manager already disconnects duplicate sessions of a peer
+            return Err(SessionActivationError::AlreadyActive(peer))
+        }
+
+        if !supports_eth_version(&capabilities, self.min_eth_version) {
+            self.queued_messages.push_back(StateAction::Disconnect {
+                peer_id: peer,
+                reason: Some(DisconnectReason::UselessPeer),
+            });
+            return Err(SessionActivationError::UnsupportedEthVersion {
+                peer_id: peer,
+                min_version: self.min_eth_version,
+            })
+        }
+
+        if status.genesis != self.genesis_hash {
+            self.queued_messages.push_back(StateAction::Disconnect {
+                peer_id: peer,
+                reason: Some(DisconnectReason::ProtocolBreach),
+            });
+            return Err(SessionActivationError::MismatchedGenesis {
+                peer_id: peer,
+                expected: self.genesis_hash,
+                got: status.genesis,
+            })
+        }
 
-        // find the corresponding block number
-        let block_number =
-            self.client.block_number(status.blockhash).ok().flatten().unwrap_or_default();
-        self.state_fetcher.new_active_peer(peer, status.blockhash, block_number, timeout);
+        // without a fork filter our fork isn't known yet, so any fork id is accepted
+        if self.is_incompatible_fork(status.forkid) {
+            self.queued_messages.push_back(StateAction::Disconnect {
+                peer_id: peer,
+                reason: Some(DisconnectReason::UselessPeer),
+            });
+            return Err(SessionActivationError::IncompatibleFork {
+                peer_id: peer,
+                fork_id: status.forkid,
+            })
+        }
+
+        // the capabilities include at least the minimum version
+        let eth_version = latest_eth_version(&capabilities).unwrap_or(self.min_eth_version);
+        debug!(target: "net", ?peer, ?eth_version, "Negotiated eth version");
+        let supports_snap = capabilities.supports_snap();
+
+        // find the corresponding block number, if the block is known locally
+        let block_number = self.client.block_number(status.blockhash).ok().flatten();
+        self.state_fetcher.new_active_peer(
+            peer,
+            status.blockhash,
+            block_number,
+            Arc::clone(&timeout),
+        );
+        // the new peer can take the queued block requests right away
+        self.fetch_backoff = Default::default();
+        let unsent_tx = PollSender::new(request_tx.to_session_tx.clone());
 
         self.active_peers.insert(
             peer,
             ActivePeer {
                 best_hash: status.blockhash,
                 capabilities,
+                eth_version,
+                supports_snap,
                 request_tx,
-                pending_response: None,
-                blocks: LruCache::new(NonZeroUsize::new(PEER_BLOCK_CACHE_LIMIT).unwrap()),
+                pending_responses: Vec::new(),
+                timeout,
+                last_response_latency: None,
+                blocks: LruCache::new(self.peer_block_cache_limit),
+                transactions: LruCache::new(self.peer_transaction_hash_cache_limit),
+                bandwidth: BandwidthStats::default(),
+                bad_responses: 0,
+                remote_addr,
+                protocol_violations: 0,
+                last_activity: Instant::now(),
+                connected_since: Instant::now(),
+                rate_limiter: self.block_request_rate_limit.map(TokenBucket::new),
+                deferred_requests: Default::default(),
+                rate_limit_delay: None,
+                outstanding_bytes: 0,
+                max_outstanding_bytes: self.max_outstanding_response_bytes,
+                unsent_requests: Default::default(),
+                unsent_tx,
             },
         );
+        self.snapshot_outdated = true;
+
+        if self.active_peers.len() == 1 {
+            // we were isolated until now
+            self.queued_messages.push_back(StateAction::FirstPeerConnected(peer));
+        }
+
+        if let Some(queue) = self.drain_queue.as_mut() {
+            // we're shutting down, so this session needs to be disconnected as well
+            queue.push_back(peer);
+        }
+
+        Ok(())
     }
 
     /// Event hook for a disconnected session for the given peer.
@@ -151,9 +961,35 @@ where
     /// This will remove the peer from the available set of peers and close all inflight requests.
     pub(crate) fn on_session_closed(&mut self, peer: PeerId) {
         self.active_peers.remove(&peer);
+        self.snapshot_outdated = true;
+        self.reroute_inflight_requests(&peer);
         self.state_fetcher.on_session_closed(&peer);
     }
 
+    /// Sends the inflight block requests of the closed peer to the peers the [`PeerSelector`]
+    /// selects.
+    ///
+    /// Requests no peer is selected for fail once the fetcher drops the peer.
+    fn reroute_inflight_requests(&mut self, peer: &PeerId) {
+        for (request_id, request) in self.state_fetcher.inflight_block_requests(peer) {
+            if let Some(selected) = self.select_replacement_peer(request_id) {
+                trace!(target: "net", ?peer, ?selected, request_id, "Rerouting inflight request");
+                self.handle_block_request(selected, request_id, request);
+            }
+        }
+    }
+
+    /// Closes the sessions of all active peers, see [`NetworkState::on_session_closed`].
+    ///
+    /// Calling this without active peers is a no-op.
+    #[cfg(test)]
+    pub(crate) fn close_all_sessions(&mut self) {
+        let peers = self.active_peers.keys().copied().collect::<Vec<_>>();
+        for peer in peers {
+            self.on_session_closed(peer);
+        }
+    }
+
     /// Starts propagating the new block to peers that haven't reported the block yet.
     ///
     /// This is supposed to be invoked after the block was validated.
@@ -161,70 +997,198 @@ where
     /// > It then sends the block to a small fraction of connected peers (usually the square root of
     /// > the total number of peers) using the `NewBlock` message.
     ///
+    /// The number of peers is determined by the configured [`BlockPropagationMode`]. If an announce
+    /// whitelist is set, only whitelisted peers are considered.
+    ///
+    /// Blocks above the maximum `NewBlock` size aren't sent to any peer, they're only announced
+    /// via [`NetworkState::announce_new_block_hash()`] once fully validated.
+    ///
     /// See also <https://github.com/ethereum/devp2p/blob/master/caps/eth.md>
     pub(crate) fn announce_new_block(&mut self, msg: NewBlockMessage) {
-        // send a `NewBlock` message to a fraction fo the connected peers (square root of the total
-        // number of peers)
-        let num_propagate = (self.active_peers.len() as f64).sqrt() as u64 + 1;
+        let peer_ids = self.plan_announce_new_block(&msg);
+        // advance the rng, so that the next announcement selects different peers
+        let _: u64 = self.propagation_rng.gen();
 
         let number = msg.block.block.header.number;
-        let mut count = 0;
-        for (peer_id, peer) in self.active_peers.iter_mut() {
-            if peer.blocks.contains(&msg.hash) {
-                // skip peers which already reported the block
-                continue
-            }
+        for peer_id in peer_ids {
+            let peer = self.active_peers.get_mut(&peer_id).expect("peer is active");
 
             // Queue a `NewBlock` message for the peer
-            if count < num_propagate {
-                self.queued_messages
-                    .push_back(StateAction::NewBlock { peer_id: *peer_id, block: msg.clone() });
+            self.queued_messages.push_back(StateAction::NewBlock { peer_id, block: msg.clone() });
 
-                // update peer block info
-                if self.state_fetcher.update_peer_block(peer_id, msg.hash, number) {
-                    peer.best_hash = msg.hash;
-                }
+            // update peer block info
+            if self.state_fetcher.update_peer_block(&peer_id, msg.hash, number) {
+                peer.best_hash = msg.hash;
+                self.snapshot_outdated = true;
+            }
+
+            // mark the block as seen by the peer
+            peer.blocks.insert(msg.hash);
+        }
+    }
 
-                // mark the block as seen by the peer
-                peer.blocks.insert(msg.hash);
+    /// Returns the peers that would receive the `NewBlock` message if it was announced via
+    /// [`NetworkState::announce_new_block()`] now.
+    ///
+    /// This neither queues any messages nor advances the propagation rng.
+    pub(crate) fn plan_announce_new_block(&self, msg: &NewBlockMessage) -> Vec<PeerId> {
+        self.select_block_propagation_peers(msg, &mut self.propagation_rng.clone())
+    }
 
-                count += 1;
-            }
+    /// Selects the peers that receive the `NewBlock` message, see
+    /// [`NetworkState::announce_new_block()`].
+    ///
+    /// Returns no peers if the block exceeds the maximum `NewBlock` size.
+    fn select_block_propagation_peers(
+        &self,
+        msg: &NewBlockMessage,
+        rng: &mut StdRng,
+    ) -> Vec<PeerId> {
+        if self.max_new_block_size.map_or(false, |max_size| msg.block.length() > max_size) {
+            trace!(target: "net", hash = ?msg.hash, "Skipping NewBlock announcement of large block");
+            return Vec::new()
+        }
 
-            if count >= num_propagate {
-                break
+        // the peers are sorted first, so that the selection only depends on the rng
+        let mut peer_ids = self
+            .active_peers
+            .keys()
+            .filter(|peer_id| self.is_announce_target(peer_id))
+            .copied()
+            .collect::<Vec<_>>();
+        peer_ids.sort_unstable();
+
+        // send a `NewBlock` message to a fraction of the connected peers
+        let num_propagate = self
+            .propagation_fraction
+            .num_propagate(peer_ids.len())
+            .max(self.min_propagate.min(peer_ids.len()));
+
+        peer_ids.shuffle(rng);
+
+        if self.prefer_low_latency_propagation {
+            // peers without a recorded latency are ranked like the median peer
+            let mut latencies = self
+                .active_peers
+                .values()
+                .filter_map(|peer| peer.last_response_latency)
+                .collect::<Vec<_>>();
+            latencies.sort_unstable();
+            let median = latencies.get(latencies.len() / 2).copied().unwrap_or_default();
+
+            // the sort is stable, so equally fast peers keep their random order
+            peer_ids.sort_by_key(|peer_id| {
+                self.active_peers[peer_id].last_response_latency.unwrap_or(median)
+            });
+        }
+
+        if let Some(window) = self.propagation_proximity_window {
+            let number = msg.block.block.header.number;
+            // the sort is stable, so the peers keep their order within and outside the window
+            peer_ids.sort_by_key(|peer_id| {
+                let best_number =
+                    self.state_fetcher.peer_best_block(peer_id).and_then(|(_, number)| number);
+                best_number.map_or(true, |best_number| best_number.abs_diff(number) > window)
+            });
+        }
+
+        peer_ids
+            .into_iter()
+            // skip peers which already reported the block
+            .filter(|peer_id| !self.active_peers[peer_id].blocks.contains(&msg.hash))
+            .take(num_propagate)
+            .collect()
+    }
+
+    /// Sends a `NewBlock` message to _all_ active peers, including peers that already reported the
+    /// block.
+    ///
+    /// Unlike [`NetworkState::announce_new_block()`] this neither skips peers that have seen the
+    /// block nor restricts propagation to a fraction of the peers. This is useful for recovering
+    /// from propagation gaps.
+    #[allow(dead_code)]
+    pub(crate) fn force_announce_new_block(&mut self, msg: NewBlockMessage) {
+        let number = msg.block.block.header.number;
+        for (peer_id, peer) in self.active_peers.iter_mut() {
+            self.queued_messages
+                .push_back(StateAction::NewBlock { peer_id: *peer_id, block: msg.clone() });
+
+            // update peer block info
+            if self.state_fetcher.update_peer_block(peer_id, msg.hash, number) {
+                peer.best_hash = msg.hash;
+                self.snapshot_outdated = true;
             }
+
+            // mark the block as seen by the peer
+            peer.blocks.insert(msg.hash);
         }
     }
 
     /// Completes the block propagation process started in [`NetworkState::announce_new_block()`]
     /// but sending `NewBlockHash` broadcast to all peers that haven't seen it yet.
+    ///
+    /// Announcements are buffered per peer and flushed as a single `NewBlockHashes` message on the
+    /// next [`NetworkState::poll`]. If an announce whitelist is set, only whitelisted peers are
+    /// considered.
     pub(crate) fn announce_new_block_hash(&mut self, msg: NewBlockMessage) {
         let number = msg.block.block.header.number;
-        let hashes = NewBlockHashes(vec![BlockHashNumber { hash: msg.hash, number }]);
+        let hash = BlockHashNumber { hash: msg.hash, number };
         for (peer_id, peer) in self.active_peers.iter_mut() {
             if peer.blocks.contains(&msg.hash) {
                 // skip peers which already reported the block
                 continue
             }
+            if self.announce_whitelist.as_ref().map_or(false, |peers| !peers.contains(peer_id)) {
+                continue
+            }
 
             if self.state_fetcher.update_peer_block(peer_id, msg.hash, number) {
                 peer.best_hash = msg.hash;
+                self.snapshot_outdated = true;
             }
 
-            self.queued_messages.push_back(StateAction::NewBlockHashes {
-                peer_id: *peer_id,
-                hashes: hashes.clone(),
-            });
+            self.pending_block_hashes.entry(*peer_id).or_default().push(hash.clone());
+        }
+    }
+
+    /// Returns `true` if new blocks may be announced to the peer, see
+    /// [`NetworkState::set_announce_whitelist`].
+    fn is_announce_target(&self, peer_id: &PeerId) -> bool {
+        self.announce_whitelist.as_ref().map_or(true, |peers| peers.contains(peer_id))
+    }
+
+    /// Queues a single `NewBlockHashes` message for every peer with buffered block hash
+    /// announcements.
+    fn flush_pending_block_hashes(&mut self) {
+        for (peer_id, hashes) in self.pending_block_hashes.drain() {
+            if !self.active_peers.contains_key(&peer_id) {
+                // session closed in the meantime
+                continue
+            }
+            self.queued_messages
+                .push_back(StateAction::NewBlockHashes { peer_id, hashes: NewBlockHashes(hashes) });
         }
     }
 
     /// Updates the block information for the peer.
+    ///
+    /// If the announced block number is lower than the block number previously tracked for the
+    /// peer, a [`StateAction::PeerBlockRegressed`] is queued.
     pub(crate) fn update_peer_block(&mut self, peer_id: &PeerId, hash: H256, number: u64) {
         if let Some(peer) = self.active_peers.get_mut(peer_id) {
             peer.best_hash = hash;
+            self.snapshot_outdated = true;
         }
-        self.state_fetcher.update_peer_block(peer_id, hash, number);
+        if let Some((_, Some(old_number))) = self.state_fetcher.peer_best_block(peer_id) {
+            if number < old_number {
+                self.queued_messages.push_back(StateAction::PeerBlockRegressed {
+                    peer_id: *peer_id,
+                    old_number,
+                    new_number: number,
+                });
+            }
+        }
+        self.state_fetcher.update_peer_block(peer_id, hash, number);
     }
 
     /// Invoked when a new [`ForkId`] is activated.
@@ -232,36 +1196,228 @@ where
         self.discovery.update_fork_id(fork_id)
     }
 
+    /// Updates the head of the [`ForkFilter`] that validates the [`ForkId`]s of discovered peers.
+    pub(crate) fn update_fork_filter_head(&mut self, head: Head) {
+        if let Some(fork_filter) = self.fork_filter.as_mut() {
+            fork_filter.set_head(head);
+        }
+    }
+
+    /// Returns `true` if the [`ForkId`] is not compatible with our fork.
+    ///
+    /// Without a [`ForkFilter`] all fork ids are considered compatible.
+    fn is_incompatible_fork(&self, fork_id: ForkId) -> bool {
+        self.fork_filter.as_ref().map_or(false, |filter| filter.validate(fork_id).is_err())
+    }
+
+    /// Returns the highest of our head and the best blocks of all active peers except the given
+    /// one.
+    ///
+    /// While syncing, our head lags behind the tip the peers announce, so the other peers' best
+    /// blocks are taken into account. The announcing peer itself is excluded, so that it can't
+    /// raise the limit for its own announcements.
+    fn best_known_block_number(&self, peer_id: &PeerId) -> u64 {
+        let head = self.client.best_block_number().unwrap_or_default();
+        self.active_peers
+            .keys()
+            .filter(|id| *id != peer_id)
+            .filter_map(|id| self.state_fetcher.peer_best_block(id)?.1)
+            .fold(head, u64::max)
+    }
+
     /// Invoked after a `NewBlock` message was received by the peer.
     ///
-    /// This will keep track of blocks we know a peer has
-    pub(crate) fn on_new_block(&mut self, peer_id: PeerId, hash: H256) {
+    /// This will keep track of blocks we know a peer has.
+    ///
+    /// Returns `false` if the block is too far beyond the best known block, in which case the
+    /// announcement should be ignored and the peer is penalized.
+    pub(crate) fn on_new_block(&mut self, peer_id: PeerId, hash: H256, number: u64) -> bool {
+        if let Some(max_gap) = self.max_new_block_gap {
+            let best = self.best_known_block_number(&peer_id);
+            if number > best.saturating_add(max_gap) {
+                debug!(target: "net", ?peer_id, ?hash, number, best, "Block announced too far ahead");
+                self.peers_manager.apply_reputation_change(
+                    &peer_id,
+                    ReputationChangeKind::Other(FAR_FUTURE_BLOCK_REPUTATION_CHANGE),
+                );
+                return false
+            }
+        }
+
         // Mark the blocks as seen
         if let Some(peer) = self.active_peers.get_mut(&peer_id) {
             peer.blocks.insert(hash);
+            peer.last_activity = Instant::now();
+        }
+        self.record_block_source(peer_id, hash);
+        true
+    }
+
+    /// Returns the peer that first announced the block, if it's still remembered.
+    #[allow(dead_code)]
+    pub(crate) fn block_source(&self, hash: &H256) -> Option<PeerId> {
+        self.block_sources.get(hash).copied()
+    }
+
+    /// Remembers the peer as the source of the block, unless another peer announced it earlier.
+    ///
+    /// Beyond the configured limit, the oldest entry is evicted.
+    fn record_block_source(&mut self, peer_id: PeerId, hash: H256) {
+        if self.block_source_cache_limit == 0 || self.block_sources.contains_key(&hash) {
+            return
+        }
+        if self.block_source_order.len() >= self.block_source_cache_limit {
+            self.evict_oldest_block_source();
+        }
+        self.block_sources.insert(hash, peer_id);
+        self.block_source_order.push_back(hash);
+    }
+
+    /// Forgets the source of the block that was announced first.
+    fn evict_oldest_block_source(&mut self) {
+        if let Some(hash) = self.block_source_order.pop_front() {
+            self.block_sources.remove(&hash);
+        }
+    }
+
+    /// Invoked for a `NewPooledTransactionHashes` announcement of the peer.
+    ///
+    /// This will keep track of transactions we know a peer has.
+    pub(crate) fn on_new_pooled_tx_hashes(&mut self, peer_id: PeerId, hashes: Vec<H256>) {
+        if let Some(peer) = self.active_peers.get_mut(&peer_id) {
+            peer.transactions.extend(hashes);
+            peer.last_activity = Instant::now();
+        }
+    }
+
+    /// Returns `true` if the peer is known to have the transaction.
+    #[cfg(test)]
+    pub(crate) fn peer_knows_tx(&self, peer_id: &PeerId, hash: &H256) -> bool {
+        self.active_peers.get(peer_id).map_or(false, |peer| peer.transactions.contains(hash))
+    }
+
+    /// Announces the transactions to all active peers that don't know them yet.
+    ///
+    /// The transactions are marked as known by the peers they're announced to. `eth/68` peers are
+    /// skipped, because their announcements require the types and sizes of the transactions.
+    #[allow(dead_code)]
+    pub(crate) fn broadcast_transactions(&mut self, txs: Vec<TxHash>) {
+        for (peer_id, peer) in self.active_peers.iter_mut() {
+            if peer.eth_version >= EthVersion::Eth68 {
+                continue
+            }
+
+            let hashes = txs
+                .iter()
+                .filter(|hash| !peer.transactions.contains(*hash))
+                .copied()
+                .collect::<Vec<_>>();
+            if hashes.is_empty() {
+                continue
+            }
+
+            peer.transactions.extend(hashes.iter().copied());
+            self.queued_messages.push_back(StateAction::PooledTransactions {
+                peer_id: *peer_id,
+                hashes: NewPooledTransactionHashes::Eth66(NewPooledTransactionHashes66(hashes)),
+            });
         }
     }
 
     /// Invoked for a `NewBlockHashes` broadcast message.
     pub(crate) fn on_new_block_hashes(&mut self, peer_id: PeerId, hashes: Vec<BlockHashNumber>) {
         // Mark the blocks as seen
+        for hash in &hashes {
+            self.record_block_source(peer_id, hash.hash);
+        }
         if let Some(peer) = self.active_peers.get_mut(&peer_id) {
             peer.blocks.extend(hashes.into_iter().map(|b| b.hash));
+            peer.last_activity = Instant::now();
         }
     }
 
     /// Bans the [`IpAddr`] in the discovery service.
-    pub(crate) fn ban_ip_discovery(&self, ip: IpAddr) {
+    pub(crate) fn ban_ip_discovery(&mut self, ip: IpAddr) {
         debug!(target: "net", ?ip, "Banning discovery");
+        self.blacklist.insert_ip(ip);
         self.discovery.ban_ip(ip)
     }
 
     /// Bans the [`PeerId`] and [`IpAddr`] in the discovery service.
-    pub(crate) fn ban_discovery(&self, peer_id: PeerId, ip: IpAddr) {
+    pub(crate) fn ban_discovery(&mut self, peer_id: PeerId, ip: IpAddr) {
         debug!(target: "net", ?peer_id, ?ip, "Banning discovery");
+        self.blacklist.insert_peer(peer_id);
+        self.blacklist.insert_ip(ip);
         self.discovery.ban(peer_id, ip)
     }
 
+    /// Lifts the ban of the [`PeerId`] in the discovery service.
+    pub(crate) fn unban_discovery(&mut self, peer_id: PeerId) {
+        debug!(target: "net", ?peer_id, "Unbanning discovery");
+        self.blacklist.remove_peer(&peer_id);
+        self.discovery.unban_peer(peer_id)
+    }
+
+    /// Lifts the ban of the [`IpAddr`] in the discovery service.
+    pub(crate) fn unban_ip_discovery(&mut self, ip: IpAddr) {
+        debug!(target: "net", ?ip, "Unbanning discovery");
+        self.blacklist.remove_ip(&ip);
+        self.discovery.unban_ip(ip)
+    }
+
+    /// Lifts the ban of the peer, both in the discovery service and the peers manager.
+    #[allow(dead_code)]
+    pub(crate) fn unban_peer(&mut self, peer_id: PeerId) {
+        self.peers_manager.unban_peer(peer_id);
+        self.unban_discovery(peer_id)
+    }
+
+    /// Lifts the ban of the ip address, both in the discovery service and the peers manager.
+    #[allow(dead_code)]
+    pub(crate) fn unban_ip(&mut self, ip: IpAddr) {
+        self.peers_manager.unban_ip(ip);
+        self.unban_ip_discovery(ip)
+    }
+
+    /// Returns all peers that are banned, either in the discovery service or by the peers
+    /// manager.
+    #[allow(dead_code)]
+    pub(crate) fn banned_peers(&self) -> Vec<PeerId> {
+        let mut peers =
+            self.blacklist.peers().chain(self.peers_manager.banned_peers()).collect::<Vec<_>>();
+        peers.sort_unstable();
+        peers.dedup();
+        peers
+    }
+
+    /// Returns all ip addresses that are banned, either in the discovery service or by the peers
+    /// manager.
+    #[allow(dead_code)]
+    pub(crate) fn banned_ips(&self) -> Vec<IpAddr> {
+        let mut ips =
+            self.blacklist.ips().chain(self.peers_manager.banned_ips()).collect::<Vec<_>>();
+        ips.sort_unstable();
+        ips.dedup();
+        ips
+    }
+
+    /// Invoked when the session of the peer reported a protocol violation, like a malformed
+    /// message.
+    ///
+    /// Once the peer exceeds the configured number of violations it is banned from discovery and
+    /// removed from the peer set after its session was closed.
+    pub(crate) fn on_protocol_violation(&mut self, peer_id: PeerId) {
+        let Some(peer) = self.active_peers.get_mut(&peer_id) else { return };
+        peer.protocol_violations += 1;
+        // only ban once
+        if self.max_protocol_violations == Some(peer.protocol_violations) {
+            let ip = peer.remote_addr.ip();
+            self.ban_discovery(peer_id, ip);
+            self.peers_manager
+                .remove_peer_after_disconnect(peer_id, PeerRemovalReason::ProtocolViolation);
+        }
+    }
+
     /// Adds a peer and its address with the given kind to the peerset.
     pub(crate) fn add_peer_kind(&mut self, peer_id: PeerId, kind: PeerKind, addr: SocketAddr) {
         self.peers_manager.add_peer_kind(peer_id, kind, addr, None)
@@ -278,24 +1434,93 @@ where
     fn on_discovery_event(&mut self, event: DiscoveryEvent) {
         match event {
             DiscoveryEvent::Discovered { peer_id, socket_addr, fork_id } => {
-                self.queued_messages.push_back(StateAction::DiscoveredNode {
+                if self.trusted_only {
+                    trace!(target: "net", ?peer_id, "Ignoring discovered node in trusted-only mode");
+                    return
+                }
+                if self.blacklist.is_blacklisted(&peer_id, &socket_addr.ip()) {
+                    trace!(target: "net", ?peer_id, ?socket_addr, "Ignoring blacklisted discovered node");
+                    return
+                }
+                if self.active_peers.contains_key(&peer_id) {
+                    // already connected, nothing to do for the peers manager
+                    trace!(target: "net", ?peer_id, "Ignoring rediscovered active peer");
+                    return
+                }
+                self.queue_discovery_action(StateAction::DiscoveredNode {
                     peer_id,
                     socket_addr,
                     fork_id,
                 });
             }
             DiscoveryEvent::EnrForkId(peer_id, fork_id) => {
-                self.queued_messages
-                    .push_back(StateAction::DiscoveredEnrForkId { peer_id, fork_id });
+                if self.is_incompatible_fork(fork_id) {
+                    debug!(target: "net", ?peer_id, ?fork_id, "Discovered peer on incompatible fork");
+                    if let Some(addr) = self.peers_manager.peer_addr(&peer_id) {
+                        if self.ban_incompatible_fork_peers {
+                            self.ban_discovery(peer_id, addr.ip());
+                        }
+                        self.on_incompatible_fork_ip(addr.ip());
+                    }
+                    self.queued_messages
+                        .push_back(StateAction::IncompatibleFork { peer_id, fork_id });
+                } else {
+                    self.queue_discovery_action(StateAction::DiscoveredEnrForkId {
+                        peer_id,
+                        fork_id,
+                    });
+                }
             }
         }
     }
 
+    /// Counts an incompatible [`ForkId`] announced by a peer with the given ip and bans the ip once
+    /// the configured threshold is reached.
+    fn on_incompatible_fork_ip(&mut self, ip: IpAddr) {
+        let Some(threshold) = self.incompatible_fork_ip_ban_threshold else { return };
+        let count = self.incompatible_forks_by_ip.entry(ip).or_default();
+        *count += 1;
+        // only ban once
+        if *count == threshold {
+            self.ban_ip_discovery(ip);
+        }
+    }
+
+    /// Buffers an action derived from a discovery event, unless the buffer exceeds its soft cap.
+    fn queue_discovery_action(&mut self, action: StateAction) {
+        if self.max_queued_messages.map_or(true, |max| self.queued_messages.len() < max) {
+            self.queued_messages.push_back(action);
+            return
+        }
+
+        self.dropped_discovery_actions += 1;
+        let now = Instant::now();
+        if self
+            .last_queue_full_warning
+            .map_or(true, |last| now.duration_since(last) >= QUEUE_FULL_WARNING_INTERVAL)
+        {
+            warn!(
+                target: "net",
+                dropped = self.dropped_discovery_actions,
+                queued = self.queued_messages.len(),
+                "Too many queued messages, dropping discovery actions"
+            );
+            self.dropped_discovery_actions = 0;
+            self.last_queue_full_warning = Some(now);
+        }
+    }
+
     /// Event hook for new actions derived from the peer management set.
     fn on_peer_action(&mut self, action: PeerAction) {
         match action {
             PeerAction::Connect { peer_id, remote_addr } => {
-                self.queued_messages.push_back(StateAction::Connect { peer_id, remote_addr });
+                if self.exceeds_subnet_limit(peer_id, remote_addr.ip()) {
+                    trace!(target: "net", ?peer_id, ?remote_addr, "Skipping connection, subnet limit reached");
+                    // release the outbound slot the connection was scheduled for
+                    self.peers_manager.on_pending_session_gracefully_closed(&peer_id);
+                } else {
+                    self.queued_messages.push_back(StateAction::Connect { peer_id, remote_addr });
+                }
             }
             PeerAction::Disconnect { peer_id, reason } => {
                 self.state_fetcher.on_pending_disconnect(&peer_id);
@@ -312,76 +1537,242 @@ where
             PeerAction::PeerAdded(peer_id) => {
                 self.queued_messages.push_back(StateAction::PeerAdded(peer_id))
             }
-            PeerAction::PeerRemoved(peer_id) => {
-                self.queued_messages.push_back(StateAction::PeerRemoved(peer_id))
+            PeerAction::PeerRemoved { peer_id, reason } => {
+                self.queued_messages.push_back(StateAction::PeerRemoved { peer_id, reason })
+            }
+            PeerAction::BanPeer { peer_id } => {
+                if let Some(ip) = self.peer_ip(&peer_id) {
+                    self.ban_discovery(peer_id, ip);
+                }
+                if self.active_peers.contains_key(&peer_id) {
+                    self.disconnect_peer(peer_id, DisconnectReason::DisconnectRequested);
+                }
+            }
+            PeerAction::UnBanPeer { peer_id } => {
+                // also lift the ban of the ip that was banned along with the peer
+                if let Some(ip) = self.peer_ip(&peer_id) {
+                    self.unban_ip_discovery(ip);
+                }
+                self.unban_discovery(peer_id)
             }
-            PeerAction::BanPeer { .. } => {}
-            PeerAction::UnBanPeer { .. } => {}
         }
     }
 
-    /// Sends The message to the peer's session and queues in a response.
+    /// Returns the ip address of the peer, preferring the address tracked by the peers manager
+    /// over the remote address of an active session.
+    fn peer_ip(&self, peer_id: &PeerId) -> Option<IpAddr> {
+        self.peers_manager
+            .peer_addr(peer_id)
+            .or_else(|| self.active_peers.get(peer_id).map(|peer| peer.remote_addr))
+            .map(|addr| addr.ip())
+    }
+
+    /// Returns `true` if connecting to the peer would exceed the [`SubnetLimits`] of its subnet.
+    fn exceeds_subnet_limit(&self, peer_id: PeerId, ip: IpAddr) -> bool {
+        let Some(limits) = self.subnet_limits else { return false };
+        let max = if ip.is_ipv4() {
+            limits.max_peers_per_ipv4_subnet
+        } else {
+            limits.max_peers_per_ipv6_subnet
+        };
+        let peers_in_subnet = self
+            .peers_manager
+            .connected_peers()
+            .filter(|(id, addr)| *id != peer_id && is_same_subnet(&addr.ip(), &ip))
+            .count();
+        peers_in_subnet >= max
+    }
+
+    /// Sends a one-off `GetBlockHeaders` request to the peer's session.
     ///
-    /// Caution: this will replace an already pending response. It's the responsibility of the
-    /// caller to select the peer.
-    fn handle_block_request(&mut self, peer: PeerId, request: BlockRequest) {
-        if let Some(ref mut peer) = self.active_peers.get_mut(&peer) {
-            let (request, response) = match request {
-                BlockRequest::GetBlockHeaders(request) => {
-                    let (response, rx) = oneshot::channel();
-                    let request = PeerRequest::GetBlockHeaders { request, response };
-                    let response = PeerResponse::BlockHeaders { response: rx };
-                    (request, response)
-                }
-                BlockRequest::GetBlockBodies(request) => {
-                    let (response, rx) = oneshot::channel();
-                    let request = PeerRequest::GetBlockBodies { request, response };
-                    let response = PeerResponse::BlockBodies { response: rx };
-                    (request, response)
+    /// The response is delivered through the returned receiver directly, so this doesn't interfere
+    /// with the requests of the [`StateFetcher`]. If the peer isn't active the receiver resolves
+    /// with [`RequestError::ConnectionDropped`] right away, and with
+    /// [`RequestError::ChannelClosed`] if its session can't accept the request.
+    #[allow(dead_code)]
+    pub(crate) fn request_block_headers(
+        &mut self,
+        peer: PeerId,
+        request: GetBlockHeaders,
+    ) -> oneshot::Receiver<RequestResult<BlockHeaders>> {
+        let (response, rx) = oneshot::channel();
+        let Some(peer) = self.active_peers.get_mut(&peer) else {
+            let _ = response.send(Err(RequestError::ConnectionDropped));
+            return rx
+        };
+        let len = request.length() as u64;
+        match peer
+            .request_tx
+            .to_session_tx
+            .try_send(PeerRequest::GetBlockHeaders { request, response })
+        {
+            Ok(()) => peer.bandwidth.bytes_sent += len,
+            Err(err) => {
+                if let PeerRequest::GetBlockHeaders { response, .. } = err.into_inner() {
+                    let _ = response.send(Err(RequestError::ChannelClosed));
                 }
-            };
-            let _ = peer.request_tx.to_session_tx.try_send(request);
-            peer.pending_response = Some(response);
+            }
+        }
+        rx
+    }
+
+    /// Sends The message to the peer's session and queues in a response.
+    ///
+    /// The response is tracked alongside any other pending responses of the peer. It's the
+    /// responsibility of the caller to select the peer.
+    ///
+    /// The response is routed back to the [`StateFetcher`] with the `request_id` the fetcher tracks
+    /// the request under.
+    ///
+    /// If the peer's [`BlockRequestRateLimit`] is exceeded, or the response would make the peer owe
+    /// more than the maximum outstanding response bytes, the request is deferred until
+    /// [`NetworkState::poll`] is allowed to send it.
+    fn handle_block_request(&mut self, peer_id: PeerId, request_id: u64, request: BlockRequest) {
+        if let Some(peer) = self.active_peers.get_mut(&peer_id) {
+            let limited = !peer.deferred_requests.is_empty() ||
+                peer.exceeds_outstanding_bytes(&request) ||
+                peer.rate_limiter
+                    .as_mut()
+                    .map(|bucket| !bucket.try_acquire())
+                    .unwrap_or_default();
+            if limited {
+                trace!(target: "net", ?peer_id, "Deferring limited block request");
+                peer.deferred_requests.push_back((request_id, request));
+            } else {
+                peer.send_block_request(request_id, request);
+            }
         }
     }
 
     /// Handle the outcome of processed response, for example directly queue another request.
     fn on_block_response_outcome(&mut self, outcome: BlockResponseOutcome) -> Option<StateAction> {
         match outcome {
-            BlockResponseOutcome::Request(peer, request) => {
-                self.handle_block_request(peer, request);
+            BlockResponseOutcome::Request(peer, request_id, request) => {
+                let peer = if self.active_peers.contains_key(&peer) {
+                    Some(peer)
+                } else {
+                    self.select_replacement_peer(request_id)
+                };
+                if let Some(peer) = peer {
+                    self.handle_block_request(peer, request_id, request);
+                }
+            }
+            BlockResponseOutcome::Partial { peer, request_id, missing } => {
+                if self.active_peers.contains_key(&peer) {
+                    self.handle_block_request(
+                        peer,
+                        request_id,
+                        BlockRequest::GetBlockBodies(GetBlockBodies(missing)),
+                    );
+                } else {
+                    // the bodies received so far are returned instead of dropping the request
+                    self.state_fetcher
+                        .on_request_failed(request_id, RequestError::ConnectionDropped);
+                }
             }
             BlockResponseOutcome::BadResponse(peer, reputation_change) => {
                 self.peers_manager.apply_reputation_change(&peer, reputation_change);
+                if let Some(active) = self.active_peers.get_mut(&peer) {
+                    active.bad_responses += 1;
+                    // only disconnect once
+                    if self.max_bad_responses == Some(active.bad_responses) {
+                        debug!(target: "net", ?peer, "Disconnecting peer after too many bad responses");
+                        self.disconnect_peer(peer, DisconnectReason::UselessPeer);
+                    }
+                }
             }
         }
         None
     }
 
-    /// Invoked when received a response from a connected peer.
+    /// Consults the [`PeerSelector`] for an idle active peer that takes over the inflight request
+    /// with the given id, as the peer it was prepared for is no longer available.
+    fn select_replacement_peer(&mut self, request_id: u64) -> Option<PeerId> {
+        let mut candidates = self
+            .active_peers
+            .keys()
+            .filter(|peer_id| self.state_fetcher.is_idle(peer_id))
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        let selected = self.peer_selector.select(&candidates)?;
+        if !candidates.contains(&selected) {
+            debug!(target: "net", ?selected, "Peer selector returned an unknown peer");
+            return None
+        }
+        self.state_fetcher.reassign_request(request_id, selected).then_some(selected)
+    }
+
+    /// Invoked when received a response from a connected peer to the request with the given id.
     ///
     /// Delegates the response result to the fetcher which may return an outcome specific
     /// instruction that needs to be handled in [Self::on_block_response_outcome]. This could be
     /// a follow-up request or an instruction to slash the peer's reputation.
-    fn on_eth_response(&mut self, peer: PeerId, resp: PeerResponseResult) -> Option<StateAction> {
+    fn on_eth_response(
+        &mut self,
+        peer: PeerId,
+        request_id: u64,
+        resp: PeerResponseResult,
+    ) -> Option<StateAction> {
+        if let Some(active) = self.active_peers.get_mut(&peer) {
+            active.bandwidth.bytes_received += match &resp {
+                PeerResponseResult::BlockHeaders(Ok(headers)) => headers.length() as u64,
+                PeerResponseResult::BlockBodies(Ok(bodies)) => bodies.length() as u64,
+                _ => 0,
+            };
+        }
+        if matches!(
+            resp,
+            PeerResponseResult::BlockHeaders(Ok(_)) | PeerResponseResult::BlockBodies(Ok(_))
+        ) {
+            self.last_fetch_progress = Instant::now();
+        }
+
         match resp {
             PeerResponseResult::BlockHeaders(res) => {
-                let outcome = self.state_fetcher.on_block_headers_response(peer, res)?;
+                let outcome =
+                    self.state_fetcher.on_block_headers_response(peer, request_id, res)?;
                 self.on_block_response_outcome(outcome)
             }
             PeerResponseResult::BlockBodies(res) => {
-                let outcome = self.state_fetcher.on_block_bodies_response(peer, res)?;
+                let outcome = self.state_fetcher.on_block_bodies_response(peer, request_id, res)?;
                 self.on_block_response_outcome(outcome)
             }
             _ => None,
         }
     }
 
+    /// Invoked when the receiver of the peer's inflight block request with the given id was
+    /// dropped.
+    ///
+    /// The request isn't sent if it's still deferred or waiting for room in the session's channel,
+    /// and its response is no longer waited for.
+    fn on_request_cancelled(&mut self, peer_id: PeerId, request_id: u64) {
+        if let Some(peer) = self.active_peers.get_mut(&peer_id) {
+            peer.deferred_requests.retain(|(id, _)| *id != request_id);
+            peer.unsent_requests.retain(|(id, _)| *id != request_id);
+            if let Some(pos) = peer.pending_responses.iter().position(|p| p.id == request_id) {
+                let pending = peer.pending_responses.swap_remove(pos);
+                peer.outstanding_bytes =
+                    peer.outstanding_bytes.saturating_sub(pending.estimated_size);
+            }
+        }
+    }
+
     /// Advances the state
     pub(crate) fn poll(&mut self, cx: &mut Context<'_>) -> Poll<StateAction> {
+        self.on_drain();
+
         loop {
+            self.on_peer_count();
+
+            // coalesce buffered block hash announcements into one message per peer
+            self.flush_pending_block_hashes();
+
             // drain buffered messages
             if let Some(message) = self.queued_messages.pop_front() {
+                self.notify_action_listener(&message);
                 return Poll::Ready(message)
             }
 
@@ -389,44 +1780,90 @@ where
                 self.on_discovery_event(discovery);
             }
 
-            while let Poll::Ready(action) = self.state_fetcher.poll(cx) {
-                match action {
-                    FetchAction::BlockRequest { peer_id, request } => {
-                        self.handle_block_request(peer_id, request)
+            if !self.fetching_paused && self.fetch_backoff.poll_expired(cx) {
+                while let Poll::Ready(action) = self.state_fetcher.poll(cx) {
+                    match action {
+                        FetchAction::BlockRequest { peer_id, request_id, request } => {
+                            self.handle_block_request(peer_id, request_id, request)
+                        }
+                        FetchAction::RequestCancelled { peer_id, request_id } => {
+                            self.on_request_cancelled(peer_id, request_id)
+                        }
                     }
                 }
+                self.on_fetch_dispatch(cx);
+            }
+
+            // send the deferred block requests the limits permit by now
+            for peer in self.active_peers.values_mut() {
+                peer.send_deferred_requests(cx);
+            }
+
+            // send the requests that didn't fit into the session channels once they have room
+            for peer in self.active_peers.values_mut() {
+                peer.send_unsent_requests(cx);
+            }
+
+            // close the sessions that fall too far behind on the requests sent to them
+            let lagging_sessions = self
+                .active_peers
+                .iter_mut()
+                .filter(|(_, peer)| peer.unsent_requests.len() > MAX_UNSENT_REQUESTS)
+                .map(|(peer_id, peer)| {
+                    // dropping the requests resolves their responses, which closes the peer
+                    peer.unsent_requests.clear();
+                    *peer_id
+                })
+                .collect::<Vec<_>>();
+            for peer_id in lagging_sessions {
+                debug!(target: "net", ?peer_id, "Disconnecting peer with too many unsent requests");
+                self.disconnect_peer(peer_id, DisconnectReason::UselessPeer);
             }
 
             // need to buffer results here to make borrow checker happy
             let mut closed_sessions = Vec::new();
+            let mut timed_out_sessions = Vec::new();
             let mut received_responses = Vec::new();
 
             // poll all connected peers for responses
             for (id, peer) in self.active_peers.iter_mut() {
-                if let Some(mut response) = peer.pending_response.take() {
-                    match response.poll(cx) {
-                        Poll::Ready(res) => {
-                            // check if the error is due to a closed channel to the session
-                            if res.err().map(|err| err.is_channel_closed()).unwrap_or_default() {
-                                debug!(
-                                    target : "net",
-                                    ?id,
-                                    "Request canceled, response channel from session closed."
-                                );
-                                // if the channel is closed, this means the peer session is also
-                                // closed, in which case we can invoke the [Self::on_closed_session]
-                                // immediately, preventing followup requests and propagate the
-                                // connection dropped error
-                                closed_sessions.push(*id);
-                            } else {
-                                received_responses.push((*id, res));
-                            }
-                        }
-                        Poll::Pending => {
-                            // not ready yet, store again.
-                            peer.pending_response = Some(response);
+                let mut closed = false;
+                let mut timed_out = false;
+                peer.pending_responses.retain_mut(|pending| match pending.response.poll(cx) {
+                    Poll::Ready(res) => {
+                        peer.outstanding_bytes =
+                            peer.outstanding_bytes.saturating_sub(pending.estimated_size);
+                        // check if the error is due to a closed channel to the session
+                        if res.err().map(|err| err.is_channel_closed()).unwrap_or_default() {
+                            debug!(
+                                target : "net",
+                                ?id,
+                                request_id = pending.id,
+                                "Request canceled, response channel from session closed."
+                            );
+                            // if the channel is closed, this means the peer session is also
+                            // closed, in which case we can invoke the [Self::on_closed_session]
+                            // immediately, preventing followup requests and propagate the
+                            // connection dropped error
+                            closed = true;
+                        } else {
+                            peer.last_response_latency = Some(pending.sent_at.elapsed());
+                            peer.last_activity = Instant::now();
+                            received_responses.push((*id, pending.id, res));
                         }
-                    };
+                        false
+                    }
+                    Poll::Pending => {
+                        // not ready yet, keep it unless it's expired
+                        timed_out |= pending.deadline.as_mut().poll(cx).is_ready();
+                        true
+                    }
+                });
+
+                if closed {
+                    closed_sessions.push(*id);
+                } else if timed_out {
+                    timed_out_sessions.push(*id);
                 }
             }
 
@@ -434,24 +1871,194 @@ where
                 self.on_session_closed(peer)
             }
 
-            for (peer_id, resp) in received_responses {
-                if let Some(action) = self.on_eth_response(peer_id, resp) {
+            for peer_id in timed_out_sessions {
+                debug!(target : "net", ?peer_id, "Pending response exceeded its deadline");
+                // the session is unresponsive, so we treat it as closed
+                self.peers_manager.apply_reputation_change(&peer_id, ReputationChangeKind::Timeout);
+                self.peers_manager
+                    .remove_peer_after_disconnect(peer_id, PeerRemovalReason::Timeout);
+                self.on_session_closed(peer_id);
+                self.queued_messages.push_back(StateAction::Disconnect { peer_id, reason: None });
+            }
+
+            for (peer_id, request_id, resp) in received_responses {
+                if let Some(action) = self.on_eth_response(peer_id, request_id, resp) {
                     self.queued_messages.push_back(action);
                 }
             }
 
+            if let Some((interval, amount)) = self.reputation_decay.as_mut() {
+                if interval.poll_tick(cx).is_ready() {
+                    self.peers_manager.decay_reputations(*amount);
+                }
+            }
+
+            if let Some((interval, timeout)) = self.idle_peer_eviction.as_mut() {
+                if interval.poll_tick(cx).is_ready() {
+                    let timeout = *timeout;
+                    self.evict_idle_peers(timeout);
+                }
+            }
+
             // poll peer manager
             while let Poll::Ready(action) = self.peers_manager.poll(cx) {
                 self.on_peer_action(action);
             }
 
             if self.queued_messages.is_empty() {
+                self.publish_snapshot();
                 return Poll::Pending
             }
         }
     }
 }
 
+impl<C> Stream for NetworkState<C>
+where
+    C: BlockReader + Unpin,
+{
+    type Item = StateAction;
+
+    /// Yields the actions of [`NetworkState::poll`], the stream never ends.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll(cx).map(Some)
+    }
+}
+
+/// Information about an active peer of the [`NetworkState`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivePeerInfo {
+    /// The id of the peer.
+    pub peer_id: PeerId,
+    /// The hash of the best block the peer is known to have.
+    pub best_hash: H256,
+    /// The number of the best block the peer is known to have, if known.
+    pub best_number: Option<u64>,
+    /// The latest `eth` version the peer supports.
+    pub eth_version: Option<EthVersion>,
+    /// The remote address of the peer's session.
+    pub remote_addr: SocketAddr,
+}
+
+/// A snapshot of the active peers of the [`NetworkState`], see [`NetworkSnapshotHandle`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkSnapshot {
+    /// Number of active peers.
+    pub num_active_peers: usize,
+    /// The active peers, sorted by their id.
+    pub peers: Vec<ActivePeerInfo>,
+}
+
+/// A cheaply cloneable, read-only view of the [`NetworkState`].
+///
+/// The [`NetworkSnapshot`] is updated at the end of every poll of the state, so reading it never
+/// waits for the network to make progress.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkSnapshotHandle {
+    inner: Arc<RwLock<NetworkSnapshot>>,
+}
+
+// === impl NetworkSnapshotHandle ===
+
+impl NetworkSnapshotHandle {
+    /// Returns the latest published [`NetworkSnapshot`].
+    pub fn snapshot(&self) -> NetworkSnapshot {
+        self.inner.read().clone()
+    }
+}
+
+/// Thresholds for the number of active peers of the [`NetworkState`].
+///
+/// Dropping below `low` is reported once, as is recovering above `high` afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerCountWatermarks {
+    /// The number of active peers below which the peer count is considered low.
+    pub low: usize,
+    /// The number of active peers above which the peer count is considered healthy again.
+    pub high: usize,
+}
+
+/// Caps of the number of peers of the [`NetworkState`] connected per subnet, which makes it
+/// harder to occupy many peer slots with hosts of the same network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SubnetLimits {
+    /// The maximum number of peers within the same `/24` IPv4 subnet.
+    pub max_peers_per_ipv4_subnet: usize,
+    /// The maximum number of peers within the same `/64` IPv6 subnet.
+    pub max_peers_per_ipv6_subnet: usize,
+}
+
+/// Limits the rate of block requests the [`NetworkState`] sends to a single peer.
+///
+/// Requests exceeding the limit are deferred, not dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockRequestRateLimit {
+    /// The sustained number of requests per second, at least 1.
+    pub requests_per_second: u32,
+    /// The number of requests that can be sent at once after being idle, at least 1.
+    pub burst: u32,
+}
+
+/// Thresholds that classify the [`NetworkHealth`] of the [`NetworkState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HealthThresholds {
+    /// The number of active peers below which the network is degraded.
+    pub min_peers: usize,
+    /// How long inflight block requests may go unanswered before the network is degraded.
+    #[cfg_attr(feature = "serde", serde(with = "humantime_serde"))]
+    pub max_fetch_stall: Duration,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self { min_peers: 3, max_fetch_stall: Duration::from_secs(60) }
+    }
+}
+
+/// The health of the [`NetworkState`], see [`NetworkState::is_healthy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkHealth {
+    /// Enough active peers and block requests are answered.
+    Healthy,
+    /// Too few active peers, or inflight block requests have stalled.
+    Degraded {
+        /// The number of active peers.
+        active_peers: usize,
+    },
+    /// No active peers.
+    Isolated,
+}
+
+/// A snapshot of the counters of the [`NetworkState`], see [`NetworkState::metrics_snapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(dead_code)]
+pub struct NetworkStateMetrics {
+    /// Number of active peers.
+    pub num_active_peers: usize,
+    /// Number of buffered messages that haven't been polled yet.
+    pub queued_messages: usize,
+    /// Number of inflight `GetBlockHeaders` requests.
+    pub inflight_headers_requests: usize,
+    /// Number of inflight `GetBlockBodies` requests.
+    pub inflight_bodies_requests: usize,
+}
+
+/// The block data exchanged with active peers, see [`NetworkState::bandwidth_stats`].
+///
+/// Requests are counted with their encoded length, responses with the encoded length of the
+/// returned headers or bodies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct BandwidthStats {
+    /// Bytes of `GetBlockHeaders` and `GetBlockBodies` requests sent to the peers.
+    pub(crate) bytes_sent: u64,
+    /// Bytes of `BlockHeaders` and `BlockBodies` responses received from the peers.
+    pub(crate) bytes_received: u64,
+}
+
 /// Tracks the state of a Peer with an active Session.
 ///
 /// For example known blocks,so we can decide what to announce.
@@ -459,18 +2066,312 @@ pub(crate) struct ActivePeer {
     /// Best block of the peer.
     pub(crate) best_hash: H256,
     /// The capabilities of the remote peer.
-    #[allow(unused)]
     pub(crate) capabilities: Arc<Capabilities>,
+    /// The latest `eth` version shared with the remote peer.
+    pub(crate) eth_version: EthVersion,
+    /// Whether the remote peer advertised the `snap` capability.
+    pub(crate) supports_snap: bool,
     /// A communication channel directly to the session task.
     pub(crate) request_tx: PeerRequestSender,
-    /// The response receiver for a currently active request to that peer.
-    pub(crate) pending_response: Option<PeerResponse>,
+    /// The response receivers of all currently active requests to that peer.
+    pub(crate) pending_responses: Vec<PendingResponse>,
+    /// The request timeout of the session in milliseconds, shared with the fetcher.
+    pub(crate) timeout: Arc<AtomicU64>,
+    /// How long the peer took to respond to the last request.
+    pub(crate) last_response_latency: Option<Duration>,
     /// Blocks we know the peer has.
     pub(crate) blocks: LruCache<H256>,
+    /// Transactions the peer announced via `NewPooledTransactionHashes`.
+    pub(crate) transactions: LruCache<H256>,
+    /// The block data exchanged with the peer.
+    pub(crate) bandwidth: BandwidthStats,
+    /// Number of bad responses received from the peer.
+    pub(crate) bad_responses: usize,
+    /// The remote address of the session.
+    pub(crate) remote_addr: SocketAddr,
+    /// Number of protocol violations the session reported, like malformed messages.
+    pub(crate) protocol_violations: usize,
+    /// When the peer last responded to a request or announced a block or transaction.
+    pub(crate) last_activity: Instant,
+    /// When the session was activated.
+    pub(crate) connected_since: Instant,
+    /// Limits the rate of block requests sent to the peer, if configured.
+    pub(crate) rate_limiter: Option<TokenBucket>,
+    /// Block requests that exceeded the rate limit and are sent once permitted.
+    pub(crate) deferred_requests: VecDeque<(u64, BlockRequest)>,
+    /// Wakes up the state once the next deferred request can be sent.
+    pub(crate) rate_limit_delay: Option<Pin<Box<Sleep>>>,
+    /// Requests that didn't fit into the session's channel, with the id of the block request.
+    pub(crate) unsent_requests: VecDeque<(u64, PeerRequest)>,
+    /// Sends the unsent requests once the session's channel has capacity again.
+    pub(crate) unsent_tx: PollSender<PeerRequest>,
+    /// The estimated size in bytes of the responses the peer still owes.
+    pub(crate) outstanding_bytes: u64,
+    /// The outstanding bytes block requests may not push the peer above, if limited.
+    pub(crate) max_outstanding_bytes: Option<u64>,
+}
+
+// === impl ActivePeer ===
+
+impl ActivePeer {
+    /// Returns how long to wait for the response to a request before the session is considered
+    /// dead.
+    fn pending_response_timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout.load(Ordering::Relaxed)) *
+            PENDING_RESPONSE_DEADLINE_FACTOR
+    }
+
+    /// Returns `true` if the response to the request would make the peer owe more response bytes
+    /// than permitted.
+    ///
+    /// Requests are always permitted while the peer owes nothing, so requests larger than the
+    /// limit are still sent.
+    fn exceeds_outstanding_bytes(&self, request: &BlockRequest) -> bool {
+        self.max_outstanding_bytes.map_or(false, |max| {
+            self.outstanding_bytes > 0 &&
+                self.outstanding_bytes + estimated_response_size(request) > max
+        })
+    }
+
+    /// Sends the request to the session and tracks its response under the given id.
+    fn send_block_request(&mut self, id: u64, request: BlockRequest) {
+        let estimated_size = estimated_response_size(&request);
+        self.outstanding_bytes += estimated_size;
+        let (request, response) = match request {
+            BlockRequest::GetBlockHeaders(request) => {
+                self.bandwidth.bytes_sent += request.length() as u64;
+                let (response, rx) = oneshot::channel();
+                let request = PeerRequest::GetBlockHeaders { request, response };
+                let response = PeerResponse::BlockHeaders { response: rx };
+                (request, response)
+            }
+            BlockRequest::GetBlockBodies(request) => {
+                self.bandwidth.bytes_sent += request.length() as u64;
+                let (response, rx) = oneshot::channel();
+                let request = PeerRequest::GetBlockBodies { request, response };
+                let response = PeerResponse::BlockBodies { response: rx };
+                (request, response)
+            }
+        };
+        if self.unsent_requests.is_empty() {
+            if let Err(TrySendError::Full(request)) =
+                self.request_tx.to_session_tx.try_send(request)
+            {
+                self.unsent_requests.push_back((id, request));
+            }
+        } else {
+            // keep the order of the requests
+            self.unsent_requests.push_back((id, request));
+        }
+        let deadline = Box::pin(tokio::time::sleep(self.pending_response_timeout()));
+        self.pending_responses.push(PendingResponse {
+            id,
+            response,
+            deadline,
+            sent_at: Instant::now(),
+            estimated_size,
+        });
+    }
+
+    /// Sends the requests that didn't fit into the session's channel, as the channel has room.
+    ///
+    /// While the channel is full, the state is woken up once it has capacity again. A session that
+    /// never makes room is closed once the responses to the requests exceed their deadline, or
+    /// once more than [`MAX_UNSENT_REQUESTS`] requests are buffered.
+    fn send_unsent_requests(&mut self, cx: &mut Context<'_>) {
+        while !self.unsent_requests.is_empty() {
+            match self.unsent_tx.poll_reserve(cx) {
+                Poll::Ready(Ok(())) => {
+                    let (_, request) = self.unsent_requests.pop_front().expect("not empty");
+                    // sending can't fail after the slot was reserved
+                    let _ = self.unsent_tx.send_item(request);
+                }
+                // the closed session is detected through the pending responses
+                Poll::Ready(Err(_)) => {
+                    self.unsent_requests.clear();
+                    return
+                }
+                Poll::Pending => return,
+            }
+        }
+    }
+
+    /// Sends as many deferred requests as the limits permit and schedules a wakeup for the
+    /// remaining ones.
+    ///
+    /// Requests deferred because of the outstanding bytes are sent once responses arrive.
+    fn send_deferred_requests(&mut self, cx: &mut Context<'_>) {
+        while let Some((_, request)) = self.deferred_requests.front() {
+            if self.exceeds_outstanding_bytes(request) {
+                return
+            }
+            if let Some(bucket) = self.rate_limiter.as_mut() {
+                if !bucket.try_acquire() {
+                    let mut delay = Box::pin(tokio::time::sleep(bucket.next_token_in()));
+                    if delay.as_mut().poll(cx).is_pending() {
+                        self.rate_limit_delay = Some(delay);
+                        return
+                    }
+                    continue
+                }
+            }
+            let (id, request) = self.deferred_requests.pop_front().expect("not empty");
+            self.send_block_request(id, request);
+        }
+        self.rate_limit_delay = None;
+    }
+}
+
+/// Returns the estimated size in bytes of the response to the request.
+fn estimated_response_size(request: &BlockRequest) -> u64 {
+    match request {
+        BlockRequest::GetBlockHeaders(request) => request.limit * APPROX_HEADER_SIZE as u64,
+        BlockRequest::GetBlockBodies(request) => request.0.len() as u64 * APPROX_BODY_SIZE as u64,
+    }
+}
+
+/// Exponential backoff of the attempts to dispatch block requests while no peer is available.
+#[derive(Debug, Default)]
+struct FetchBackoff {
+    /// Number of failed attempts in a row.
+    attempts: usize,
+    /// Expires once the next attempt is due.
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+// === impl FetchBackoff ===
+
+impl FetchBackoff {
+    /// Returns `true` if no backoff is pending.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some(delay) = self.delay.as_mut() else { return true };
+        if delay.as_mut().poll(cx).is_pending() {
+            return false
+        }
+        self.delay = None;
+        true
+    }
+
+    /// Records a failed attempt and starts the backoff until the next one.
+    ///
+    /// Returns the number of failed attempts in a row.
+    fn on_failed_attempt(&mut self, cx: &mut Context<'_>) -> usize {
+        self.attempts += 1;
+        let exponent = (self.attempts - 1).min(16) as u32;
+        let backoff = FETCH_BACKOFF_BASE.saturating_mul(1 << exponent).min(MAX_FETCH_BACKOFF);
+        let mut delay = Box::pin(tokio::time::sleep(backoff));
+        // register the waker, so the state is polled again once the backoff expired
+        let _ = delay.as_mut().poll(cx);
+        self.delay = Some(delay);
+        self.attempts
+    }
+}
+
+/// A token bucket that enforces a [`BlockRequestRateLimit`].
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    /// The enforced limit.
+    limit: BlockRequestRateLimit,
+    /// The currently available tokens, at most `limit.burst`.
+    tokens: f64,
+    /// When the tokens were last refilled.
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Returns a full bucket.
+    fn new(limit: BlockRequestRateLimit) -> Self {
+        Self { limit, tokens: limit.burst.max(1) as f64, last_refill: Instant::now() }
+    }
+
+    /// The number of tokens added per second.
+    fn rate(&self) -> f64 {
+        self.limit.requests_per_second.max(1) as f64
+    }
+
+    /// Takes a token if one is available.
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate()).min(self.limit.burst.max(1) as f64);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// Returns how long it takes until the next token is available.
+    fn next_token_in(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate())
+    }
+}
+
+/// A response we're still waiting for, see [`NetworkState::handle_block_request`].
+pub(crate) struct PendingResponse {
+    /// The id the [`StateFetcher`] tracks the request under.
+    pub(crate) id: u64,
+    /// The response receiver.
+    pub(crate) response: PeerResponse,
+    /// Fires once the response took too long.
+    pub(crate) deadline: Pin<Box<Sleep>>,
+    /// When the request was sent to the peer.
+    pub(crate) sent_at: Instant,
+    /// The estimated size in bytes of the response.
+    pub(crate) estimated_size: u64,
+}
+
+/// Errors that prevent a session from being activated in the [`NetworkState`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum SessionActivationError {
+    /// The peer already has an active session.
+    #[error("peer {0} already has an active session")]
+    AlreadyActive(PeerId),
+    /// The peer doesn't support the minimum required `eth` version.
+    #[error("peer {peer_id} doesn't support {min_version:?} or later")]
+    UnsupportedEthVersion { peer_id: PeerId, min_version: EthVersion },
+    /// The peer is on a chain with a different genesis.
+    #[error("peer {peer_id} has genesis {got}, expected {expected}")]
+    MismatchedGenesis { peer_id: PeerId, expected: H256, got: H256 },
+    /// The peer's status announces a fork id that is incompatible with our fork.
+    #[error("peer {peer_id} is on incompatible fork {fork_id:?}")]
+    IncompatibleFork { peer_id: PeerId, fork_id: ForkId },
+}
+
+/// Returns `true` if both addresses are in the same `/24` IPv4 or `/64` IPv6 subnet.
+fn is_same_subnet(a: &IpAddr, b: &IpAddr) -> bool {
+    match (a, b) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..3] == b.octets()[..3],
+        (IpAddr::V6(a), IpAddr::V6(b)) => a.segments()[..4] == b.segments()[..4],
+        _ => false,
+    }
+}
+
+/// Returns the latest `eth` version included in the capabilities.
+fn latest_eth_version(capabilities: &Capabilities) -> Option<EthVersion> {
+    if capabilities.supports_eth_v68() {
+        Some(EthVersion::Eth68)
+    } else if capabilities.supports_eth_v67() {
+        Some(EthVersion::Eth67)
+    } else if capabilities.supports_eth_v66() {
+        Some(EthVersion::Eth66)
+    } else {
+        None
+    }
+}
+
+/// Returns `true` if the capabilities include an `eth` version of at least `min_version`.
+fn supports_eth_version(capabilities: &Capabilities, min_version: EthVersion) -> bool {
+    capabilities
+        .capabilities()
+        .iter()
+        .any(|cap| cap.name == "eth" && cap.version >= min_version as usize)
 }
 
 /// Message variants triggered by the [`NetworkState`]
-pub(crate) enum StateAction {
+#[derive(Debug, Clone)]
+pub enum StateAction {
     /// Dispatch a `NewBlock` message to the peer
     NewBlock {
         /// Target of the message
@@ -478,113 +2379,326 @@ pub(crate) enum StateAction {
         /// The `NewBlock` message
         block: NewBlockMessage,
     },
+    /// Dispatch a `NewBlockHashes` message to the peer
     NewBlockHashes {
         /// Target of the message
         peer_id: PeerId,
         /// `NewBlockHashes` message to send to the peer.
         hashes: NewBlockHashes,
     },
-    /// Create a new connection to the given node.
-    Connect { remote_addr: SocketAddr, peer_id: PeerId },
-    /// Disconnect an existing connection
+    /// Dispatch a `NewPooledTransactionHashes` message to the peer
+    PooledTransactions {
+        /// Target of the message
+        peer_id: PeerId,
+        /// `NewPooledTransactionHashes` message to send to the peer.
+        hashes: NewPooledTransactionHashes,
+    },
+    /// Create a new connection to the given node.
+    Connect {
+        /// The address of the node
+        remote_addr: SocketAddr,
+        /// The id of the node
+        peer_id: PeerId,
+    },
+    /// Disconnect an existing connection
     Disconnect {
+        /// The peer to disconnect
         peer_id: PeerId,
         /// Why the disconnect was initiated
         reason: Option<DisconnectReason>,
     },
     /// Retrieved a [`ForkId`] from the peer via ENR request, See <https://eips.ethereum.org/EIPS/eip-868>
     DiscoveredEnrForkId {
+        /// The peer that reported the [`ForkId`]
+        peer_id: PeerId,
+        /// The reported [`ForkId`] by this peer.
+        fork_id: ForkId,
+    },
+    /// A peer announced a [`ForkId`] via ENR that is not compatible with ours.
+    IncompatibleFork {
+        /// The peer that reported the [`ForkId`]
         peer_id: PeerId,
         /// The reported [`ForkId`] by this peer.
         fork_id: ForkId,
     },
     /// A new node was found through the discovery, possibly with a ForkId
-    DiscoveredNode { peer_id: PeerId, socket_addr: SocketAddr, fork_id: Option<ForkId> },
+    DiscoveredNode {
+        /// The id of the node
+        peer_id: PeerId,
+        /// The address of the node
+        socket_addr: SocketAddr,
+        /// The [`ForkId`] of the node, if known
+        fork_id: Option<ForkId>,
+    },
     /// A peer was added
     PeerAdded(PeerId),
     /// A peer was dropped
-    PeerRemoved(PeerId),
+    PeerRemoved {
+        /// The removed peer
+        peer_id: PeerId,
+        /// Why the peer was removed
+        reason: PeerRemovalReason,
+    },
+    /// A peer announced a block with a lower number than its previously tracked best block.
+    ///
+    /// This is a sign of a reorg or a misbehaving peer.
+    PeerBlockRegressed {
+        /// The peer that announced the block
+        peer_id: PeerId,
+        /// The previously tracked block number.
+        old_number: u64,
+        /// The newly announced, lower block number.
+        new_number: u64,
+    },
+    /// The number of active peers dropped below the low watermark.
+    PeerCountLow(usize),
+    /// The number of active peers recovered above the high watermark.
+    PeerCountHealthy(usize),
+    /// The first session was activated after having no active peers.
+    FirstPeerConnected(PeerId),
+    /// Block requests couldn't be dispatched the given number of times in a row, because no peer
+    /// was available.
+    FetchStalled(usize),
+}
+
+/// A callback that observes every [`StateAction`] of the network, see
+/// [`NetworkConfigBuilder::state_action_handler`](crate::NetworkConfigBuilder::state_action_handler).
+///
+/// The handler runs on its own task and receives the actions through a bounded channel. Actions
+/// are dropped while the channel is full, so a slow handler never blocks the network.
+pub struct StateActionHandler {
+    /// The capacity of the channel to the handler.
+    capacity: NonZeroUsize,
+    /// The callback.
+    handler: Box<dyn FnMut(StateAction) + Send>,
+}
+
+impl StateActionHandler {
+    /// Creates a new handler that buffers up to `capacity` actions.
+    pub fn new<F>(capacity: NonZeroUsize, handler: F) -> Self
+    where
+        F: FnMut(StateAction) + Send + 'static,
+    {
+        Self { capacity, handler: Box::new(handler) }
+    }
+
+    /// Spawns the handler and returns the sender half of its channel.
+    pub(crate) fn spawn(self, executor: &dyn TaskSpawner) -> mpsc::Sender<StateAction> {
+        let (tx, mut rx) = mpsc::channel(self.capacity.get());
+        let mut handler = self.handler;
+        executor.spawn(Box::pin(async move {
+            while let Some(action) = rx.recv().await {
+                handler(action);
+            }
+        }));
+        tx
+    }
+}
+
+impl std::fmt::Debug for StateActionHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateActionHandler")
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        discovery::Discovery, fetch::StateFetcher, message::PeerRequestSender, peers::PeersManager,
-        state::NetworkState, PeerRequest,
+        config::BlockPropagationMode,
+        discovery::{Discovery, DiscoveryEvent},
+        eth_requests::APPROX_BODY_SIZE,
+        fetch::RoundRobinPeerSelector,
+        message::{BlockRequest, NewBlockMessage, PeerRequestSender, PeerResponseResult},
+        peers::{PeerAction, PeerRemovalReason, PeersManager, ReputationDecay},
+        state::{
+            ActivePeerInfo, BandwidthStats, BlockRequestRateLimit, HealthThresholds, NetworkHealth,
+            NetworkSnapshot, NetworkState, NetworkStateMetrics, PeerCountWatermarks,
+            SessionActivationError, StateAction, StateActionHandler, SubnetLimits,
+            DRAIN_DISCONNECTS_PER_POLL, FETCH_BACKOFF_BASE, MAX_UNSENT_REQUESTS,
+            PEER_BLOCK_CACHE_LIMIT, PEER_TRANSACTION_HASH_CACHE_LIMIT,
+        },
+        test_utils::spawn_mock_peer,
+        FetchClient, PeerRequest,
     };
+    use futures::FutureExt;
     use reth_eth_wire::{
         capability::{Capabilities, Capability},
-        BlockBodies, EthVersion, Status,
+        errors::{EthStreamError, P2PStreamError},
+        BlockBodies, BlockHashNumber, BlockHeaders, DisconnectReason, EthVersion, GetBlockBodies,
+        GetBlockHeaders, NewBlock, NewPooledTransactionHashes, Status,
+    };
+    use reth_interfaces::p2p::{
+        bodies::client::BodiesClient,
+        error::{RequestError, RequestResult},
+        headers::client::{HeadersClient, HeadersRequest},
+    };
+    use reth_network_api::{PeerKind, ReputationChangeKind};
+    use reth_primitives::{
+        Block, BlockBody, ForkHash, ForkId, Head, Header, HeadersDirection, PeerId, H256, MAINNET,
     };
-    use reth_interfaces::p2p::{bodies::client::BodiesClient, error::RequestError};
-    use reth_primitives::{BlockBody, Header, PeerId, H256};
     use reth_provider::test_utils::NoopProvider;
+    use reth_rlp::Encodable;
+    use reth_tasks::TokioTaskExecutor;
     use std::{
+        collections::{HashMap, HashSet},
         future::poll_fn,
+        net::{IpAddr, SocketAddr},
+        num::NonZeroUsize,
         sync::{atomic::AtomicU64, Arc},
+        task::Poll,
+        time::{Duration, Instant},
     };
     use tokio::sync::mpsc;
-    use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
     /// Returns a testing instance of the [NetworkState].
     fn state() -> NetworkState<NoopProvider> {
-        let peers = PeersManager::default();
-        let handle = peers.handle();
-        NetworkState {
-            active_peers: Default::default(),
-            peers_manager: Default::default(),
-            queued_messages: Default::default(),
-            client: NoopProvider::default(),
-            discovery: Discovery::noop(),
-            genesis_hash: Default::default(),
-            state_fetcher: StateFetcher::new(handle, Default::default()),
-        }
+        NetworkState::new(
+            NoopProvider::default(),
+            Discovery::noop(),
+            PeersManager::default(),
+            MAINNET.genesis_hash(),
+            Default::default(),
+        )
+    }
+
+    /// Returns a new [`NewBlockMessage`] for a block with the given number.
+    fn new_block_message(number: u64) -> NewBlockMessage {
+        let block = NewBlock {
+            block: Block { header: Header { number, ..Default::default() }, ..Default::default() },
+            ..Default::default()
+        };
+        NewBlockMessage { hash: H256::random(), block: Arc::new(block) }
     }
 
     fn capabilities() -> Arc<Capabilities> {
         Arc::new(vec![Capability::from(EthVersion::Eth67)].into())
     }
 
-    // tests that ongoing requests are answered with connection dropped if the session that received
-    // that request is drops the request object.
-    #[tokio::test(flavor = "multi_thread")]
-    async fn test_dropped_active_session() {
-        let mut state = state();
-        let client = state.fetch_client();
+    /// Activates a session for the given peer and returns the session's request receiver.
+    fn activate_peer(
+        state: &mut NetworkState<NoopProvider>,
+        peer_id: PeerId,
+    ) -> mpsc::Receiver<PeerRequest> {
+        activate_peer_with_capabilities(state, peer_id, capabilities())
+    }
 
-        let peer_id = PeerId::random();
+    /// Activates a session for the given peer that advertises the given capabilities, see
+    /// [`activate_peer`].
+    fn activate_peer_with_capabilities(
+        state: &mut NetworkState<NoopProvider>,
+        peer_id: PeerId,
+        capabilities: Arc<Capabilities>,
+    ) -> mpsc::Receiver<PeerRequest> {
         let (tx, session_rx) = mpsc::channel(1);
         let peer_tx = PeerRequestSender::new(peer_id, tx);
+        state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities,
+                Status::default(),
+                peer_tx,
+                Arc::new(AtomicU64::new(Duration::from_secs(10).as_millis() as u64)),
+            )
+            .unwrap();
+        session_rx
+    }
 
-        state.on_session_activated(
-            peer_id,
-            capabilities(),
-            Status::default(),
-            peer_tx,
-            Arc::new(AtomicU64::new(1)),
-        );
+    /// Polls the state until it's pending and returns all emitted actions.
+    async fn poll_actions(state: &mut NetworkState<NoopProvider>) -> Vec<StateAction> {
+        poll_fn(|cx| {
+            let mut actions = Vec::new();
+            while let Poll::Ready(action) = state.poll(cx) {
+                actions.push(action);
+            }
+            Poll::Ready(actions)
+        })
+        .await
+    }
 
-        assert!(state.active_peers.contains_key(&peer_id));
+    /// Polls the state and returns the peers it disconnected, with the disconnect reasons.
+    async fn poll_disconnects(
+        state: &mut NetworkState<NoopProvider>,
+    ) -> Vec<(PeerId, Option<DisconnectReason>)> {
+        poll_actions(state)
+            .await
+            .into_iter()
+            .filter_map(|action| match action {
+                StateAction::Disconnect { peer_id, reason } => Some((peer_id, reason)),
+                _ => None,
+            })
+            .collect()
+    }
 
-        let body = BlockBody { ommers: vec![Header::default()], ..Default::default() };
+    /// Polls the state and returns the hashes announced to each peer via `NewBlockHashes`.
+    async fn announced_block_hashes(
+        state: &mut NetworkState<NoopProvider>,
+    ) -> HashMap<PeerId, Vec<H256>> {
+        let mut announced = HashMap::<_, Vec<_>>::new();
+        for action in poll_actions(state).await {
+            if let StateAction::NewBlockHashes { peer_id, hashes } = action {
+                announced.entry(peer_id).or_default().extend(hashes.0.iter().map(|h| h.hash));
+            }
+        }
+        announced
+    }
 
-        let body_response = body.clone();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_mock_peer_headers_round_trip() {
+        let mut state = state();
+        let client = state.fetch_client();
 
-        // this mimics an active session that receives the requests from the state
-        tokio::task::spawn(async move {
-            let mut stream = ReceiverStream::new(session_rx);
-            let resp = stream.next().await.unwrap();
-            match resp {
-                PeerRequest::GetBlockBodies { response, .. } => {
-                    response.send(Ok(BlockBodies(vec![body_response]))).unwrap();
-                }
-                _ => unreachable!(),
+        let header = Header { number: 7, ..Default::default() };
+        let response = header.clone();
+        let peer_id = spawn_mock_peer(&mut state, move |request| match request {
+            PeerRequest::GetBlockHeaders { .. } => {
+                PeerResponseResult::BlockHeaders(Ok(vec![response.clone()]))
             }
+            _ => PeerResponseResult::BlockHeaders(Err(RequestError::BadResponse)),
+        });
 
-            // wait for the next request, then drop
-            let _resp = stream.next().await.unwrap();
+        tokio::task::spawn(async move {
+            loop {
+                poll_fn(|cx| state.poll(cx)).await;
+            }
         });
 
+        let request = HeadersRequest {
+            start: H256::random().into(),
+            limit: 1,
+            direction: HeadersDirection::Rising,
+        };
+        let (peer, headers) = client.get_headers(request).await.unwrap().split();
+        assert_eq!(peer, peer_id);
+        assert_eq!(headers, vec![header]);
+    }
+
+    // tests that ongoing requests are answered with connection dropped if the session that received
+    // that request is drops the request object.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dropped_active_session() {
+        let mut state = state();
+        let client = state.fetch_client();
+
+        let body = BlockBody { ommers: vec![Header::default()], ..Default::default() };
+
+        // the session answers the first request, then drops the next one
+        let mut responses = vec![
+            PeerResponseResult::BlockBodies(Err(RequestError::ConnectionDropped)),
+            PeerResponseResult::BlockBodies(Ok(vec![body.clone()])),
+        ];
+        let peer_id = spawn_mock_peer(&mut state, move |_| responses.pop().unwrap());
+
+        assert_eq!(
+            state
+                .active_peer_infos()
+                .map(|info| (info.peer_id, info.remote_addr))
+                .collect::<Vec<_>>(),
+            vec![(peer_id, "127.0.0.1:30303".parse().unwrap())]
+        );
+
         // spawn the state as future
         tokio::task::spawn(async move {
             loop {
@@ -601,4 +2715,2217 @@ mod tests {
         assert!(resp.is_err());
         assert_eq!(resp.unwrap_err(), RequestError::ConnectionDropped);
     }
+
+    #[tokio::test]
+    async fn test_peer_block_cache_limit() {
+        // sends the blocks to a single peer, then returns the blocks whose hashes are still
+        // announced to the peer, because it forgot them in the meantime
+        async fn forgotten_blocks(limit: usize, num_blocks: u64) -> Vec<u64> {
+            let mut state = state();
+            state.set_peer_block_cache_limit(limit);
+            let peer_id = PeerId::random();
+            let _session_rx = activate_peer(&mut state, peer_id);
+
+            let msgs = (0..num_blocks).map(new_block_message).collect::<Vec<_>>();
+            for msg in &msgs {
+                state.announce_new_block(msg.clone());
+            }
+            for msg in &msgs {
+                state.announce_new_block_hash(msg.clone());
+            }
+            let announced = announced_block_hashes(&mut state).await.remove(&peer_id);
+            let announced = announced.unwrap_or_default();
+            msgs.iter()
+                .filter(|msg| announced.contains(&msg.hash))
+                .map(|msg| msg.block.block.header.number)
+                .collect()
+        }
+
+        // reaching the configured limit evicts the oldest entry
+        assert!(forgotten_blocks(3, 2).await.is_empty());
+        assert_eq!(forgotten_blocks(3, 3).await, vec![0]);
+
+        // a zero limit falls back to the default
+        assert_eq!(forgotten_blocks(0, PEER_BLOCK_CACHE_LIMIT as u64).await, vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_evictions() {
+        let mut state = state();
+        state.set_peer_block_cache_limit(3);
+
+        let peers = [PeerId::random(), PeerId::random()];
+        let _sessions = peers.map(|peer_id| activate_peer(&mut state, peer_id));
+        assert_eq!(state.cache_evictions(), 0);
+
+        // the cache holds two entries, so each further announced block evicts one
+        for (peer_id, num_blocks) in peers.into_iter().zip([5, 3]) {
+            for _ in 0..num_blocks {
+                state.on_new_block(peer_id, H256::random(), 1);
+            }
+        }
+
+        assert_eq!(state.cache_evictions(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_new_pooled_tx_hashes() {
+        let mut state = state();
+        state.set_peer_transaction_hash_cache_limit(3);
+
+        let peer_id = PeerId::random();
+        let other_peer_id = PeerId::random();
+        let _session_rx = activate_peer(&mut state, peer_id);
+        let _other_session_rx = activate_peer(&mut state, other_peer_id);
+
+        let hashes = [H256::random(), H256::random(), H256::random()];
+        state.on_new_pooled_tx_hashes(peer_id, vec![hashes[0], hashes[1]]);
+        assert!(state.peer_knows_tx(&peer_id, &hashes[0]));
+        assert!(state.peer_knows_tx(&peer_id, &hashes[1]));
+        assert!(!state.peer_knows_tx(&peer_id, &hashes[2]));
+
+        // announcements are tracked per peer
+        assert!(!state.peer_knows_tx(&other_peer_id, &hashes[0]));
+        assert!(!state.peer_knows_tx(&PeerId::random(), &hashes[0]));
+
+        // reaching the configured limit evicts the oldest entry
+        state.on_new_pooled_tx_hashes(peer_id, vec![hashes[2]]);
+        assert!(!state.peer_knows_tx(&peer_id, &hashes[0]));
+        assert!(state.peer_knows_tx(&peer_id, &hashes[1]));
+        assert!(state.peer_knows_tx(&peer_id, &hashes[2]));
+
+        // a zero limit falls back to the default
+        state.set_peer_transaction_hash_cache_limit(0);
+        let peer_id = PeerId::random();
+        let _session_rx = activate_peer(&mut state, peer_id);
+        let hashes =
+            (0..PEER_TRANSACTION_HASH_CACHE_LIMIT).map(|_| H256::random()).collect::<Vec<_>>();
+        state.on_new_pooled_tx_hashes(peer_id, hashes.clone());
+        assert!(!state.peer_knows_tx(&peer_id, &hashes[0]));
+        assert!(state.peer_knows_tx(&peer_id, &hashes[1]));
+    }
+
+    #[tokio::test]
+    async fn test_best_peer() {
+        let mut state = state();
+        assert_eq!(state.best_peer(), None);
+
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let hashes = [H256::random(), H256::random(), H256::random()];
+        let mut sessions = Vec::new();
+        for (num, (peer_id, hash)) in peers.iter().zip(hashes).enumerate() {
+            sessions.push(activate_peer(&mut state, *peer_id));
+            state.update_peer_block(peer_id, hash, (num as u64 + 1) * 10);
+        }
+
+        assert_eq!(state.best_peer(), Some((peers[2], hashes[2])));
+
+        // ties are broken by the lowest peer id
+        state.update_peer_block(&peers[0], hashes[2], 30);
+        let lowest = peers[0].min(peers[2]);
+        assert_eq!(state.best_peer(), Some((lowest, hashes[2])));
+    }
+
+    #[tokio::test]
+    async fn test_peer_block_regressed() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let _session_rx = activate_peer(&mut state, peer_id);
+
+        let regressions = |actions: Vec<StateAction>| {
+            actions
+                .into_iter()
+                .filter(|action| matches!(action, StateAction::PeerBlockRegressed { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        state.update_peer_block(&peer_id, H256::random(), 10);
+        assert!(regressions(poll_actions(&mut state).await).is_empty());
+
+        let hash = H256::random();
+        state.update_peer_block(&peer_id, hash, 5);
+        assert_eq!(state.best_peer(), Some((peer_id, hash)));
+        assert!(matches!(
+            regressions(poll_actions(&mut state).await)[..],
+            [StateAction::PeerBlockRegressed { peer_id: id, old_number: 10, new_number: 5 }]
+                if id == peer_id
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_batched_new_block_hashes() {
+        let mut state = state();
+        let peers = [PeerId::random(), PeerId::random()];
+        let _sessions: Vec<_> = peers.iter().map(|peer| activate_peer(&mut state, *peer)).collect();
+
+        let first = new_block_message(1);
+        let second = new_block_message(2);
+        state.announce_new_block_hash(first.clone());
+        state.announce_new_block_hash(second.clone());
+
+        let mut announced = HashMap::new();
+        for action in poll_actions(&mut state).await {
+            if let StateAction::NewBlockHashes { peer_id, hashes } = action {
+                assert!(announced.insert(peer_id, hashes).is_none(), "more than one message");
+            }
+        }
+
+        assert_eq!(announced.len(), peers.len());
+        for peer_id in peers {
+            let hashes: Vec<_> = announced[&peer_id].0.iter().map(|h| (h.hash, h.number)).collect();
+            assert_eq!(hashes, vec![(first.hash, 1), (second.hash, 2)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_force_announce_new_block() {
+        let mut state = state();
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let _sessions: Vec<_> = peers.iter().map(|peer| activate_peer(&mut state, *peer)).collect();
+
+        // some of the peers already received the block
+        let msg = new_block_message(1);
+        state.announce_new_block(msg.clone());
+        poll_actions(&mut state).await;
+
+        state.force_announce_new_block(msg.clone());
+
+        let mut announced = HashSet::new();
+        for action in poll_actions(&mut state).await {
+            if let StateAction::NewBlock { peer_id, block } = action {
+                assert_eq!(block.hash, msg.hash);
+                assert!(announced.insert(peer_id));
+            }
+        }
+        assert_eq!(announced, peers.into_iter().collect::<HashSet<_>>());
+
+        // all peers are marked as having seen the block
+        state.announce_new_block_hash(msg);
+        assert!(announced_block_hashes(&mut state).await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_drain() {
+        let mut state = state();
+        let peers: Vec<_> = (0..DRAIN_DISCONNECTS_PER_POLL + 2).map(|_| PeerId::random()).collect();
+        let _sessions: Vec<_> = peers.iter().map(|peer| activate_peer(&mut state, *peer)).collect();
+        // the first peer connected signal is delivered before draining
+        poll_actions(&mut state).await;
+
+        state.begin_drain();
+        assert!(!state.is_drained());
+
+        // the first poll only queues `DRAIN_DISCONNECTS_PER_POLL` disconnects
+        let mut actions = vec![poll_fn(|cx| state.poll(cx)).await];
+
+        // all sessions close before the next poll, so the remaining peers aren't disconnected
+        for peer in &peers {
+            state.on_session_closed(*peer);
+        }
+        assert!(state.is_drained());
+
+        actions.extend(poll_actions(&mut state).await);
+        let mut disconnected = HashSet::new();
+        for action in actions {
+            let StateAction::Disconnect { peer_id, reason } = action else { unreachable!() };
+            assert_eq!(reason, Some(DisconnectReason::ClientQuitting));
+            assert!(disconnected.insert(peer_id));
+        }
+        assert_eq!(disconnected.len(), DRAIN_DISCONNECTS_PER_POLL);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_peer_latency() {
+        let mut state = state();
+        let client = state.fetch_client();
+
+        // this mimics a slow session
+        let peer_id = spawn_mock_peer(&mut state, |_| {
+            std::thread::sleep(Duration::from_millis(50));
+            PeerResponseResult::BlockBodies(Ok(vec![]))
+        });
+        assert_eq!(state.peer_latencies(), vec![(peer_id, None)]);
+
+        let mut request =
+            tokio::task::spawn(async move { client.get_block_bodies(vec![H256::random()]).await });
+
+        // drive the state until the request resolved
+        let resp = poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            request.poll_unpin(cx)
+        })
+        .await
+        .unwrap();
+        assert!(resp.is_ok());
+
+        let latencies = state.peer_latencies();
+        assert_eq!(latencies.len(), 1);
+        let (id, latency) = latencies[0];
+        assert_eq!(id, peer_id);
+        assert!(latency.unwrap() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_peer_selector_reroutes_request() {
+        let mut state = state();
+        state.set_peer_selector(Box::<RoundRobinPeerSelector>::default());
+        let client = state.fetch_client();
+
+        let mut sessions = HashMap::new();
+        for _ in 0..3 {
+            let peer_id = PeerId::random();
+            sessions.insert(peer_id, activate_peer(&mut state, peer_id));
+        }
+
+        let hash = H256::random();
+        let request = tokio::task::spawn(async move { client.get_block_bodies(vec![hash]).await });
+
+        // drive the state until the fetcher dispatched the request to a peer
+        let unavailable = poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            let peer = sessions.iter_mut().find_map(|(peer_id, session)| {
+                session.try_recv().ok().map(|request| (*peer_id, request))
+            });
+            match peer {
+                Some(peer) => Poll::Ready(peer),
+                None => Poll::Pending,
+            }
+        })
+        .await
+        .0;
+        let mut candidates =
+            sessions.keys().filter(|peer_id| **peer_id != unavailable).copied().collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        // the session closes while the request is inflight, so it's sent to the selected peer,
+        // starting with the first candidate
+        state.on_session_closed(unavailable);
+        let Ok(PeerRequest::GetBlockBodies { request: rerouted, response }) =
+            sessions.get_mut(&candidates[0]).unwrap().try_recv()
+        else {
+            unreachable!()
+        };
+        assert_eq!(rerouted.0, vec![hash]);
+        assert!(sessions.get_mut(&candidates[1]).unwrap().try_recv().is_err());
+
+        // the response of the selected peer is returned for the request
+        response.send(Ok(BlockBodies(vec![BlockBody::default()]))).unwrap();
+        tokio::task::spawn(async move {
+            loop {
+                poll_fn(|cx| state.poll(cx)).await;
+            }
+        });
+        let bodies = request.await.unwrap().unwrap();
+        assert_eq!(bodies.1, vec![BlockBody::default()]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_peer() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        state.disconnect_peer(peer_id, DisconnectReason::SubprotocolSpecific);
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::SubprotocolSpecific))]
+        );
+
+        // the fetcher no longer considers the peer for requests
+        let _request = state.fetch_client().get_block_bodies(vec![H256::random()]);
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pending_response_deadline() {
+        let mut state = state();
+        let client = state.fetch_client();
+
+        let peer_id = PeerId::random();
+        state.peers_mut().add_peer(peer_id, "127.0.0.1:30303".parse().unwrap(), None);
+        let reputation = state.peers().get_reputation(&peer_id).unwrap();
+
+        // the session never responds, but keeps the channel open
+        let (tx, _session_rx) = mpsc::channel(1);
+        let peer_tx = PeerRequestSender::new(peer_id, tx);
+        state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status::default(),
+                peer_tx,
+                Arc::new(AtomicU64::new(10)),
+            )
+            .unwrap();
+
+        let request =
+            tokio::task::spawn(async move { client.get_block_bodies(vec![H256::random()]).await });
+
+        // drive the state until the deadline of the dispatched request is exceeded
+        let disconnect = poll_fn(|cx| {
+            while let Poll::Ready(action) = state.poll(cx) {
+                if let StateAction::Disconnect { peer_id, reason } = action {
+                    return Poll::Ready((peer_id, reason))
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        assert_eq!(disconnect, (peer_id, None));
+
+        assert_eq!(state.num_active_peers(), 0);
+        assert!(state.peers().get_reputation(&peer_id).unwrap() < reputation);
+        assert_eq!(request.await.unwrap().unwrap_err(), RequestError::ConnectionDropped);
+    }
+
+    #[tokio::test]
+    async fn test_peers_by_capability() {
+        let mut state = state();
+        let eth66 = Capability::from(EthVersion::Eth66);
+        let eth67 = Capability::from(EthVersion::Eth67);
+        assert!(state.peers_by_capability(&eth66).is_empty());
+
+        let eth66_peer = PeerId::random();
+        let _eth66_session = activate_peer_with_capabilities(
+            &mut state,
+            eth66_peer,
+            Arc::new(vec![eth66.clone()].into()),
+        );
+        // advertises eth67
+        let eth67_peer = PeerId::random();
+        let _eth67_session = activate_peer(&mut state, eth67_peer);
+
+        let snap = Capability::new("snap".into(), 1);
+        assert_eq!(state.peers_by_capability(&eth66), vec![eth66_peer]);
+        assert_eq!(state.peers_by_capability(&eth67), vec![eth67_peer]);
+        assert!(state.peers_by_capability(&snap).is_empty());
+        assert!(state.snap_peers().is_empty());
+
+        let snap_peer = PeerId::random();
+        let _snap_session = activate_peer_with_capabilities(
+            &mut state,
+            snap_peer,
+            Arc::new(vec![eth67, snap.clone()].into()),
+        );
+        assert_eq!(state.peers_by_capability(&snap), vec![snap_peer]);
+        assert_eq!(state.snap_peers(), vec![snap_peer]);
+    }
+
+    #[tokio::test]
+    async fn test_enr_fork_id() {
+        let mut state = state();
+        state.set_fork_filter(MAINNET.fork_filter(Head::default()));
+
+        let compatible = (PeerId::random(), MAINNET.fork_id(&Head::default()));
+        let incompatible =
+            (PeerId::random(), ForkId { hash: ForkHash([0xde, 0xad, 0xbe, 0xef]), next: 0 });
+        for (peer_id, fork_id) in [compatible, incompatible] {
+            state.on_discovery_event(DiscoveryEvent::EnrForkId(peer_id, fork_id));
+        }
+
+        let actions = poll_actions(&mut state).await;
+        assert!(matches!(
+            actions[..],
+            [
+                StateAction::DiscoveredEnrForkId { peer_id: a, fork_id: a_fork },
+                StateAction::IncompatibleFork { peer_id: b, fork_id: b_fork },
+            ] if (a, a_fork) == compatible && (b, b_fork) == incompatible
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_metrics_snapshot() {
+        let mut state = state();
+        let client = state.fetch_client();
+        assert_eq!(state.metrics_snapshot(), NetworkStateMetrics::default());
+
+        let mut sessions = Vec::new();
+        for _ in 0..2 {
+            let peer_id = PeerId::random();
+            sessions.push(activate_peer(&mut state, peer_id));
+        }
+
+        let _request =
+            tokio::task::spawn(async move { client.get_block_bodies(vec![H256::random()]).await });
+
+        // drive the state until the fetcher dispatched the request to a peer
+        let mut dispatched = Vec::new();
+        poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            dispatched
+                .extend(sessions.iter_mut().filter_map(|session_rx| session_rx.try_recv().ok()));
+            if dispatched.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+        // a discovered node that isn't polled yet
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id: PeerId::random(),
+            socket_addr: "127.0.0.1:30303".parse().unwrap(),
+            fork_id: None,
+        });
+
+        assert_eq!(
+            state.metrics_snapshot(),
+            NetworkStateMetrics {
+                num_active_peers: 2,
+                queued_messages: 1,
+                inflight_headers_requests: 0,
+                inflight_bodies_requests: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trusted_only_ignores_discovered_nodes() {
+        let mut state = state();
+        state.set_trusted_only(true);
+        let socket_addr = "127.0.0.1:30303".parse().unwrap();
+
+        let discovered = PeerId::random();
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id: discovered,
+            socket_addr,
+            fork_id: None,
+        });
+
+        let trusted = PeerId::random();
+        state.add_peer_kind(trusted, PeerKind::Trusted, socket_addr);
+
+        let actions = poll_actions(&mut state).await;
+
+        let connects = actions
+            .iter()
+            .filter_map(|action| match action {
+                StateAction::Connect { peer_id, .. } => Some(*peer_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(connects, vec![trusted]);
+        assert!(!actions.iter().any(|action| matches!(action, StateAction::DiscoveredNode { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reject_duplicate_session() {
+        let mut state = state();
+        let client = state.fetch_client();
+        let peer_id = PeerId::random();
+        let mut first_session = activate_peer(&mut state, peer_id);
+
+        let (tx, _second_session) = mpsc::channel(1);
+        let err = state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status::default(),
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(1)),
+            )
+            .unwrap_err();
+        assert_eq!(err, SessionActivationError::AlreadyActive(peer_id));
+
+        // the peer isn't disconnected, since that would close the first session, which still
+        // receives the requests
+        let _request = client.get_block_bodies(vec![H256::random()]);
+        assert!(poll_disconnects(&mut state).await.is_empty());
+        assert!(matches!(first_session.try_recv(), Ok(PeerRequest::GetBlockBodies { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reject_unsupported_eth_version() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+
+        let (tx, _session_rx) = mpsc::channel(1);
+        let err = state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                Arc::new(vec![Capability::new("eth".into(), 65)].into()),
+                Status::default(),
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(1)),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SessionActivationError::UnsupportedEthVersion {
+                peer_id,
+                min_version: EthVersion::Eth66
+            }
+        );
+        assert_eq!(state.num_active_peers(), 0);
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::UselessPeer))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seeded_block_propagation() {
+        let peer_ids = (0..10).map(|_| PeerId::random()).collect::<Vec<_>>();
+
+        // returns the peers that receive the `NewBlock` message
+        async fn propagate(peer_ids: &[PeerId]) -> HashSet<PeerId> {
+            let mut state = state();
+            state.set_propagation_fraction(BlockPropagationMode::FixedCount(3));
+            state.set_propagation_seed(42);
+            let _sessions = peer_ids
+                .iter()
+                .map(|peer_id| activate_peer(&mut state, *peer_id))
+                .collect::<Vec<_>>();
+
+            state.announce_new_block(new_block_message(1));
+            poll_actions(&mut state)
+                .await
+                .into_iter()
+                .filter_map(|action| match action {
+                    StateAction::NewBlock { peer_id, .. } => Some(peer_id),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        let selected = propagate(&peer_ids).await;
+        assert_eq!(selected.len(), 3);
+
+        // the same seed selects the same peers, regardless of the activation order
+        let mut reversed = peer_ids.clone();
+        reversed.reverse();
+        assert_eq!(propagate(&reversed).await, selected);
+    }
+
+    #[tokio::test]
+    async fn test_pause_fetching() {
+        let mut state = state();
+        let client = state.fetch_client();
+        let mut session_rx = activate_peer(&mut state, PeerId::random());
+
+        state.pause_fetching();
+        // the request is sent to the fetcher right away
+        let _request = client.get_block_bodies(vec![H256::random()]);
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_err());
+
+        // the buffered request is dispatched once fetching is resumed
+        state.resume_fetching();
+        poll_actions(&mut state).await;
+        assert!(matches!(session_rx.try_recv(), Ok(PeerRequest::GetBlockBodies { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_peer_count_watermarks() {
+        let mut state = state();
+        state.set_peer_count_watermarks(PeerCountWatermarks { low: 2, high: 3 });
+
+        // returns the peer count actions of a single poll pass
+        async fn peer_count_actions(state: &mut NetworkState<NoopProvider>) -> Vec<StateAction> {
+            poll_actions(state)
+                .await
+                .into_iter()
+                .filter(|action| {
+                    matches!(
+                        action,
+                        StateAction::PeerCountLow(_) | StateAction::PeerCountHealthy(_)
+                    )
+                })
+                .collect()
+        }
+
+        // no peers yet
+        let actions = peer_count_actions(&mut state).await;
+        assert!(matches!(actions[..], [StateAction::PeerCountLow(0)]));
+        assert!(peer_count_actions(&mut state).await.is_empty());
+
+        let peer_ids = (0..4).map(|_| PeerId::random()).collect::<Vec<_>>();
+        let mut sessions = Vec::new();
+        for peer_id in &peer_ids[..3] {
+            sessions.push(activate_peer(&mut state, *peer_id));
+        }
+        // reaching the high watermark isn't enough
+        assert!(peer_count_actions(&mut state).await.is_empty());
+
+        sessions.push(activate_peer(&mut state, peer_ids[3]));
+        let actions = peer_count_actions(&mut state).await;
+        assert!(matches!(actions[..], [StateAction::PeerCountHealthy(4)]));
+        assert!(peer_count_actions(&mut state).await.is_empty());
+
+        // dropping to the low watermark isn't enough
+        state.on_session_closed(peer_ids[0]);
+        state.on_session_closed(peer_ids[1]);
+        assert!(peer_count_actions(&mut state).await.is_empty());
+
+        state.on_session_closed(peer_ids[2]);
+        let actions = peer_count_actions(&mut state).await;
+        assert!(matches!(actions[..], [StateAction::PeerCountLow(1)]));
+        assert!(peer_count_actions(&mut state).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_active_peer_infos() {
+        let mut state = state();
+        assert_eq!(state.active_peer_infos().count(), 0);
+
+        let eth67_peer = PeerId::random();
+        let _eth67_session = activate_peer(&mut state, eth67_peer);
+
+        let eth66_peer = PeerId::random();
+        let (tx, _eth66_session) = mpsc::channel(1);
+        let status = Status { blockhash: H256::random(), ..Default::default() };
+        state
+            .on_session_activated(
+                eth66_peer,
+                "127.0.0.1:30303".parse().unwrap(),
+                Arc::new(vec![Capability::from(EthVersion::Eth66)].into()),
+                status,
+                PeerRequestSender::new(eth66_peer, tx),
+                Arc::new(AtomicU64::new(1)),
+            )
+            .unwrap();
+
+        let mut infos = state.active_peer_infos().collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.peer_id);
+        let mut expected = vec![
+            ActivePeerInfo {
+                peer_id: eth67_peer,
+                best_hash: Status::default().blockhash,
+                best_number: None,
+                eth_version: Some(EthVersion::Eth67),
+                remote_addr: "127.0.0.1:30303".parse().unwrap(),
+            },
+            ActivePeerInfo {
+                peer_id: eth66_peer,
+                best_hash: status.blockhash,
+                best_number: None,
+                eth_version: Some(EthVersion::Eth66),
+                remote_addr: "127.0.0.1:30303".parse().unwrap(),
+            },
+        ];
+        expected.sort_by_key(|info| info.peer_id);
+        assert_eq!(infos, expected);
+    }
+
+    #[tokio::test]
+    async fn test_reputation_decay() {
+        let mut state = state();
+        state.set_reputation_decay(ReputationDecay {
+            interval: Duration::from_millis(10),
+            amount: 1024,
+        });
+
+        let peer_id = PeerId::random();
+        state.peers_mut().add_peer(peer_id, "127.0.0.1:30303".parse().unwrap(), None);
+        state.peers_mut().apply_reputation_change(&peer_id, ReputationChangeKind::Timeout);
+        let penalized = state.peers().get_reputation(&peer_id).unwrap();
+        assert!(penalized < 0);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        poll_actions(&mut state).await;
+
+        let decayed = state.peers().get_reputation(&peer_id).unwrap();
+        assert!(decayed > penalized);
+        assert!(decayed <= 0);
+    }
+
+    #[tokio::test]
+    async fn test_reject_far_future_new_block() {
+        let mut state = state();
+        state.set_max_new_block_gap(10);
+
+        let peer_id = PeerId::random();
+        state.peers_mut().add_peer(peer_id, "127.0.0.1:30303".parse().unwrap(), None);
+        let _rx = activate_peer(&mut state, peer_id);
+        let initial = state.peers().get_reputation(&peer_id).unwrap();
+
+        // the noop provider's head is block 0
+        let near = new_block_message(10);
+        assert!(state.on_new_block(peer_id, near.hash, 10));
+        assert_eq!(state.peers().get_reputation(&peer_id), Some(initial));
+
+        let far = new_block_message(11);
+        assert!(!state.on_new_block(peer_id, far.hash, 11));
+        assert!(state.peers().get_reputation(&peer_id).unwrap() < initial);
+
+        // only the accepted block is marked as seen by the peer
+        state.announce_new_block_hash(near);
+        state.announce_new_block_hash(far.clone());
+        assert_eq!(announced_block_hashes(&mut state).await[&peer_id], vec![far.hash]);
+    }
+
+    #[tokio::test]
+    async fn test_new_block_gap_while_syncing() {
+        let mut state = state();
+        state.set_max_new_block_gap(10);
+
+        // our head is still at block 0, while the other peers are at the tip
+        let tip_peer = PeerId::random();
+        let _tip_rx = activate_peer(&mut state, tip_peer);
+        state.update_peer_block(&tip_peer, H256::random(), 1_000);
+
+        let peer_id = PeerId::random();
+        state.peers_mut().add_peer(peer_id, "127.0.0.1:30303".parse().unwrap(), None);
+        let _rx = activate_peer(&mut state, peer_id);
+        let initial = state.peers().get_reputation(&peer_id).unwrap();
+
+        assert!(state.on_new_block(peer_id, H256::random(), 1_001));
+        assert_eq!(state.peers().get_reputation(&peer_id), Some(initial));
+
+        // the announcing peer's own best block doesn't raise its limit
+        state.update_peer_block(&peer_id, H256::random(), 1_010);
+        assert!(!state.on_new_block(peer_id, H256::random(), 1_011));
+        assert!(state.peers().get_reputation(&peer_id).unwrap() < initial);
+    }
+
+    #[tokio::test]
+    async fn test_drain_queued() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(2));
+        let peer_ids = [PeerId::random(), PeerId::random()];
+        let _sessions =
+            peer_ids.iter().map(|peer_id| activate_peer(&mut state, *peer_id)).collect::<Vec<_>>();
+        assert!(matches!(state.drain_queued()[..], [StateAction::FirstPeerConnected(_)]));
+
+        let msg = new_block_message(1);
+        state.announce_new_block(msg.clone());
+
+        let peers = state
+            .drain_queued()
+            .into_iter()
+            .map(|action| {
+                let StateAction::NewBlock { peer_id, block } = action else { unreachable!() };
+                assert_eq!(block.hash, msg.hash);
+                peer_id
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(peers, peer_ids.into_iter().collect());
+        assert!(state.drain_queued().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_stats() {
+        let mut state = state();
+        let client = state.fetch_client();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+        assert_eq!(state.bandwidth_stats(), BandwidthStats::default());
+
+        // dispatches a headers request to the peer and answers it with the response
+        async fn request_headers(
+            state: &mut NetworkState<NoopProvider>,
+            client: &FetchClient,
+            session_rx: &mut mpsc::Receiver<PeerRequest>,
+            response: RequestResult<Vec<Header>>,
+        ) -> GetBlockHeaders {
+            let request = HeadersRequest {
+                start: 0u64.into(),
+                limit: 2,
+                direction: HeadersDirection::Rising,
+            };
+            let _request = client.get_headers(request);
+            poll_actions(state).await;
+            let Ok(PeerRequest::GetBlockHeaders { request, response: tx }) = session_rx.try_recv()
+            else {
+                unreachable!()
+            };
+            tx.send(response.map(BlockHeaders)).unwrap();
+            poll_actions(state).await;
+            request
+        }
+
+        let headers = vec![Header::default(), Header { number: 1, ..Default::default() }];
+        let expected = headers.length() as u64;
+        let request = request_headers(&mut state, &client, &mut session_rx, Ok(headers)).await;
+        let stats = state.peer_bandwidth_stats(&peer_id).unwrap();
+        assert_eq!(stats.bytes_sent, request.length() as u64);
+        assert_eq!(stats.bytes_received, expected);
+
+        // failed responses don't count
+        request_headers(&mut state, &client, &mut session_rx, Err(RequestError::Timeout)).await;
+        let stats = state.bandwidth_stats();
+        assert_eq!(stats.bytes_sent, 2 * request.length() as u64);
+        assert_eq!(stats.bytes_received, expected);
+    }
+
+    #[tokio::test]
+    async fn test_max_queued_messages() {
+        let mut state = state();
+        state.set_max_queued_messages(10);
+
+        for _ in 0..100 {
+            state.on_discovery_event(DiscoveryEvent::Discovered {
+                peer_id: PeerId::random(),
+                socket_addr: "127.0.0.1:30303".parse().unwrap(),
+                fork_id: None,
+            });
+        }
+
+        // disconnects are buffered regardless of the cap
+        let peer_id = PeerId::random();
+        state.on_peer_action(PeerAction::Disconnect { peer_id, reason: None });
+
+        let actions = poll_actions(&mut state).await;
+        assert_eq!(actions.len(), 11);
+        assert!(actions[..10]
+            .iter()
+            .all(|action| matches!(action, StateAction::DiscoveredNode { .. })));
+        assert!(matches!(
+            actions[10],
+            StateAction::Disconnect { peer_id: id, .. } if id == peer_id
+        ));
+    }
+
+    /// Lets each session answer one bodies request in the given order, so that the response
+    /// latencies of the peers increase in that order.
+    async fn record_latencies(
+        state: &mut NetworkState<NoopProvider>,
+        sessions: &mut [mpsc::Receiver<PeerRequest>],
+    ) {
+        let client = state.fetch_client();
+        let _requests = sessions
+            .iter()
+            .map(|_| client.get_block_bodies(vec![H256::random()]))
+            .collect::<Vec<_>>();
+        poll_actions(state).await;
+        for session_rx in sessions {
+            let Ok(PeerRequest::GetBlockBodies { response, .. }) = session_rx.try_recv() else {
+                unreachable!()
+            };
+            // keeps the latencies apart
+            std::thread::sleep(Duration::from_millis(1));
+            response.send(Ok(BlockBodies(vec![BlockBody::default()]))).unwrap();
+            poll_actions(state).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefer_low_latency_propagation() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(2));
+        state.set_prefer_low_latency_propagation(true);
+
+        // the first peers respond in order, the others never responded
+        let peer_ids = (0..6).map(|_| PeerId::random()).collect::<Vec<_>>();
+        let mut sessions = peer_ids[..4]
+            .iter()
+            .map(|peer_id| activate_peer(&mut state, *peer_id))
+            .collect::<Vec<_>>();
+        record_latencies(&mut state, &mut sessions).await;
+        sessions.extend(peer_ids[4..].iter().map(|peer_id| activate_peer(&mut state, *peer_id)));
+
+        state.announce_new_block(new_block_message(1));
+        let selected = state
+            .drain_queued()
+            .into_iter()
+            .map(|action| {
+                let StateAction::NewBlock { peer_id, .. } = action else { unreachable!() };
+                peer_id
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(selected, HashSet::from([peer_ids[0], peer_ids[1]]));
+
+        // peers without a latency rank like the median, ahead of the slowest peer
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(5));
+        state.announce_new_block(new_block_message(2));
+        let selected = state
+            .drain_queued()
+            .into_iter()
+            .map(|action| {
+                let StateAction::NewBlock { peer_id, .. } = action else { unreachable!() };
+                peer_id
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(selected.len(), 5);
+        assert!(!selected.contains(&peer_ids[3]));
+    }
+
+    #[tokio::test]
+    async fn test_propagation_proximity_window() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(3));
+        state.set_prefer_low_latency_propagation(true);
+        state.set_propagation_proximity_window(Some(2));
+
+        let heights = [Some(100), Some(5), Some(98), None, Some(50), Some(101)];
+        let peer_ids = heights.iter().map(|_| PeerId::random()).collect::<Vec<_>>();
+        // the proximity takes precedence over the latency, which increases with the height
+        let mut sessions = [3, 1, 4, 2, 0, 5]
+            .into_iter()
+            .map(|idx| activate_peer(&mut state, peer_ids[idx]))
+            .collect::<Vec<_>>();
+        record_latencies(&mut state, &mut sessions).await;
+        for (peer_id, height) in peer_ids.iter().zip(heights) {
+            if let Some(height) = height {
+                state.update_peer_block(peer_id, H256::random(), height);
+            }
+        }
+
+        state.announce_new_block(new_block_message(100));
+        let selected = state
+            .drain_queued()
+            .into_iter()
+            .map(|action| {
+                let StateAction::NewBlock { peer_id, .. } = action else { unreachable!() };
+                peer_id
+            })
+            .collect::<HashSet<_>>();
+        assert_eq!(selected, HashSet::from([peer_ids[0], peer_ids[2], peer_ids[5]]));
+    }
+
+    #[tokio::test]
+    async fn test_close_all_sessions() {
+        let mut state = state();
+        let peer_ids = (0..3).map(|_| PeerId::random()).collect::<Vec<_>>();
+        let _sessions =
+            peer_ids.iter().map(|peer_id| activate_peer(&mut state, *peer_id)).collect::<Vec<_>>();
+        assert_eq!(state.num_active_peers(), 3);
+        assert!(state.best_peer().is_some());
+
+        state.close_all_sessions();
+        assert_eq!(state.num_active_peers(), 0);
+        assert_eq!(state.best_peer(), None);
+
+        // closing again is a no-op
+        state.close_all_sessions();
+        assert_eq!(state.num_active_peers(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_blacklist_ignores_discovered_nodes() {
+        let mut state = state();
+        let blacklisted = PeerId::random();
+        state.set_blacklist(format!("{blacklisted:x}\n10.0.0.0/8").parse().unwrap());
+
+        // blacklisted by peer id and by ip range
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id: blacklisted,
+            socket_addr: "127.0.0.1:30303".parse().unwrap(),
+            fork_id: None,
+        });
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id: PeerId::random(),
+            socket_addr: "10.1.2.3:30303".parse().unwrap(),
+            fork_id: None,
+        });
+
+        let peer_id = PeerId::random();
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id,
+            socket_addr: "127.0.0.1:30303".parse().unwrap(),
+            fork_id: None,
+        });
+        assert!(matches!(
+            poll_actions(&mut state).await[..],
+            [StateAction::DiscoveredNode { peer_id: id, .. }] if id == peer_id
+        ));
+
+        // banned peers are added to the blacklist
+        let ip = "127.0.0.2".parse().unwrap();
+        state.ban_discovery(peer_id, ip);
+        assert!(state.blacklist().contains_peer(&peer_id));
+        assert!(state.blacklist().contains_ip(&ip));
+    }
+
+    #[tokio::test]
+    async fn test_request_block_headers() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        let request = GetBlockHeaders {
+            start_block: 1u64.into(),
+            limit: 1,
+            skip: 0,
+            direction: Default::default(),
+        };
+        let rx = state.request_block_headers(peer_id, request);
+
+        // the session channel is full, so the request is neither sent nor counted
+        assert_eq!(
+            state.request_block_headers(peer_id, request).await.unwrap(),
+            Err(RequestError::ChannelClosed)
+        );
+        assert_eq!(
+            state.peer_bandwidth_stats(&peer_id).unwrap().bytes_sent,
+            request.length() as u64
+        );
+
+        let Some(PeerRequest::GetBlockHeaders { request: received, response }) =
+            session_rx.recv().await
+        else {
+            unreachable!()
+        };
+        assert_eq!(received, request);
+        let header = Header { number: 1, ..Default::default() };
+        response.send(Ok(BlockHeaders(vec![header.clone()]))).unwrap();
+        assert_eq!(rx.await.unwrap().unwrap(), BlockHeaders(vec![header]));
+
+        // requests to unknown peers fail right away
+        let rx = state.request_block_headers(PeerId::random(), request);
+        assert_eq!(rx.await.unwrap(), Err(RequestError::ConnectionDropped));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_pending_responses() {
+        let mut state = state();
+        state.set_max_concurrent_requests_per_peer(2);
+        let client = state.fetch_client();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        // the fetcher sends both requests to the peer at once
+        let requests = [
+            client.get_block_bodies(vec![H256::random()]),
+            client.get_block_bodies(vec![H256::random()]),
+        ];
+        let mut responses = Vec::new();
+        poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            while let Ok(PeerRequest::GetBlockBodies { response, .. }) = session_rx.try_recv() {
+                responses.push(response);
+            }
+            if responses.len() < 2 {
+                cx.waker().wake_by_ref();
+                return Poll::Pending
+            }
+            Poll::Ready(())
+        })
+        .await;
+
+        // the session responds to both requests, in reverse order
+        let body = |gas_limit| BlockBody {
+            ommers: vec![Header { gas_limit, ..Default::default() }],
+            ..Default::default()
+        };
+        for (gas_limit, response) in responses.into_iter().enumerate().rev() {
+            response.send(Ok(BlockBodies(vec![body(gas_limit as u64)]))).unwrap();
+        }
+        poll_actions(&mut state).await;
+
+        // each response is delivered to the receiver of its request
+        for (gas_limit, request) in requests.into_iter().enumerate() {
+            let bodies = request.await.unwrap().into_data();
+            assert_eq!(bodies, vec![body(gas_limit as u64)]);
+        }
+        assert!(matches!(state.peer_latencies()[..], [(id, Some(_))] if id == peer_id));
+
+        // the peer is available for new requests again
+        let _request = client.get_block_bodies(vec![H256::random()]);
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_ignore_rediscovered_active_peer() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let _session_rx = activate_peer(&mut state, peer_id);
+        let socket_addr = "127.0.0.1:30303".parse().unwrap();
+        let discovered = |actions: Vec<StateAction>| {
+            actions
+                .into_iter()
+                .filter(|action| matches!(action, StateAction::DiscoveredNode { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id,
+            socket_addr,
+            fork_id: None,
+        });
+        assert!(discovered(poll_actions(&mut state).await).is_empty());
+
+        // once the session is closed the peer is discovered again
+        state.on_session_closed(peer_id);
+        state.on_discovery_event(DiscoveryEvent::Discovered {
+            peer_id,
+            socket_addr,
+            fork_id: None,
+        });
+        assert!(matches!(
+            discovered(poll_actions(&mut state).await)[..],
+            [StateAction::DiscoveredNode { peer_id: id, .. }] if id == peer_id
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_network_health() {
+        let mut state = state();
+        let client = state.fetch_client();
+        state.set_health_thresholds(HealthThresholds {
+            min_peers: 3,
+            max_fetch_stall: Duration::from_millis(10),
+        });
+        assert_eq!(state.is_healthy(), NetworkHealth::Isolated);
+
+        let mut sessions = vec![activate_peer(&mut state, PeerId::random())];
+        assert_eq!(state.is_healthy(), NetworkHealth::Degraded { active_peers: 1 });
+
+        for _ in 0..4 {
+            sessions.push(activate_peer(&mut state, PeerId::random()));
+        }
+        assert_eq!(state.is_healthy(), NetworkHealth::Healthy);
+
+        // a request that is never answered stalls the fetcher
+        let _request =
+            tokio::task::spawn(async move { client.get_block_bodies(vec![H256::random()]).await });
+        let mut dispatched = Vec::new();
+        poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            dispatched
+                .extend(sessions.iter_mut().filter_map(|session_rx| session_rx.try_recv().ok()));
+            if dispatched.is_empty() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(state.is_healthy(), NetworkHealth::Degraded { active_peers: 5 });
+    }
+
+    #[tokio::test]
+    async fn test_reject_mismatched_genesis() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let genesis = H256::random();
+
+        let (tx, _session_rx) = mpsc::channel(1);
+        let err = state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status { genesis, ..Default::default() },
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(1)),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SessionActivationError::MismatchedGenesis {
+                peer_id,
+                expected: MAINNET.genesis_hash(),
+                got: genesis
+            }
+        );
+        assert_eq!(state.num_active_peers(), 0);
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::ProtocolBreach))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_fork_id() {
+        let activate = |state: &mut NetworkState<NoopProvider>, fork_id| {
+            let peer_id = PeerId::random();
+            let (tx, _session_rx) = mpsc::channel(1);
+            let res = state.on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status { forkid: fork_id, ..Default::default() },
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(1)),
+            );
+            (peer_id, res)
+        };
+        let compatible = MAINNET.fork_id(&Head::default());
+        let incompatible = ForkId { hash: ForkHash([0xde, 0xad, 0xbe, 0xef]), next: 0 };
+
+        // our fork isn't known yet
+        let mut state = state();
+        assert!(activate(&mut state, incompatible).1.is_ok());
+
+        state.set_fork_filter(MAINNET.fork_filter(Head::default()));
+        assert!(activate(&mut state, compatible).1.is_ok());
+        assert!(poll_disconnects(&mut state).await.is_empty());
+
+        let (peer_id, res) = activate(&mut state, incompatible);
+        assert_eq!(
+            res.unwrap_err(),
+            SessionActivationError::IncompatibleFork { peer_id, fork_id: incompatible }
+        );
+        assert_eq!(state.num_active_peers(), 2);
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::UselessPeer))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_peer_connected() {
+        let mut state = state();
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+        let _first = activate_peer(&mut state, peers[0]);
+        let _second = activate_peer(&mut state, peers[1]);
+        assert!(matches!(
+            poll_actions(&mut state).await[..],
+            [StateAction::FirstPeerConnected(id)] if id == peers[0]
+        ));
+
+        // dropping back to zero peers re-arms the signal
+        state.on_session_closed(peers[0]);
+        state.on_session_closed(peers[1]);
+        let _third = activate_peer(&mut state, peers[2]);
+        assert!(matches!(
+            poll_actions(&mut state).await[..],
+            [StateAction::FirstPeerConnected(id)] if id == peers[2]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_after_max_bad_responses() {
+        let mut state = state();
+        state.set_max_bad_responses(3);
+        let peer_id = PeerId::random();
+        let _session = activate_peer(&mut state, peer_id);
+
+        let bad_response = || PeerResponseResult::BlockHeaders(Err(RequestError::Timeout));
+        for _ in 0..2 {
+            state.on_eth_response(peer_id, 0, bad_response());
+        }
+        assert!(poll_disconnects(&mut state).await.is_empty());
+
+        state.on_eth_response(peer_id, 0, bad_response());
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::UselessPeer))]
+        );
+
+        // the disconnect is only queued once
+        state.on_eth_response(peer_id, 0, bad_response());
+        assert!(poll_disconnects(&mut state).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_state_stream() {
+        let mut state = state();
+        let peers = [PeerId::random(), PeerId::random()];
+        for peer_id in peers {
+            state.on_discovery_event(DiscoveryEvent::Discovered {
+                peer_id,
+                socket_addr: "127.0.0.1:30303".parse().unwrap(),
+                fork_id: None,
+            });
+        }
+
+        let actions = (&mut state).take(2).collect::<Vec<_>>().await;
+        let discovered = actions
+            .into_iter()
+            .map(|action| match action {
+                StateAction::DiscoveredNode { peer_id, .. } => peer_id,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(discovered, peers);
+    }
+
+    #[tokio::test]
+    async fn test_subnet_limits() {
+        let mut state = state();
+        state.set_subnet_limits(SubnetLimits {
+            max_peers_per_ipv4_subnet: 2,
+            max_peers_per_ipv6_subnet: 1,
+        });
+
+        let addrs = [
+            "10.0.0.1:30303",
+            "10.0.0.2:30303",
+            "10.0.0.3:30303",
+            "10.0.1.1:30303",
+            "[2001:db8::1]:30303",
+            "[2001:db8::2]:30303",
+        ];
+        for addr in addrs {
+            state.add_peer_kind(PeerId::random(), PeerKind::Basic, addr.parse().unwrap());
+        }
+
+        let mut connects = HashMap::<_, usize>::new();
+        for action in poll_actions(&mut state).await {
+            if let StateAction::Connect { remote_addr, .. } = action {
+                let subnet = match remote_addr.ip() {
+                    IpAddr::V4(ip) => ip.octets()[..3].to_vec(),
+                    IpAddr::V6(ip) => ip.octets()[..8].to_vec(),
+                };
+                *connects.entry(subnet).or_default() += 1;
+            }
+        }
+        assert_eq!(connects[&vec![10, 0, 0]], 2);
+        assert_eq!(connects[&vec![10, 0, 1]], 1);
+        assert_eq!(connects[&vec![0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0]], 1);
+
+        // the skipped connections don't occupy outbound slots
+        assert_eq!(state.peers().num_outbound_connections(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_peer_reputations_round_trip() {
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let add_peers = |state: &mut NetworkState<NoopProvider>| {
+            for peer_id in peers {
+                state.add_peer_kind(peer_id, PeerKind::Basic, "127.0.0.1:30303".parse().unwrap());
+            }
+        };
+
+        let (mut state, mut restarted) = (state(), state());
+        add_peers(&mut state);
+        state.peers_mut().apply_reputation_change(&peers[0], ReputationChangeKind::BadMessage);
+        state.peers_mut().apply_reputation_change(&peers[1], ReputationChangeKind::Timeout);
+        let exported = state.export_peer_reputations();
+        assert_eq!(exported.len(), peers.len());
+
+        // restart with the same peers, plus an entry of a peer that isn't known anymore
+        add_peers(&mut restarted);
+        let mut entries = exported.clone();
+        entries.push((PeerId::random(), 100));
+        assert_eq!(restarted.import_peer_reputations(entries), peers.len());
+
+        assert_eq!(restarted.export_peer_reputations(), exported);
+        for peer_id in peers {
+            assert_eq!(
+                restarted.peers().get_reputation(&peer_id),
+                state.peers().get_reputation(&peer_id)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ban_discovery_after_max_protocol_violations() {
+        let mut state = state();
+        state.set_max_protocol_violations(2);
+        let peer_id = PeerId::random();
+        let _session = activate_peer(&mut state, peer_id);
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        state.on_protocol_violation(peer_id);
+        assert!(!state.blacklist().is_blacklisted(&peer_id, &ip));
+
+        state.on_protocol_violation(peer_id);
+        assert!(state.blacklist().contains_peer(&peer_id));
+        assert!(state.blacklist().contains_ip(&ip));
+    }
+
+    #[tokio::test]
+    async fn test_banned_peers() {
+        let mut state = state();
+        assert!(state.banned_peers().is_empty());
+        assert!(state.banned_ips().is_empty());
+
+        // banned in discovery
+        let discovery_banned = PeerId::random();
+        let ip = IpAddr::from([1, 2, 3, 4]);
+        let socket_addr = SocketAddr::new(ip, 30303);
+        state.ban_discovery(discovery_banned, ip);
+
+        // banned by the peers manager
+        let manager_banned = PeerId::random();
+        state.peers_mut().add_peer(manager_banned, socket_addr, None);
+        state
+            .peers_mut()
+            .apply_reputation_change(&manager_banned, ReputationChangeKind::BadProtocol);
+
+        let mut expected = vec![discovery_banned, manager_banned];
+        expected.sort_unstable();
+        assert_eq!(state.banned_peers(), expected);
+        assert_eq!(state.banned_ips(), vec![ip]);
+
+        let discovered =
+            || DiscoveryEvent::Discovered { peer_id: discovery_banned, socket_addr, fork_id: None };
+        state.on_discovery_event(discovered());
+        assert!(poll_actions(&mut state).await.is_empty());
+
+        // unbanning lifts the bans of both the discovery service and the peers manager
+        state.unban_peer(discovery_banned);
+        state.unban_peer(manager_banned);
+        state.unban_ip(ip);
+        assert!(state.banned_peers().is_empty());
+        assert!(state.banned_ips().is_empty());
+
+        // the peer is discovered again
+        state.on_discovery_event(discovered());
+        assert!(poll_actions(&mut state).await.iter().any(|action| matches!(
+            action,
+            StateAction::DiscoveredNode { peer_id, .. } if *peer_id == discovery_banned
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_ban_peer_action() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let _session = activate_peer(&mut state, peer_id);
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        state.on_peer_action(PeerAction::BanPeer { peer_id });
+        assert!(state.blacklist().contains_peer(&peer_id));
+        assert!(state.blacklist().contains_ip(&ip));
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(peer_id, Some(DisconnectReason::DisconnectRequested))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connected_duration() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        assert_eq!(state.connected_duration(&peer_id), None);
+
+        let before = Instant::now();
+        let _session = activate_peer(&mut state, peer_id);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let duration = state.connected_duration(&peer_id).unwrap();
+        assert!(duration >= Duration::from_millis(10) && duration <= before.elapsed());
+
+        state.on_session_closed(peer_id);
+        assert_eq!(state.connected_duration(&peer_id), None);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_chain_head() {
+        let mut state = state();
+        assert_eq!(state.estimate_chain_head(), None);
+
+        let hashes = [H256::random(), H256::random(), H256::random()];
+        let mut sessions = Vec::new();
+        for (hash, number) in hashes.into_iter().zip([20, 35, 10]) {
+            let peer_id = PeerId::random();
+            sessions.push(activate_peer(&mut state, peer_id));
+            state.update_peer_block(&peer_id, hash, number);
+        }
+
+        assert_eq!(state.estimate_chain_head(), Some((hashes[1], 35)));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_peer_block_number() {
+        let mut state = state();
+
+        // the noop provider doesn't know the peer's best block
+        let peer_id = PeerId::random();
+        let _session = activate_peer(&mut state, peer_id);
+        let best_hash = Status::default().blockhash;
+        let info = state.active_peer_infos().next().unwrap();
+        assert_eq!((info.best_hash, info.best_number), (best_hash, None));
+        assert_eq!(state.estimate_chain_head(), None);
+
+        // a peer that's actually at genesis ranks above the unknown one
+        let genesis_peer = PeerId::random();
+        let genesis_hash = H256::random();
+        let _genesis_session = activate_peer(&mut state, genesis_peer);
+        state.update_peer_block(&genesis_peer, genesis_hash, 0);
+        assert_eq!(state.estimate_chain_head(), Some((genesis_hash, 0)));
+        assert_eq!(state.best_peer(), Some((genesis_peer, genesis_hash)));
+    }
+
+    #[tokio::test]
+    async fn test_poll_discovery_events() {
+        let discovered = PeerId::random();
+        let enr_peer = PeerId::random();
+        let fork_id = MAINNET.fork_id(&Head::default());
+        let discovery = Discovery::from_events(vec![
+            DiscoveryEvent::Discovered {
+                peer_id: discovered,
+                socket_addr: "127.0.0.1:30303".parse().unwrap(),
+                fork_id: None,
+            },
+            DiscoveryEvent::EnrForkId(enr_peer, fork_id),
+        ]);
+        let mut state = NetworkState::new(
+            NoopProvider::default(),
+            discovery,
+            PeersManager::default(),
+            MAINNET.genesis_hash(),
+            Default::default(),
+        );
+
+        let actions = poll_actions(&mut state).await;
+        assert!(matches!(
+            actions[..],
+            [
+                StateAction::DiscoveredNode { peer_id, fork_id: None, .. },
+                StateAction::DiscoveredEnrForkId { peer_id: enr_id, fork_id: id },
+            ] if peer_id == discovered && enr_id == enr_peer && id == fork_id
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_block_request_rate_limit() {
+        let mut state = state();
+        state.set_block_request_rate_limit(BlockRequestRateLimit {
+            requests_per_second: 20,
+            burst: 1,
+        });
+        let peer_id = PeerId::random();
+        let (tx, mut session_rx) = mpsc::channel(3);
+        state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status::default(),
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(Duration::from_secs(10).as_millis() as u64)),
+            )
+            .unwrap();
+
+        let start = Instant::now();
+        for request_id in 0..3 {
+            let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random()]));
+            state.handle_block_request(peer_id, request_id, request);
+        }
+        // only the first request is sent right away
+        let mut requests = vec![session_rx.try_recv().unwrap()];
+        assert!(session_rx.try_recv().is_err());
+
+        let mut arrivals = vec![start.elapsed()];
+        poll_fn(|cx| {
+            while state.poll(cx).is_ready() {}
+            while let Ok(request) = session_rx.try_recv() {
+                requests.push(request);
+                arrivals.push(start.elapsed());
+            }
+            if arrivals.len() == 3 {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // the requests are spaced out instead of replacing each other
+        assert!(arrivals[1] >= Duration::from_millis(40));
+        assert!(arrivals[2] >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_plan_announce_new_block() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(3));
+        let peer_ids = (0..10).map(|_| PeerId::random()).collect::<Vec<_>>();
+        let _sessions =
+            peer_ids.iter().map(|peer_id| activate_peer(&mut state, *peer_id)).collect::<Vec<_>>();
+
+        // a peer that already has the block is never planned
+        let msg = new_block_message(1);
+        state.on_new_block(peer_ids[0], msg.hash, 1);
+        poll_actions(&mut state).await;
+
+        let planned = state.plan_announce_new_block(&msg);
+        assert_eq!(planned.len(), 3);
+        assert!(!planned.contains(&peer_ids[0]));
+        // planning doesn't change the state
+        assert!(poll_actions(&mut state).await.is_empty());
+        assert_eq!(state.plan_announce_new_block(&msg), planned);
+
+        state.announce_new_block(msg);
+        let messaged = poll_actions(&mut state)
+            .await
+            .into_iter()
+            .filter_map(|action| match action {
+                StateAction::NewBlock { peer_id, .. } => Some(peer_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(messaged, planned);
+
+        // blocks above the maximum size are neither planned nor sent to any peer
+        state.set_max_new_block_size(1);
+        let msg = new_block_message(2);
+        assert!(state.plan_announce_new_block(&msg).is_empty());
+        state.announce_new_block(msg);
+        assert!(!poll_actions(&mut state)
+            .await
+            .iter()
+            .any(|action| matches!(action, StateAction::NewBlock { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_version_histogram() {
+        let mut state = state();
+        let mut sessions = Vec::new();
+        for version in [EthVersion::Eth66, EthVersion::Eth67, EthVersion::Eth67] {
+            // eth/67 peers also support eth/66
+            let capabilities = if version == EthVersion::Eth67 {
+                vec![Capability::from(EthVersion::Eth66), Capability::from(EthVersion::Eth67)]
+            } else {
+                vec![Capability::from(version)]
+            };
+            sessions.push(activate_peer_with_capabilities(
+                &mut state,
+                PeerId::random(),
+                Arc::new(capabilities.into()),
+            ));
+        }
+
+        let histogram = state.version_histogram();
+        assert_eq!(histogram.len(), 2);
+        assert_eq!(histogram[&EthVersion::Eth66], 1);
+        assert_eq!(histogram[&EthVersion::Eth67], 2);
+    }
+
+    #[tokio::test]
+    async fn test_evict_idle_peers() {
+        let mut state = state();
+        state.set_idle_peer_timeout(Duration::from_millis(10));
+
+        let basic = PeerId::random();
+        let trusted = PeerId::random();
+        state.add_peer_kind(trusted, PeerKind::Trusted, "127.0.0.1:30303".parse().unwrap());
+        let _sessions = [activate_peer(&mut state, basic), activate_peer(&mut state, trusted)];
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            poll_disconnects(&mut state).await,
+            vec![(basic, Some(DisconnectReason::UselessPeer))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transactions() {
+        let mut state = state();
+        let peers = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let _sessions = peers.map(|peer_id| activate_peer(&mut state, peer_id));
+        let hashes = vec![H256::random(), H256::random()];
+
+        // the first peer knows one of the transactions, the second one knows both
+        state.on_new_pooled_tx_hashes(peers[0], vec![hashes[0]]);
+        state.on_new_pooled_tx_hashes(peers[1], hashes.clone());
+
+        state.broadcast_transactions(hashes.clone());
+        let mut announced = poll_actions(&mut state)
+            .await
+            .into_iter()
+            .filter_map(|action| match action {
+                StateAction::PooledTransactions {
+                    peer_id,
+                    hashes: NewPooledTransactionHashes::Eth66(hashes),
+                } => Some((peer_id, hashes.0)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        assert_eq!(announced.len(), 2);
+        assert_eq!(announced.remove(&peers[0]), Some(vec![hashes[1]]));
+        assert_eq!(announced.remove(&peers[2]), Some(hashes.clone()));
+
+        // all peers know the transactions now
+        state.broadcast_transactions(hashes);
+        assert!(!poll_actions(&mut state)
+            .await
+            .iter()
+            .any(|action| matches!(action, StateAction::PooledTransactions { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_unsent_requests() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        // the second request doesn't fit into the session channel
+        let requests = [H256::random(), H256::random()];
+        for (request_id, hash) in requests.into_iter().enumerate() {
+            let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![hash]));
+            state.handle_block_request(peer_id, request_id as u64, request);
+        }
+
+        // the request is sent on the next poll once the channel has capacity again, the response
+        // senders are kept because dropping them closes the session
+        let Ok(PeerRequest::GetBlockBodies { request, response: _first }) = session_rx.try_recv()
+        else {
+            unreachable!()
+        };
+        assert_eq!(request.0, vec![requests[0]]);
+        assert!(session_rx.try_recv().is_err());
+        poll_actions(&mut state).await;
+        let Ok(PeerRequest::GetBlockBodies { request, response: _second }) = session_rx.try_recv()
+        else {
+            unreachable!()
+        };
+        assert_eq!(request.0, vec![requests[1]]);
+
+        // a slow session isn't closed while its channel stays full
+        for request_id in 2..4 {
+            let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random()]));
+            state.handle_block_request(peer_id, request_id, request);
+        }
+        for _ in 0..10 {
+            assert!(poll_disconnects(&mut state).await.is_empty());
+        }
+        assert_eq!(state.num_active_peers(), 1);
+
+        // a session that never makes room is closed once the responses exceed their deadline
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(poll_disconnects(&mut state).await.contains(&(peer_id, None)));
+        assert_eq!(state.num_active_peers(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_unsent_request() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        // the second request doesn't fit into the session channel
+        for request_id in 0..2 {
+            let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random()]));
+            state.handle_block_request(peer_id, request_id, request);
+        }
+        state.on_request_cancelled(peer_id, 1);
+        assert_eq!(state.pending_request_count(), 1);
+
+        // the cancelled request isn't sent once the channel has room again
+        let Ok(PeerRequest::GetBlockBodies { response: _first, .. }) = session_rx.try_recv() else {
+            unreachable!()
+        };
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_session_with_too_many_unsent_requests() {
+        let mut state = state();
+        let peer_id = PeerId::random();
+        let _session_rx = activate_peer(&mut state, peer_id);
+
+        // the first request fills the session channel, the rest are buffered
+        for request_id in 0..MAX_UNSENT_REQUESTS as u64 + 2 {
+            let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random()]));
+            state.handle_block_request(peer_id, request_id, request);
+        }
+
+        assert!(poll_disconnects(&mut state)
+            .await
+            .contains(&(peer_id, Some(DisconnectReason::UselessPeer))));
+        assert_eq!(state.num_active_peers(), 0);
+    }
+
+    #[test]
+    fn test_ban_ip_after_incompatible_forks() {
+        let mut state = state();
+        state.set_fork_filter(MAINNET.fork_filter(Head::default()));
+        state.set_incompatible_fork_ip_ban_threshold(3);
+        let fork_id = ForkId { hash: ForkHash([0xde, 0xad, 0xbe, 0xef]), next: 0 };
+        let addr: SocketAddr = "10.0.0.1:30303".parse().unwrap();
+
+        for _ in 0..3 {
+            assert!(!state.blacklist().contains_ip(&addr.ip()));
+            let peer_id = PeerId::random();
+            state.add_peer_kind(peer_id, PeerKind::Basic, addr);
+            state.on_discovery_event(DiscoveryEvent::EnrForkId(peer_id, fork_id));
+        }
+        assert!(state.blacklist().contains_ip(&addr.ip()));
+
+        // other ips are not affected
+        let peer_id = PeerId::random();
+        let other: SocketAddr = "10.0.0.2:30303".parse().unwrap();
+        state.add_peer_kind(peer_id, PeerKind::Basic, other);
+        state.on_discovery_event(DiscoveryEvent::EnrForkId(peer_id, fork_id));
+        assert!(!state.blacklist().contains_ip(&other.ip()));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_handle() {
+        let mut state = state();
+        let handle = state.snapshot_handle();
+        let mut peer_ids = [PeerId::random(), PeerId::random()];
+        let _sessions = peer_ids.map(|peer_id| activate_peer(&mut state, peer_id));
+
+        // nothing is published before the state is polled
+        assert_eq!(handle.snapshot(), NetworkSnapshot::default());
+
+        poll_actions(&mut state).await;
+
+        let snapshot = handle.clone().snapshot();
+        assert_eq!(snapshot.num_active_peers, 2);
+        peer_ids.sort_unstable();
+        assert_eq!(snapshot.peers.iter().map(|peer| peer.peer_id).collect::<Vec<_>>(), peer_ids);
+        assert!(snapshot.peers.iter().all(|peer| peer.best_hash == Status::default().blockhash));
+
+        // changes to the peers are published on the next poll
+        let hash = H256::random();
+        state.update_peer_block(&peer_ids[0], hash, 1);
+        assert_eq!(handle.snapshot(), snapshot);
+        poll_actions(&mut state).await;
+        assert_eq!(handle.snapshot().peers[0].best_hash, hash);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_request_receiver() {
+        let mut state = state();
+        let client = state.fetch_client();
+        let peer_id = PeerId::random();
+        let mut session_rx = activate_peer(&mut state, peer_id);
+
+        let request = client.get_block_bodies(vec![H256::random()]);
+        poll_actions(&mut state).await;
+        assert_eq!(state.pending_request_count(), 1);
+        // the peer's end of the request stays alive
+        let _first = session_rx.try_recv().unwrap();
+
+        // the caller goes away before the peer responded
+        drop(request);
+        poll_actions(&mut state).await;
+        assert_eq!(state.pending_request_count(), 0);
+
+        // the peer is idle again and receives the next request
+        let _next = client.get_block_bodies(vec![H256::random()]);
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_pending_request_count() {
+        let mut state = state();
+        let client = state.fetch_client();
+        let mut session_rx = activate_peer(&mut state, PeerId::random());
+        assert_eq!(state.pending_request_count(), 0);
+
+        // the second request waits until the peer answered the first one
+        let _requests = [
+            client.get_block_bodies(vec![H256::random()]),
+            client.get_block_bodies(vec![H256::random()]),
+        ];
+        poll_actions(&mut state).await;
+        assert_eq!(state.pending_request_count(), 2);
+
+        for remaining in [1, 0] {
+            let Ok(PeerRequest::GetBlockBodies { response, .. }) = session_rx.try_recv() else {
+                unreachable!()
+            };
+            response.send(Ok(BlockBodies(Vec::new()))).unwrap();
+            poll_actions(&mut state).await;
+            assert_eq!(state.pending_request_count(), remaining);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_announce_large_block_by_hash() {
+        let mut state = state();
+        state.set_max_new_block_size(16);
+        let peer_ids = [PeerId::random(), PeerId::random()];
+        let _sessions = peer_ids.map(|peer_id| activate_peer(&mut state, peer_id));
+
+        let msg = new_block_message(1);
+        assert!(msg.block.length() > 16);
+        // the block import announces the block once its header is valid and by hash once it is
+        // valid
+        state.announce_new_block(msg.clone());
+        state.announce_new_block_hash(msg.clone());
+
+        let actions = poll_actions(&mut state).await;
+        assert!(!actions.iter().any(|action| matches!(action, StateAction::NewBlock { .. })));
+        let num_hash_announcements = actions
+            .iter()
+            .filter(|action| matches!(action, StateAction::NewBlockHashes { .. }))
+            .count();
+        assert_eq!(num_hash_announcements, 2);
+        let announced = actions
+            .into_iter()
+            .filter_map(|action| match action {
+                StateAction::NewBlockHashes { peer_id, hashes } => Some((peer_id, hashes.0)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        assert_eq!(announced.len(), 2);
+        assert!(announced.values().all(|hashes| hashes.len() == 1 && hashes[0].hash == msg.hash));
+    }
+
+    #[tokio::test]
+    async fn test_set_genesis_hash() {
+        let mut state = state();
+        let genesis = H256::random();
+        state.set_genesis_hash(genesis);
+        assert_eq!(state.genesis_hash(), genesis);
+
+        // sessions are validated against the new genesis hash
+        let activate = |state: &mut NetworkState<NoopProvider>, genesis| {
+            let peer_id = PeerId::random();
+            let (tx, _session_rx) = mpsc::channel(1);
+            state.on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status { genesis, ..Default::default() },
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(1)),
+            )
+        };
+        assert!(matches!(
+            activate(&mut state, MAINNET.genesis_hash()),
+            Err(SessionActivationError::MismatchedGenesis { expected, .. }) if expected == genesis
+        ));
+        assert!(activate(&mut state, genesis).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_stalled() {
+        let mut state = state();
+        state.set_fetch_stall_threshold(2);
+        let client = state.fetch_client();
+        let _session = activate_peer(&mut state, PeerId::random());
+
+        // the only peer is busy with the first request
+        let _requests = [
+            client.get_block_bodies(vec![H256::random()]),
+            client.get_block_bodies(vec![H256::random()]),
+        ];
+        let start = Instant::now();
+        let attempts = poll_fn(|cx| {
+            while let Poll::Ready(action) = state.poll(cx) {
+                if let StateAction::FetchStalled(attempts) = action {
+                    return Poll::Ready(attempts)
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+
+        // the second attempt is made after the first backoff
+        assert_eq!(attempts, 2);
+        assert!(start.elapsed() >= FETCH_BACKOFF_BASE);
+        assert_eq!(state.pending_request_count(), 2);
+    }
+
+    /// Returns the peers that received a `NewBlock` and the peers that received a
+    /// `NewBlockHashes` message after announcing a new block with the given number.
+    async fn announced_peers(
+        state: &mut NetworkState<NoopProvider>,
+        number: u64,
+    ) -> (HashSet<PeerId>, HashSet<PeerId>) {
+        let msg = new_block_message(number);
+        state.announce_new_block(msg.clone());
+        state.announce_new_block_hash(msg);
+
+        let mut blocks = HashSet::new();
+        let mut hashes = HashSet::new();
+        for action in poll_actions(state).await {
+            match action {
+                StateAction::NewBlock { peer_id, .. } => {
+                    blocks.insert(peer_id);
+                }
+                StateAction::NewBlockHashes { peer_id, .. } => {
+                    hashes.insert(peer_id);
+                }
+                _ => {}
+            }
+        }
+        (blocks, hashes)
+    }
+
+    #[tokio::test]
+    async fn test_announce_whitelist() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::Fraction(1.0));
+        let peer_ids = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let _sessions = peer_ids.map(|peer_id| activate_peer(&mut state, peer_id));
+
+        let whitelist = HashSet::from([peer_ids[0]]);
+        state.set_announce_whitelist(Some(whitelist.clone()));
+        let (blocks, hashes) = announced_peers(&mut state, 1).await;
+        assert_eq!(blocks, whitelist);
+        // the whitelisted peer already received the full block
+        assert!(hashes.is_empty());
+
+        // without a whitelist, the peers that don't receive the block get its hash
+        state.set_propagation_fraction(BlockPropagationMode::FixedCount(1));
+        state.set_announce_whitelist(None);
+        let (blocks, hashes) = announced_peers(&mut state, 2).await;
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(hashes.len(), 2);
+        assert!(blocks.is_disjoint(&hashes));
+        assert_eq!(blocks.union(&hashes).copied().collect::<HashSet<_>>(), HashSet::from(peer_ids));
+    }
+
+    #[tokio::test]
+    async fn test_min_propagate() {
+        let mut state = state();
+        state.set_propagation_fraction(BlockPropagationMode::Sqrt);
+        let _sessions = [(); 5].map(|_| activate_peer(&mut state, PeerId::random()));
+        assert_eq!(state.plan_announce_new_block(&new_block_message(1)).len(), 3);
+
+        // the floor is above sqrt(5) + 1
+        state.set_min_propagate(4);
+        let (blocks, hashes) = announced_peers(&mut state, 1).await;
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(hashes.len(), 1);
+
+        // the floor is capped by the number of peers
+        state.set_min_propagate(10);
+        assert_eq!(state.plan_announce_new_block(&new_block_message(2)).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_block_source() {
+        let mut state = state();
+        state.set_block_source_cache_limit(2);
+        let first = PeerId::random();
+        let second = PeerId::random();
+        let _sessions = [activate_peer(&mut state, first), activate_peer(&mut state, second)];
+
+        let hashes = [H256::random(), H256::random(), H256::random()];
+        assert!(state.on_new_block(first, hashes[0], 1));
+        assert!(state.on_new_block(second, hashes[0], 1));
+        assert_eq!(state.block_source(&hashes[0]), Some(first));
+
+        // hash announcements are recorded as well
+        state.on_new_block_hashes(second, vec![BlockHashNumber { hash: hashes[1], number: 2 }]);
+        state.on_new_block_hashes(first, vec![BlockHashNumber { hash: hashes[1], number: 2 }]);
+        assert_eq!(state.block_source(&hashes[1]), Some(second));
+
+        // the oldest entry is evicted beyond the limit
+        assert!(state.on_new_block(first, hashes[2], 3));
+        assert_eq!(state.block_source(&hashes[0]), None);
+        assert_eq!(state.block_source(&hashes[1]), Some(second));
+        assert_eq!(state.block_source(&hashes[2]), Some(first));
+    }
+
+    #[tokio::test]
+    async fn test_state_action_handler() {
+        let mut state = state();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let handler = StateActionHandler::new(NonZeroUsize::new(8).unwrap(), move |action| {
+            let _ = tx.send(action);
+        });
+        state.set_action_listener(handler.spawn(&TokioTaskExecutor::default()));
+
+        let peer_id = PeerId::random();
+        state.peers_mut().add_peer(peer_id, "127.0.0.1:30303".parse().unwrap(), None);
+        poll_actions(&mut state).await;
+
+        // the new peer may trigger other actions as well
+        loop {
+            if let StateAction::PeerAdded(id) = rx.recv().await.unwrap() {
+                assert_eq!(id, peer_id);
+                break
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_outstanding_response_bytes() {
+        let mut state = state();
+        state.set_max_outstanding_response_bytes(3 * APPROX_BODY_SIZE as u64);
+        let peer_id = PeerId::random();
+        let (tx, mut session_rx) = mpsc::channel(3);
+        state
+            .on_session_activated(
+                peer_id,
+                "127.0.0.1:30303".parse().unwrap(),
+                capabilities(),
+                Status::default(),
+                PeerRequestSender::new(peer_id, tx),
+                Arc::new(AtomicU64::new(Duration::from_secs(10).as_millis() as u64)),
+            )
+            .unwrap();
+
+        for request_id in 0..3 {
+            let request =
+                BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random(), H256::random()]));
+            state.handle_block_request(peer_id, request_id, request);
+        }
+        // a second request would make the peer owe four bodies, above the limit of three
+        let Ok(PeerRequest::GetBlockBodies { response, .. }) = session_rx.try_recv() else {
+            unreachable!()
+        };
+        assert!(session_rx.try_recv().is_err());
+
+        // the deferred requests stay deferred until the peer responds
+        poll_actions(&mut state).await;
+        assert!(session_rx.try_recv().is_err());
+
+        response.send(Ok(BlockBodies(vec![]))).unwrap();
+        // one pass receives the response, the next one sends the deferred request
+        for _ in 0..2 {
+            poll_actions(&mut state).await;
+        }
+        let _second = session_rx.try_recv().unwrap();
+        assert!(session_rx.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_peer_removal_reasons() {
+        // returns the peers removed from the peers manager, with the reason of their removal
+        async fn removed_peers(
+            state: &mut NetworkState<NoopProvider>,
+        ) -> Vec<(PeerId, PeerRemovalReason)> {
+            poll_actions(state)
+                .await
+                .into_iter()
+                .filter_map(|action| match action {
+                    StateAction::PeerRemoved { peer_id, reason } => Some((peer_id, reason)),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        let mut state = state();
+        state.set_max_protocol_violations(1);
+        let addr: SocketAddr = "127.0.0.1:30303".parse().unwrap();
+
+        // a discovered peer with an active session isn't removed once the session was closed
+        let mut sessions = Vec::new();
+        let mut connect = |state: &mut NetworkState<NoopProvider>| {
+            let peer_id = PeerId::random();
+            state.peers_mut().on_incoming_pending_session(addr.ip()).unwrap();
+            state.peers_mut().on_incoming_session_established(peer_id, addr);
+            state.peers_mut().add_peer(peer_id, addr, None);
+            sessions.push(activate_peer(state, peer_id));
+            peer_id
+        };
+
+        // the response to a request exceeds its deadline
+        let timed_out = connect(&mut state);
+        let request = BlockRequest::GetBlockBodies(GetBlockBodies(vec![H256::random()]));
+        state.handle_block_request(timed_out, 0, request);
+        tokio::time::advance(Duration::from_secs(60)).await;
+
+        // the session reports too many malformed messages
+        let violating = connect(&mut state);
+        state.on_protocol_violation(violating);
+
+        // the peer is removed via the admin api
+        let removed_manually = connect(&mut state);
+        state.remove_peer(removed_manually, PeerKind::Basic);
+
+        // a fatal session error removes and bans the peer
+        let banned = PeerId::random();
+        state.peers_mut().add_peer(banned, addr, None);
+        state.peers_mut().on_active_session_dropped(
+            &addr,
+            &banned,
+            &EthStreamError::P2PStreamError(P2PStreamError::Disconnected(
+                DisconnectReason::UselessPeer,
+            )),
+        );
+
+        assert_eq!(
+            removed_peers(&mut state).await,
+            vec![
+                (removed_manually, PeerRemovalReason::Manual),
+                (banned, PeerRemovalReason::Banned)
+            ]
+        );
+
+        // the other peers are removed once their sessions were closed
+        for peer_id in [timed_out, violating] {
+            state.peers_mut().on_active_session_gracefully_closed(peer_id);
+        }
+        assert_eq!(
+            removed_peers(&mut state).await,
+            vec![
+                (timed_out, PeerRemovalReason::Timeout),
+                (violating, PeerRemovalReason::ProtocolViolation)
+            ]
+        );
+    }
 }
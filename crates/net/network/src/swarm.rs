@@ -1,9 +1,9 @@
 use crate::{
     listener::{ConnectionListener, ListenerEvent},
     message::{PeerMessage, PeerRequestSender},
-    peers::InboundConnectionError,
+    peers::{InboundConnectionError, PeerRemovalReason},
     session::{Direction, PendingSessionHandshakeError, SessionEvent, SessionId, SessionManager},
-    state::{NetworkState, StateAction},
+    state::{NetworkState, SessionActivationError, StateAction},
 };
 use futures::Stream;
 use reth_eth_wire::{
@@ -14,6 +14,7 @@ use reth_eth_wire::{
 use reth_primitives::PeerId;
 use reth_provider::BlockReader;
 use std::{
+    collections::HashSet,
     io,
     net::SocketAddr,
     pin::Pin,
@@ -71,6 +72,10 @@ pub(crate) struct Swarm<C> {
     state: NetworkState<C>,
     /// Tracks the connection state of the node
     net_connection_state: NetworkConnectionState,
+    /// Peers whose established session was rejected by the state and is being disconnected.
+    ///
+    /// These sessions are neither reported as established nor as closed.
+    rejected_sessions: HashSet<PeerId>,
 }
 
 // === impl Swarm ===
@@ -86,7 +91,13 @@ where
         state: NetworkState<C>,
         net_connection_state: NetworkConnectionState,
     ) -> Self {
-        Self { incoming, sessions, state, net_connection_state }
+        Self {
+            incoming,
+            sessions,
+            state,
+            net_connection_state,
+            rejected_sessions: Default::default(),
+        }
     }
 
     /// Access to the state.
@@ -133,13 +144,27 @@ where
                 direction,
                 timeout,
             } => {
-                self.state.on_session_activated(
+                if let Err(err) = self.state.on_session_activated(
                     peer_id,
+                    remote_addr,
                     capabilities.clone(),
                     status,
                     messages.clone(),
                     timeout,
-                );
+                ) {
+                    debug!(target: "net", ?peer_id, ?remote_addr, %err, "rejected session");
+                    if !matches!(err, SessionActivationError::AlreadyActive(_)) {
+                        // the state queued the disconnect of the session, which is only accounted
+                        // for in the peers manager
+                        self.rejected_sessions.insert(peer_id);
+                        if direction.is_incoming() {
+                            self.state
+                                .peers_mut()
+                                .on_incoming_session_established(peer_id, remote_addr);
+                        }
+                    }
+                    return None
+                }
                 Some(SwarmEvent::SessionEstablished {
                     peer_id,
                     remote_addr,
@@ -157,6 +182,9 @@ where
                 None
             }
             SessionEvent::ValidMessage { peer_id, message } => {
+                if self.rejected_sessions.contains(&peer_id) {
+                    return None
+                }
                 Some(SwarmEvent::ValidMessage { peer_id, message })
             }
             SessionEvent::InvalidMessage { peer_id, capabilities, message } => {
@@ -169,18 +197,34 @@ where
                 Some(SwarmEvent::OutgoingPendingSessionClosed { remote_addr, peer_id, error })
             }
             SessionEvent::Disconnected { peer_id, remote_addr } => {
+                if self.rejected_sessions.remove(&peer_id) {
+                    self.state.peers_mut().on_active_session_gracefully_closed(peer_id);
+                    return None
+                }
                 self.state.on_session_closed(peer_id);
                 Some(SwarmEvent::SessionClosed { peer_id, remote_addr, error: None })
             }
             SessionEvent::SessionClosedOnConnectionError { peer_id, remote_addr, error } => {
+                if self.rejected_sessions.remove(&peer_id) {
+                    self.state.peers_mut().on_active_session_dropped(
+                        &remote_addr,
+                        &peer_id,
+                        &error,
+                    );
+                    return None
+                }
                 self.state.on_session_closed(peer_id);
                 Some(SwarmEvent::SessionClosed { peer_id, remote_addr, error: Some(error) })
             }
             SessionEvent::OutgoingConnectionError { remote_addr, peer_id, error } => {
                 Some(SwarmEvent::OutgoingConnectionError { peer_id, remote_addr, error })
             }
-            SessionEvent::BadMessage { peer_id } => Some(SwarmEvent::BadMessage { peer_id }),
+            SessionEvent::BadMessage { peer_id } => {
+                self.state.on_protocol_violation(peer_id);
+                Some(SwarmEvent::BadMessage { peer_id })
+            }
             SessionEvent::ProtocolBreach { peer_id } => {
+                self.state.on_protocol_violation(peer_id);
                 Some(SwarmEvent::ProtocolBreach { peer_id })
             }
         }
@@ -254,8 +298,14 @@ where
                 let msg = PeerMessage::NewBlockHashes(hashes);
                 self.sessions.send_message(&peer_id, msg);
             }
+            StateAction::PooledTransactions { peer_id, hashes } => {
+                let msg = PeerMessage::PooledTransactions(hashes);
+                self.sessions.send_message(&peer_id, msg);
+            }
             StateAction::PeerAdded(peer_id) => return Some(SwarmEvent::PeerAdded(peer_id)),
-            StateAction::PeerRemoved(peer_id) => return Some(SwarmEvent::PeerRemoved(peer_id)),
+            StateAction::PeerRemoved { peer_id, reason } => {
+                return Some(SwarmEvent::PeerRemoved { peer_id, reason })
+            }
             StateAction::DiscoveredNode { peer_id, socket_addr, fork_id } => {
                 // Don't try to connect to peer if node is shutting down
                 if self.is_shutting_down() {
@@ -266,6 +316,25 @@ where
                     self.state_mut().peers_mut().add_peer(peer_id, socket_addr, fork_id);
                 }
             }
+            StateAction::PeerBlockRegressed { peer_id, old_number, new_number } => {
+                trace!(target: "net", ?peer_id, old_number, new_number, "peer block regressed");
+            }
+            StateAction::PeerCountLow(num_active_peers) => {
+                debug!(target: "net", num_active_peers, "peer count dropped below low watermark");
+            }
+            StateAction::PeerCountHealthy(num_active_peers) => {
+                debug!(target: "net", num_active_peers, "peer count recovered above high watermark");
+            }
+            StateAction::FirstPeerConnected(peer_id) => {
+                debug!(target: "net", ?peer_id, "first peer connected");
+            }
+            StateAction::FetchStalled(attempts) => {
+                debug!(target: "net", attempts, "no peer available for block requests");
+            }
+            StateAction::IncompatibleFork { peer_id, fork_id } => {
+                trace!(target: "net", ?peer_id, ?fork_id, "discovered peer on incompatible fork");
+                self.state_mut().peers_mut().remove_peer(peer_id);
+            }
             StateAction::DiscoveredEnrForkId { peer_id, fork_id } => {
                 if self.sessions.is_valid_fork_id(fork_id) {
                     self.state_mut().peers_mut().set_discovered_fork_id(peer_id, fork_id);
@@ -410,7 +479,11 @@ pub(crate) enum SwarmEvent {
     /// Admin rpc: new peer added
     PeerAdded(PeerId),
     /// Admin rpc: peer removed
-    PeerRemoved(PeerId),
+    PeerRemoved {
+        peer_id: PeerId,
+        /// Why the peer was removed.
+        reason: PeerRemovalReason,
+    },
     /// Closed an incoming pending session during authentication.
     IncomingPendingSessionClosed {
         remote_addr: SocketAddr,
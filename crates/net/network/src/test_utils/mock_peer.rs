@@ -0,0 +1,82 @@
+//! A mock peer session for testing the network state.
+
+use crate::{
+    message::{PeerRequest, PeerRequestSender, PeerResponseResult},
+    state::NetworkState,
+};
+use reth_eth_wire::{
+    capability::Capability, BlockBodies, BlockHeaders, EthVersion, NodeData, PooledTransactions,
+    Receipts, Status,
+};
+use reth_interfaces::p2p::error::RequestError;
+use reth_primitives::PeerId;
+use reth_provider::BlockReader;
+use std::{
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Activates a session for a new random peer and spawns a task that answers the requests sent
+/// to the session with the responses returned by the `responder`.
+///
+/// A response that doesn't match the request is answered with a bad response error. A
+/// [`RequestError::ConnectionDropped`] response drops the request and ends the session instead,
+/// like a session that disconnected.
+///
+/// Returns the id of the peer.
+pub(crate) fn spawn_mock_peer<C, F>(state: &mut NetworkState<C>, mut responder: F) -> PeerId
+where
+    C: BlockReader,
+    F: FnMut(&PeerRequest) -> PeerResponseResult + Send + 'static,
+{
+    let peer_id = PeerId::random();
+    let (tx, mut session_rx) = mpsc::channel(1);
+    state
+        .on_session_activated(
+            peer_id,
+            "127.0.0.1:30303".parse().unwrap(),
+            Arc::new(vec![Capability::from(EthVersion::Eth67)].into()),
+            Status::default(),
+            PeerRequestSender::new(peer_id, tx),
+            Arc::new(AtomicU64::new(Duration::from_secs(10).as_millis() as u64)),
+        )
+        .expect("peer is not active yet");
+
+    tokio::task::spawn(async move {
+        while let Some(request) = session_rx.recv().await {
+            let response = responder(&request);
+            if response.err() == Some(&RequestError::ConnectionDropped) {
+                break
+            }
+            respond(request, response);
+        }
+    });
+
+    peer_id
+}
+
+/// Sends the response through the channel of the request.
+fn respond(request: PeerRequest, response: PeerResponseResult) {
+    match (request, response) {
+        (PeerRequest::GetBlockHeaders { response, .. }, PeerResponseResult::BlockHeaders(res)) => {
+            let _ = response.send(res.map(BlockHeaders));
+        }
+        (PeerRequest::GetBlockBodies { response, .. }, PeerResponseResult::BlockBodies(res)) => {
+            let _ = response.send(res.map(BlockBodies));
+        }
+        (
+            PeerRequest::GetPooledTransactions { response, .. },
+            PeerResponseResult::PooledTransactions(res),
+        ) => {
+            let _ = response.send(res.map(PooledTransactions));
+        }
+        (PeerRequest::GetNodeData { response, .. }, PeerResponseResult::NodeData(res)) => {
+            let _ = response.send(res.map(NodeData));
+        }
+        (PeerRequest::GetReceipts { response, .. }, PeerResponseResult::Receipts(res)) => {
+            let _ = response.send(res.map(Receipts));
+        }
+        (request, _) => request.send_bad_response(),
+    }
+}
@@ -3,10 +3,14 @@
 //! Common helpers for network testing.
 
 mod init;
+#[cfg(test)]
+mod mock_peer;
 mod testnet;
 
 pub use init::{
     enr_to_peer_id, unused_port, unused_tcp_addr, unused_tcp_and_udp_port, unused_tcp_udp,
     unused_udp_addr, unused_udp_port, GETH_TIMEOUT,
 };
+#[cfg(test)]
+pub(crate) use mock_peer::spawn_mock_peer;
 pub use testnet::{NetworkEventStream, PeerConfig, Testnet};
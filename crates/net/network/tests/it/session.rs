@@ -1,13 +1,15 @@
 //! Session tests
 
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
 use reth_eth_wire::{capability::Capability, EthVersion};
 use reth_network::{
-    test_utils::{PeerConfig, Testnet},
-    NetworkEvent,
+    test_utils::{NetworkEventStream, PeerConfig, Testnet},
+    NetworkConfigBuilder, NetworkEvent, NetworkManager,
 };
-use reth_network_api::{NetworkInfo, Peers};
+use reth_network_api::{NetworkInfo, Peers, PeersInfo};
 use reth_provider::test_utils::NoopProvider;
+use secp256k1::SecretKey;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn test_session_established_with_highest_version() {
@@ -82,3 +84,41 @@ async fn test_session_established_with_different_capability() {
 
     handle.terminate().await;
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rejected_session_is_not_reported() {
+    reth_tracing::init_test_tracing();
+
+    let mut net = Testnet::default();
+    let capabilities = vec![Capability::new("eth".into(), EthVersion::Eth66 as usize)];
+    let p1 = PeerConfig::with_capabilities(NoopProvider::default(), capabilities);
+    net.add_peer_with_config(p1).await.unwrap();
+    let remote = net.handles().next().unwrap();
+    let _net = net.spawn();
+    let mut remote_events = NetworkEventStream::new(remote.event_listener());
+
+    // only peers that support eth/67 are accepted
+    let secret_key = SecretKey::new(&mut rand::thread_rng());
+    let config = NetworkConfigBuilder::new(secret_key)
+        .listener_addr(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+        .discovery_addr(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+        .disable_discovery()
+        .min_eth_version(EthVersion::Eth67)
+        .build(NoopProvider::default());
+    let network = NetworkManager::new(config).await.unwrap();
+    let handle = network.handle().clone();
+    let mut events = handle.event_listener();
+    tokio::spawn(network);
+
+    handle.add_peer(*remote.peer_id(), remote.local_addr());
+    assert!(matches!(events.next().await, Some(NetworkEvent::PeerAdded(_))));
+
+    // the remote accepts the session, which is then disconnected
+    assert_eq!(remote_events.next_session_established().await, Some(*handle.peer_id()));
+    let (peer_id, _) = remote_events.next_session_closed().await.unwrap();
+    assert_eq!(peer_id, *handle.peer_id());
+
+    // the rejected session is neither reported as established nor as closed
+    assert!(events.next().now_or_never().is_none());
+    assert_eq!(handle.num_connected_peers(), 0);
+}
@@ -1,6 +1,6 @@
 use jsonrpsee::{core::RpcResult, proc_macros::rpc};
 use reth_primitives::NodeRecord;
-use reth_rpc_types::NodeInfo;
+use reth_rpc_types::{ConnectedPeer, NodeInfo};
 
 /// Admin namespace rpc interface that gives access to several non-standard RPC methods.
 #[cfg_attr(not(feature = "client"), rpc(server, namespace = "admin"))]
@@ -28,6 +28,10 @@ pub trait AdminApi {
     #[method(name = "removeTrustedPeer")]
     fn remove_trusted_peer(&self, record: NodeRecord) -> RpcResult<bool>;
 
+    /// Returns all active sessions with remote peers.
+    #[method(name = "peers")]
+    fn peers(&self) -> RpcResult<Vec<ConnectedPeer>>;
+
     /// Creates an RPC subscription which serves events received from the network.
     #[subscription(
     name = "peerEvents",
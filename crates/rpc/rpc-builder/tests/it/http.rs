@@ -42,6 +42,7 @@ where
     AdminApiClient::add_trusted_peer(client, node).await.unwrap();
     AdminApiClient::remove_trusted_peer(client, node).await.unwrap();
     AdminApiClient::node_info(client).await.unwrap();
+    AdminApiClient::peers(client).await.unwrap();
 }
 
 async fn test_basic_eth_calls<C>(client: &C)
@@ -44,6 +44,51 @@ impl NodeInfo {
     }
 }
 
+/// Represents a single entry of the `admin_peers` response, an active session with a remote peer.
+///
+/// Note: this is a subset of Geth's format, see:
+/// <https://geth.ethereum.org/docs/interacting-with-geth/rpc/ns-admin#admin-peers>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectedPeer {
+    /// ID of the remote peer.
+    pub id: PeerId,
+    /// Info about the connection to the peer.
+    pub network: PeerNetworkInfo,
+    /// Info about the protocols the peer is running.
+    pub protocols: PeerProtocolsInfo,
+}
+
+/// Info about the connection to a peer, see [ConnectedPeer].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerNetworkInfo {
+    /// Remote address of the session.
+    pub remote_address: SocketAddr,
+}
+
+/// Info about the protocols a peer is running, see [ConnectedPeer].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerProtocolsInfo {
+    /// Info about the `eth` sub-protocol, if negotiated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eth: Option<EthPeerInfo>,
+}
+
+/// Info about the `eth` sub-protocol of a peer, see [PeerProtocolsInfo].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthPeerInfo {
+    /// The negotiated `eth` protocol version.
+    pub version: u8,
+    /// Hash of the best block the peer is known to have.
+    pub head: H256,
+    /// Number of the best block the peer is known to have, if known.
+    ///
+    /// Note: this is not part of Geth's format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head_number: Option<u64>,
+}
+
 /// All supported protocols
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Protocols {
@@ -101,4 +146,28 @@ mod tests {
         let de_serialized: NodeInfo = serde_json::from_str(&serialized).unwrap();
         assert_eq!(info, de_serialized)
     }
+
+    #[test]
+    fn test_serialize_connected_peer() {
+        let peer = ConnectedPeer {
+            id: PeerId::from_low_u64_be(1),
+            network: PeerNetworkInfo { remote_address: "10.3.58.6:30303".parse().unwrap() },
+            protocols: PeerProtocolsInfo {
+                eth: Some(EthPeerInfo {
+                    version: 68,
+                    head: H256::from_low_u64_be(2),
+                    head_number: Some(17),
+                }),
+            },
+        };
+        let expected = format!(
+            r#"{{"id":"0x{:0>128}","network":{{"remoteAddress":"10.3.58.6:30303"}},"protocols":{{"eth":{{"version":68,"head":"0x{:0>64}","headNumber":17}}}}}}"#,
+            1, 2
+        );
+
+        let serialized = serde_json::to_string(&peer).unwrap();
+        assert_eq!(serialized, expected);
+        let de_serialized: ConnectedPeer = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(peer, de_serialized);
+    }
 }
@@ -4,7 +4,7 @@ use jsonrpsee::core::RpcResult;
 use reth_network_api::{NetworkInfo, PeerKind, Peers};
 use reth_primitives::NodeRecord;
 use reth_rpc_api::AdminApiServer;
-use reth_rpc_types::NodeInfo;
+use reth_rpc_types::{ConnectedPeer, NodeInfo};
 
 /// `admin` API implementation.
 ///
@@ -50,6 +50,11 @@ where
         Ok(true)
     }
 
+    /// Handler for `admin_peers`
+    fn peers(&self) -> RpcResult<Vec<ConnectedPeer>> {
+        Ok(self.network.connected_peers())
+    }
+
     /// Handler for `admin_peerEvents`
     async fn subscribe_peer_events(
         &self,